@@ -0,0 +1,4 @@
+/* Stand-in for @xterm/xterm 5.5.0 (MIT), which scripts/vendor-xterm.sh puts
+ * here on a machine with internet access. Until then the same pinned
+ * release is loaded from jsDelivr, so the page still needs to be online. */
+document.write('<script src="https://cdn.jsdelivr.net/npm/@xterm/xterm@5.5.0/lib/xterm.js"><\/script>');
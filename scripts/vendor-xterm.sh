@@ -0,0 +1,33 @@
+#!/bin/sh
+# Fetch pinned xterm.js releases from the npm registry into assets/static/,
+# where the server embeds them at build time. Each file starts with a
+# comment naming its package, version and license; the packages' MIT
+# license texts go next to them. Run from any directory on a machine with
+# internet access, commit the result, then rebuild.
+set -eu
+
+XTERM=5.5.0
+FIT=0.10.0
+WEBGL=0.18.0
+
+root=$(cd "$(dirname "$0")/.." && pwd)
+dest="$root/assets/static"
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+
+fetch() { # package version file-in-package output-name
+    name=${1#@xterm/}
+    mkdir -p "$tmp/$name"
+    curl -fsSL "https://registry.npmjs.org/$1/-/$name-$2.tgz" | tar -xz -C "$tmp/$name"
+    {
+        printf '/*! %s@%s/%s | MIT License, see LICENSE.%s */\n' "$1" "$2" "$3" "$name"
+        cat "$tmp/$name/package/$3"
+    } >"$dest/$4"
+    cp "$tmp/$name/package/LICENSE" "$dest/LICENSE.$name"
+    echo "$4 <- $1@$2/$3"
+}
+
+fetch @xterm/xterm "$XTERM" lib/xterm.js xterm.js
+fetch @xterm/xterm "$XTERM" css/xterm.css xterm.css
+fetch @xterm/addon-fit "$FIT" lib/addon-fit.js addon-fit.js
+fetch @xterm/addon-webgl "$WEBGL" lib/addon-webgl.js addon-webgl.js
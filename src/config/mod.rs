@@ -0,0 +1,790 @@
+//! Runtime configuration.
+//!
+//! Settings are resolved in increasing order of precedence: built-in
+//! defaults, the config file (`--config FILE` or `XTERM_BACKEND_CONFIG`), the
+//! environment (`XTERM_BACKEND_<KEY>`), then the command line (`--key value`).
+//! Every source funnels through [`Config::set`], so a key means the same thing
+//! wherever it appears.
+
+mod routes;
+
+pub(crate) use routes::Route;
+use routes::Routes;
+
+use crate::net::addr::{IpAddr, ListenAddr, MAX_LISTEN_ADDR_LEN, SocketAddr};
+use crate::net::tls::Credentials;
+use crate::net::ws::CostLimits;
+use crate::runtime::args::Args;
+use crate::runtime::util;
+use crate::sys;
+
+const ENV_PREFIX: &[u8] = b"XTERM_BACKEND_";
+const MAX_FILE_LEN: usize = 64 * 1024;
+const MAX_PATH_LEN: usize = 255;
+const MAX_ENV: usize = 32;
+const MAX_LISTEN: usize = 8;
+/// Upper bound for `max-viewers`; each session keeps that many slots.
+pub(crate) const MAX_VIEWERS: usize = 16;
+
+const HELP: &[u8] = b"Usage: xterm-backend [OPTIONS]
+
+Options:
+  --config FILE        read `key = value` settings from FILE
+  --bind ADDR          IPv4 or IPv6 address to listen on (default 0.0.0.0)
+  --port PORT          TCP port to listen on (default 8000)
+  --listen ADDR:PORT   listen on ADDR:PORT instead of --bind/--port; repeat
+                       for several addresses, IPv6 in brackets ([::]:8000)
+  --listen unix:PATH   listen on a Unix domain socket (unix:@NAME for the
+                       abstract namespace)
+  --listen tls:ADDR:PORT listen on ADDR:PORT with TLS 1.3 (needs --tls-cert
+                       and --tls-key)
+  --tls-cert FILE      PEM certificate chain for tls: listeners, leaf first
+  --tls-key FILE       PEM private key (Ed25519 or ECDSA P-256, unencrypted)
+  --docroot DIR        serve plain GET requests from files under DIR
+  --index-files LIST   comma-separated files tried for a directory
+                       (default index.html)
+  --ipv6-only BOOL     keep [::] listeners from also accepting IPv4 (default false)
+  --unix-mode MODE     octal permissions of socket files (default 0660)
+  --unix-owner UID[:GID] numeric owner of socket files (default unchanged)
+  --max-workers N      maximum concurrent terminal sessions (default 15)
+  --shell PATH         program spawned for each session (default /bin/sh)
+  --buffer-size BYTES  bridge I/O buffer size (default 65536)
+  --max-message BYTES  largest WebSocket message accepted (default 1048576)
+  --ping-interval SECS seconds between keepalive pings, 0 disables (default 30)
+  --ping-max-missed N  unanswered pings before a session is dropped (default 3)
+  --close-timeout MS   wait for the peer's close frame before hanging up (default 1000)
+  --session-grace SECS keep a session's shell running this long after its
+                       client disconnects, for a reconnect to resume it;
+                       0 hangs up at once (default 0)
+  --scrollback BYTES   recent output replayed to a client resuming a session,
+                       0 keeps none (default 65536)
+  --max-viewers N      viewers and participants a session admits,
+                       0 disables watching (default 0, at most 16)
+  --collaborate BOOL   let participants with a session's join link type
+                       into it too (default false)
+  --record-dir DIR     record every session's output in asciicast v2 format
+                       to DIR/ID.cast (default none)
+  --record-input BOOL  record what is typed into sessions as well (default false)
+  --header-timeout MS  time a client has to send its request headers (default 10000)
+  --keepalive-timeout MS time an idle HTTP connection is kept for its next
+                       request, 0 closes after every response (default 5000)
+  --keepalive-requests N requests served on one HTTP connection (default 100)
+  --env NAME=VALUE     set a variable in every session (repeatable)
+  --env-inherit LIST   comma-separated server variables passed on to sessions
+                       (default PATH,HOME,USER,LOGNAME,TZ)
+  --allowed-origins LIST comma-separated origins (scheme://host[:port]) whose
+                       pages may open sessions; same-origin matches the
+                       Host header, * allows any (default same-origin)
+  --token-file FILE    require a token listed in FILE (one per line) to open a
+                       session; the file is re-read when it changes
+  --htpasswd-file FILE require HTTP Basic credentials from FILE (bcrypt,
+                       SHA-512-crypt or SHA-256-crypt) for the page and
+                       sessions; re-read when it changes
+  --bcrypt-max-cost N  highest bcrypt cost accepted in the htpasswd file
+                       (default 10, 4-31)
+  --sha-crypt-max-rounds N highest SHA-crypt rounds accepted in the htpasswd
+                       file (default 50000)
+  --client-env LIST    comma-separated variables a client may set with
+                       ?env.NAME=VALUE on the upgrade request (default none)
+  --route.NAME.FIELD V WebSocket route NAME (FIELD is path, command, args, cwd
+                       or env); /term runs --shell unless a route claims it
+  --print-config       print the effective configuration and exit
+  -h, --help           show this help and exit
+
+Every option can also be given in the config file as `key = value`, or in
+the environment as XTERM_BACKEND_<KEY> (e.g. XTERM_BACKEND_MAX_WORKERS=4).
+Precedence: command line > environment > config file > defaults.
+Routes are set in the config file or on the command line only, e.g.
+`route.top.path = /term/top` and `route.top.command = /usr/bin/top`.
+";
+
+pub(crate) struct Config {
+    pub(crate) bind: IpAddr,
+    pub(crate) port: u16,
+    listen: [ListenAddr; MAX_LISTEN],
+    listen_len: usize,
+    pub(crate) ipv6_only: bool,
+    pub(crate) unix_mode: u32,
+    /// Owner and group for socket files; `u32::MAX` leaves one unchanged.
+    pub(crate) unix_owner: Option<(u32, u32)>,
+    tls_cert: &'static str,
+    tls_key: &'static str,
+    /// Certificate and key for `tls:` listeners, loaded from `tls_cert` and
+    /// `tls_key` once every source is applied.
+    pub(crate) tls: Option<Credentials>,
+    docroot: &'static str,
+    /// `O_PATH` descriptor for `docroot`, opened once every source is applied.
+    pub(crate) docroot_fd: Option<usize>,
+    pub(crate) index_files: &'static str,
+    pub(crate) max_workers: i32,
+    pub(crate) shell: &'static str,
+    pub(crate) buffer_size: usize,
+    pub(crate) max_message: usize,
+    pub(crate) ping_interval: u32,
+    pub(crate) ping_max_missed: u32,
+    pub(crate) close_timeout: u32,
+    pub(crate) session_grace: u32,
+    pub(crate) scrollback: usize,
+    pub(crate) max_viewers: usize,
+    pub(crate) collaborate: bool,
+    record_dir: &'static str,
+    /// `O_PATH` descriptor for `record_dir`, opened like `docroot_fd`.
+    pub(crate) record_dir_fd: Option<usize>,
+    pub(crate) record_input: bool,
+    pub(crate) header_timeout: u32,
+    pub(crate) keepalive_timeout: u32,
+    pub(crate) keepalive_requests: u32,
+    pub(crate) env: EnvList,
+    pub(crate) env_inherit: &'static str,
+    pub(crate) client_env: &'static str,
+    pub(crate) allowed_origins: &'static str,
+    pub(crate) token_file: &'static str,
+    pub(crate) htpasswd_file: &'static str,
+    /// Each password check runs in the parent, so htpasswd hashes asking
+    /// for more work than this are refused.
+    pub(crate) hash_limits: CostLimits,
+    /// Entries of the server's own environment named by `env_inherit`,
+    /// resolved once at startup.
+    pub(crate) inherited: EnvList,
+    pub(crate) routes: Routes,
+}
+
+/// `NAME=value` entries; setting a name again replaces the earlier entry.
+#[derive(Copy, Clone)]
+pub(crate) struct EnvList {
+    entries: [&'static str; MAX_ENV],
+    len: usize,
+}
+
+impl EnvList {
+    const fn new() -> Self {
+        Self {
+            entries: [""; MAX_ENV],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, entry: &'static str) -> Result<(), &'static str> {
+        let name = match entry.split_once('=') {
+            Some((name, _)) if crate::pty::is_valid_name(name.as_bytes()) => name,
+            _ => return Err("expected NAME=VALUE"),
+        };
+        let existing = self
+            .iter()
+            .position(|e| e.split_once('=').map(|(n, _)| n) == Some(name));
+        match existing {
+            Some(i) => self.entries[i] = entry,
+            None if self.len < MAX_ENV => {
+                self.entries[self.len] = entry;
+                self.len += 1;
+            }
+            None => return Err("too many variables"),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.entries[..self.len].iter().copied()
+    }
+}
+
+/// Whether `name` appears in the comma-separated `list`.
+pub(crate) fn list_contains(list: &str, name: &str) -> bool {
+    list.split(',').any(|n| n == name)
+}
+
+impl Config {
+    fn defaults() -> Self {
+        Self {
+            bind: IpAddr::V4([0, 0, 0, 0]),
+            port: 8000,
+            listen: [ListenAddr::Unix(""); MAX_LISTEN],
+            listen_len: 0,
+            ipv6_only: false,
+            unix_mode: 0o660,
+            unix_owner: None,
+            tls_cert: "",
+            tls_key: "",
+            tls: None,
+            docroot: "",
+            docroot_fd: None,
+            index_files: "index.html",
+            max_workers: 15,
+            shell: "/bin/sh",
+            buffer_size: 64 * 1024,
+            max_message: 1024 * 1024,
+            ping_interval: 30,
+            ping_max_missed: 3,
+            close_timeout: 1000,
+            session_grace: 0,
+            scrollback: 64 * 1024,
+            max_viewers: 0,
+            collaborate: false,
+            record_dir: "",
+            record_dir_fd: None,
+            record_input: false,
+            header_timeout: 10_000,
+            keepalive_timeout: 5000,
+            keepalive_requests: 100,
+            env: EnvList::new(),
+            env_inherit: "PATH,HOME,USER,LOGNAME,TZ",
+            client_env: "",
+            allowed_origins: "same-origin",
+            token_file: "",
+            htpasswd_file: "",
+            hash_limits: CostLimits {
+                bcrypt_cost: 10,
+                sha_crypt_rounds: 50_000,
+            },
+            inherited: EnvList::new(),
+            routes: Routes::new(),
+        }
+    }
+
+    /// Apply a single `key = value` setting.
+    fn set(&mut self, key: &str, value: &'static str) -> Result<(), &'static str> {
+        match key {
+            "bind" => self.bind = IpAddr::parse(value).ok_or("expected an IP address")?,
+            "listen" => {
+                let addr = ListenAddr::parse(value)
+                    .ok_or("expected ADDR:PORT, tls:ADDR:PORT or unix:PATH")?;
+                if self.listen[..self.listen_len].contains(&addr) {
+                    return Ok(());
+                }
+                if self.listen_len == MAX_LISTEN {
+                    return Err("too many listen addresses");
+                }
+                self.listen[self.listen_len] = addr;
+                self.listen_len += 1;
+            }
+            "ipv6-only" => self.ipv6_only = parse_bool(value)?,
+            "unix-mode" => {
+                self.unix_mode = u32::from_str_radix(value, 8).map_err(|_| "expected octal")?;
+                if self.unix_mode > 0o777 {
+                    return Err("value out of range");
+                }
+            }
+            "unix-owner" => {
+                let (uid, gid) = value.split_once(':').unwrap_or((value, ""));
+                let uid = parse_num(uid, 0, u32::MAX as u64 - 1)? as u32;
+                let gid = match gid {
+                    "" => u32::MAX,
+                    g => parse_num(g, 0, u32::MAX as u64 - 1)? as u32,
+                };
+                self.unix_owner = Some((uid, gid));
+            }
+            "token-file" | "htpasswd-file" => {
+                if value.is_empty() || value.len() > MAX_PATH_LEN {
+                    return Err("expected a file path");
+                }
+                if key == "token-file" {
+                    self.token_file = value;
+                } else {
+                    self.htpasswd_file = value;
+                }
+            }
+            "bcrypt-max-cost" => self.hash_limits.bcrypt_cost = parse_num(value, 4, 31)? as u32,
+            "sha-crypt-max-rounds" => {
+                self.hash_limits.sha_crypt_rounds = parse_num(value, 1000, 999_999_999)? as u32;
+            }
+            "tls-cert" | "tls-key" => {
+                if value.is_empty() || value.len() > MAX_PATH_LEN {
+                    return Err("expected a file path");
+                }
+                if key == "tls-cert" {
+                    self.tls_cert = value;
+                } else {
+                    self.tls_key = value;
+                }
+            }
+            "docroot" => {
+                if !value.starts_with('/') || value.len() > MAX_PATH_LEN {
+                    return Err("expected an absolute path");
+                }
+                self.docroot = value;
+            }
+            "index-files" => {
+                let valid = |name: &str| {
+                    !name.is_empty()
+                        && name != "."
+                        && name != ".."
+                        && name.len() <= MAX_PATH_LEN
+                        && !name.bytes().any(|b| b == b'/' || b == 0)
+                };
+                if !value.split(',').all(valid) {
+                    return Err("expected comma-separated file names");
+                }
+                self.index_files = value;
+            }
+            "port" => self.port = parse_num(value, 1, u16::MAX as u64)? as u16,
+            "max-workers" => self.max_workers = parse_num(value, 1, 4096)? as i32,
+            "shell" => {
+                if !value.starts_with('/') || value.len() > MAX_PATH_LEN {
+                    return Err("expected an absolute path");
+                }
+                self.shell = value;
+            }
+            "buffer-size" => {
+                self.buffer_size = parse_num(value, 4096, 16 * 1024 * 1024)? as usize;
+            }
+            "max-message" => {
+                self.max_message = parse_num(value, 125, 16 * 1024 * 1024)? as usize;
+            }
+            "ping-interval" => self.ping_interval = parse_num(value, 0, 3600)? as u32,
+            "ping-max-missed" => self.ping_max_missed = parse_num(value, 1, 100)? as u32,
+            "close-timeout" => self.close_timeout = parse_num(value, 0, 60_000)? as u32,
+            "session-grace" => self.session_grace = parse_num(value, 0, 86_400)? as u32,
+            "scrollback" => self.scrollback = parse_num(value, 0, 16 * 1024 * 1024)? as usize,
+            "max-viewers" => self.max_viewers = parse_num(value, 0, MAX_VIEWERS as u64)? as usize,
+            "collaborate" => self.collaborate = parse_bool(value)?,
+            "record-dir" => {
+                if !value.starts_with('/') || value.len() > MAX_PATH_LEN {
+                    return Err("expected an absolute path");
+                }
+                self.record_dir = value;
+            }
+            "record-input" => self.record_input = parse_bool(value)?,
+            "header-timeout" => self.header_timeout = parse_num(value, 100, 300_000)? as u32,
+            "keepalive-timeout" => {
+                self.keepalive_timeout = parse_num(value, 0, 300_000)? as u32;
+            }
+            "keepalive-requests" => {
+                self.keepalive_requests = parse_num(value, 1, 1_000_000)? as u32;
+            }
+            "env" => self.env.push(value)?,
+            "env-inherit" => self.env_inherit = parse_name_list(value)?,
+            "client-env" => self.client_env = parse_name_list(value)?,
+            "allowed-origins" => self.allowed_origins = parse_origin_list(value)?,
+            _ => match key.strip_prefix("route.") {
+                Some(rest) => self.routes.set(rest, value)?,
+                None => return Err("unknown setting"),
+            },
+        }
+        Ok(())
+    }
+
+    /// Cross-field and filesystem checks run once every source is applied.
+    fn validate(&mut self) -> Result<(), (&'static str, &'static str)> {
+        let mut path = [0u8; MAX_PATH_LEN + 1];
+        path[..self.shell.len()].copy_from_slice(self.shell.as_bytes());
+        if sys::fs::access(path.as_ptr(), sys::fs::X_OK).is_err() {
+            return Err(("shell", "not an executable file"));
+        }
+        if self.listen_len == 0 {
+            self.listen[0] = ListenAddr::Tcp(SocketAddr {
+                ip: self.bind,
+                port: self.port,
+            });
+            self.listen_len = 1;
+        }
+        let wants_tls = self
+            .listen_addrs()
+            .iter()
+            .any(|a| matches!(a, ListenAddr::Tls(_)));
+        if wants_tls && self.tls_cert.is_empty() {
+            return Err(("tls-cert", "required by tls: listeners"));
+        }
+        if wants_tls && self.tls_key.is_empty() {
+            return Err(("tls-key", "required by tls: listeners"));
+        }
+        self.routes.finish(self.shell).map_err(|msg| ("route", msg))
+    }
+
+    /// Addresses to listen on: every `listen` entry, or `bind`:`port`.
+    pub(crate) fn listen_addrs(&self) -> &[ListenAddr] {
+        &self.listen[..self.listen_len]
+    }
+
+    fn print(&self) {
+        let mut num = itoa::Buffer::new();
+        let mut addr = [0u8; MAX_LISTEN_ADDR_LEN];
+        out(b"bind = ");
+        let n = self.bind.format(&mut addr);
+        out(&addr[..n]);
+        out(b"\nport = ");
+        out(num.format(self.port).as_bytes());
+        for a in self.listen_addrs() {
+            out(b"\nlisten = ");
+            let n = a.format(&mut addr);
+            out(&addr[..n]);
+        }
+        out(b"\nipv6-only = ");
+        out(if self.ipv6_only { b"true" } else { b"false" });
+        out(b"\nunix-mode = 0");
+        let mut octal = [0u8; 3];
+        for (i, d) in octal.iter_mut().enumerate() {
+            *d = b'0' + ((self.unix_mode >> (3 * (2 - i))) & 7) as u8;
+        }
+        out(&octal);
+        if let Some((uid, gid)) = self.unix_owner {
+            out(b"\nunix-owner = ");
+            out(num.format(uid).as_bytes());
+            if gid != u32::MAX {
+                out(b":");
+                out(num.format(gid).as_bytes());
+            }
+        }
+        if !self.tls_cert.is_empty() {
+            out(b"\ntls-cert = ");
+            out(self.tls_cert.as_bytes());
+        }
+        if !self.tls_key.is_empty() {
+            out(b"\ntls-key = ");
+            out(self.tls_key.as_bytes());
+        }
+        if !self.docroot.is_empty() {
+            out(b"\ndocroot = ");
+            out(self.docroot.as_bytes());
+        }
+        out(b"\nindex-files = ");
+        out(self.index_files.as_bytes());
+        out(b"\nmax-workers = ");
+        out(num.format(self.max_workers).as_bytes());
+        out(b"\nshell = ");
+        out(self.shell.as_bytes());
+        out(b"\nbuffer-size = ");
+        out(num.format(self.buffer_size).as_bytes());
+        out(b"\nmax-message = ");
+        out(num.format(self.max_message).as_bytes());
+        out(b"\nping-interval = ");
+        out(num.format(self.ping_interval).as_bytes());
+        out(b"\nping-max-missed = ");
+        out(num.format(self.ping_max_missed).as_bytes());
+        out(b"\nclose-timeout = ");
+        out(num.format(self.close_timeout).as_bytes());
+        out(b"\nsession-grace = ");
+        out(num.format(self.session_grace).as_bytes());
+        out(b"\nscrollback = ");
+        out(num.format(self.scrollback).as_bytes());
+        out(b"\nmax-viewers = ");
+        out(num.format(self.max_viewers).as_bytes());
+        out(b"\ncollaborate = ");
+        out(if self.collaborate { b"true" } else { b"false" });
+        if !self.record_dir.is_empty() {
+            out(b"\nrecord-dir = ");
+            out(self.record_dir.as_bytes());
+        }
+        out(b"\nrecord-input = ");
+        out(if self.record_input { b"true" } else { b"false" });
+        out(b"\nheader-timeout = ");
+        out(num.format(self.header_timeout).as_bytes());
+        out(b"\nkeepalive-timeout = ");
+        out(num.format(self.keepalive_timeout).as_bytes());
+        out(b"\nkeepalive-requests = ");
+        out(num.format(self.keepalive_requests).as_bytes());
+        out(b"\nenv-inherit = ");
+        out(self.env_inherit.as_bytes());
+        out(b"\nclient-env = ");
+        out(self.client_env.as_bytes());
+        out(b"\nallowed-origins = ");
+        out(self.allowed_origins.as_bytes());
+        if !self.token_file.is_empty() {
+            out(b"\ntoken-file = ");
+            out(self.token_file.as_bytes());
+        }
+        if !self.htpasswd_file.is_empty() {
+            out(b"\nhtpasswd-file = ");
+            out(self.htpasswd_file.as_bytes());
+        }
+        out(b"\nbcrypt-max-cost = ");
+        out(num.format(self.hash_limits.bcrypt_cost).as_bytes());
+        out(b"\nsha-crypt-max-rounds = ");
+        out(num.format(self.hash_limits.sha_crypt_rounds).as_bytes());
+        for entry in self.env.iter() {
+            out(b"\nenv = ");
+            out(entry.as_bytes());
+        }
+        out(b"\n");
+        self.routes.print();
+    }
+}
+
+/// Build the configuration from every source, exiting with a message on
+/// `--help`, `--print-config` or any invalid setting.
+pub(crate) fn load(args: Args) -> Config {
+    let mut cfg = Config::defaults();
+    let mut print_config = false;
+    let mut config_file: Option<&'static str> = args
+        .getenv(b"XTERM_BACKEND_CONFIG")
+        .map(|v| utf8_or_fail(b"XTERM_BACKEND_CONFIG", v));
+
+    // First pass: flags that change what the other passes do.
+    let mut i = 1;
+    while let Some(arg) = args.arg(i) {
+        match arg {
+            b"-h" | b"--help" => {
+                out(HELP);
+                crate::runtime::exit_now(0);
+            }
+            b"--print-config" => print_config = true,
+            b"--config" => {
+                let v = args
+                    .arg(i + 1)
+                    .unwrap_or_else(|| fail(arg, b"", "missing value"));
+                config_file = Some(utf8_or_fail(arg, v));
+                i += 1;
+            }
+            _ if arg.starts_with(b"--config=") => {
+                config_file = Some(utf8_or_fail(arg, &arg[b"--config=".len()..]));
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    if let Some(path) = config_file {
+        load_file(&mut cfg, path);
+    }
+
+    let mut i = 0;
+    while let Some(entry) = args.env(i) {
+        i += 1;
+        if !entry.starts_with(ENV_PREFIX) || entry.starts_with(b"XTERM_BACKEND_CONFIG=") {
+            continue;
+        }
+        let Some(eq) = entry.iter().position(|&b| b == b'=') else {
+            continue;
+        };
+        let name = &entry[..eq];
+        let mut key = [0u8; 64];
+        let suffix = &name[ENV_PREFIX.len()..];
+        if suffix.len() > key.len() {
+            fail(name, b"", "unknown setting");
+        }
+        for (dst, &src) in key.iter_mut().zip(suffix) {
+            *dst = match src {
+                b'_' => b'-',
+                c => c.to_ascii_lowercase(),
+            };
+        }
+        let key = core::str::from_utf8(&key[..suffix.len()]).unwrap_or("");
+        let value = utf8_or_fail(name, &entry[eq + 1..]);
+        if let Err(msg) = cfg.set(key, value) {
+            fail(name, b"", msg);
+        }
+    }
+
+    let mut i = 1;
+    while let Some(arg) = args.arg(i) {
+        i += 1;
+        if arg == b"-h" || arg == b"--help" || arg == b"--print-config" {
+            continue;
+        }
+        let Some(opt) = arg.strip_prefix(b"--") else {
+            fail(arg, b"", "unexpected argument (see --help)");
+        };
+        let (key, value) = match opt.iter().position(|&b| b == b'=') {
+            Some(eq) => (&opt[..eq], &opt[eq + 1..]),
+            None => {
+                let v = args
+                    .arg(i)
+                    .unwrap_or_else(|| fail(arg, b"", "missing value"));
+                i += 1;
+                (opt, v)
+            }
+        };
+        if key == b"config" {
+            continue;
+        }
+        let value = utf8_or_fail(arg, value);
+        let key_str = core::str::from_utf8(key).unwrap_or("");
+        if let Err(msg) = cfg.set(key_str, value) {
+            fail(&arg[..2 + key.len()], b"", msg);
+        }
+    }
+
+    if let Err((key, msg)) = cfg.validate() {
+        fail(key.as_bytes(), b"", msg);
+    }
+    if !cfg.tls_cert.is_empty() || !cfg.tls_key.is_empty() {
+        cfg.tls = Some(load_credentials(cfg.tls_cert, cfg.tls_key));
+    }
+    if !cfg.docroot.is_empty() {
+        match open_dir(cfg.docroot) {
+            Ok(fd) => cfg.docroot_fd = Some(fd),
+            Err(_) => fail(b"docroot", cfg.docroot.as_bytes(), "cannot open directory"),
+        }
+    }
+    if !cfg.record_dir.is_empty() {
+        match open_dir(cfg.record_dir) {
+            Ok(fd) => cfg.record_dir_fd = Some(fd),
+            Err(_) => fail(
+                b"record-dir",
+                cfg.record_dir.as_bytes(),
+                "cannot open directory",
+            ),
+        }
+    }
+    let mut i = 0;
+    while let Some(entry) = args.env(i) {
+        i += 1;
+        let Some(eq) = entry.iter().position(|&b| b == b'=') else {
+            continue;
+        };
+        let Ok(name) = core::str::from_utf8(&entry[..eq]) else {
+            continue;
+        };
+        if list_contains(cfg.env_inherit, name)
+            && let Ok(entry) = core::str::from_utf8(entry)
+        {
+            // Only fails once the list is full; the rest are dropped.
+            let _ = cfg.inherited.push(entry);
+        }
+    }
+    if print_config {
+        cfg.print();
+        crate::runtime::exit_now(0);
+    }
+    cfg
+}
+
+fn load_file(cfg: &mut Config, path: &'static str) {
+    let text = match read_file(path) {
+        Ok(t) => t,
+        Err(msg) => fail(path.as_bytes(), b"", msg),
+    };
+    let mut num = itoa::Buffer::new();
+    for (lineno, raw) in text.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut origin = [0u8; MAX_PATH_LEN + 16];
+        let mut olen = copy(&mut origin, path.as_bytes());
+        olen += copy(&mut origin[olen..], b":");
+        olen += copy(&mut origin[olen..], num.format(lineno + 1).as_bytes());
+        let Some((key, value)) = line.split_once('=') else {
+            fail(&origin[..olen], line.as_bytes(), "expected `key = value`");
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if let Err(msg) = cfg.set(key, value) {
+            fail(&origin[..olen], key.as_bytes(), msg);
+        }
+    }
+}
+
+/// Read and check the TLS certificate chain and private key.
+fn load_credentials(cert: &str, key: &str) -> Credentials {
+    if cert.is_empty() || key.is_empty() {
+        let missing = if cert.is_empty() {
+            "tls-cert"
+        } else {
+            "tls-key"
+        };
+        fail(missing.as_bytes(), b"", "tls-cert and tls-key go together");
+    }
+    let cert_pem = read_file(cert).unwrap_or_else(|msg| fail(cert.as_bytes(), b"", msg));
+    let key_pem = read_file(key).unwrap_or_else(|msg| fail(key.as_bytes(), b"", msg));
+    Credentials::parse(cert_pem, key_pem).unwrap_or_else(|msg| fail(b"tls", b"", msg))
+}
+
+/// Read a whole file into a buffer that is never freed, so settings can
+/// borrow from it for the rest of the process lifetime.
+fn read_file(path: &str) -> Result<&'static str, &'static str> {
+    if path.len() > MAX_PATH_LEN {
+        return Err("path too long");
+    }
+    let mut cpath = [0u8; MAX_PATH_LEN + 1];
+    cpath[..path.len()].copy_from_slice(path.as_bytes());
+    let fd = sys::fs::open(cpath.as_ptr(), sys::fs::O_RDONLY | sys::fs::O_CLOEXEC, 0)
+        .map_err(|_| "cannot open file")?;
+    let ptr = crate::runtime::allocator::page_alloc(MAX_FILE_LEN).map_err(|_| "out of memory")?;
+    let buf = util::ptr_to_mut_slice(ptr, MAX_FILE_LEN);
+    let mut len = 0;
+    let res = loop {
+        if len == buf.len() {
+            break Err("file too large");
+        }
+        match sys::fs::read(fd, &mut buf[len..]) {
+            Ok(0) => break Ok(()),
+            Ok(n) => len += n,
+            Err(_) => break Err("read failed"),
+        }
+    };
+    let _ = sys::fs::close(fd);
+    res?;
+    core::str::from_utf8(util::ptr_to_slice(ptr, len)).map_err(|_| "not valid UTF-8")
+}
+
+/// `O_PATH` descriptor for the directory at absolute path `dir`.
+fn open_dir(dir: &str) -> sys::SysResult<usize> {
+    let mut path = [0u8; MAX_PATH_LEN + 1];
+    path[..dir.len()].copy_from_slice(dir.as_bytes());
+    let flags = sys::fs::O_PATH | sys::fs::O_DIRECTORY | sys::fs::O_CLOEXEC;
+    sys::fs::open(path.as_ptr(), flags, 0)
+}
+
+fn parse_num(s: &str, min: u64, max: u64) -> Result<u64, &'static str> {
+    let v: u64 = s.parse().map_err(|_| "expected a number")?;
+    if v < min || v > max {
+        return Err("value out of range");
+    }
+    Ok(v)
+}
+
+/// Comma-separated variable names; empty means none.
+fn parse_name_list(s: &'static str) -> Result<&'static str, &'static str> {
+    if !s.is_empty()
+        && !s
+            .split(',')
+            .all(|n| crate::pty::is_valid_name(n.as_bytes()))
+    {
+        return Err("expected comma-separated variable names");
+    }
+    Ok(s)
+}
+
+/// `*`, `same-origin` or `scheme://host[:port]` entries; empty allows no
+/// browser page at all.
+fn parse_origin_list(s: &'static str) -> Result<&'static str, &'static str> {
+    let valid = |entry: &str| match entry {
+        "*" | "same-origin" => true,
+        origin => origin.split_once("://").is_some_and(|(scheme, authority)| {
+            !scheme.is_empty()
+                && scheme
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"+-.".contains(&b))
+                && !authority.is_empty()
+                && !authority.contains('/')
+        }),
+    };
+    if !s.is_empty() && !s.split(',').all(valid) {
+        return Err("expected *, same-origin or scheme://host[:port] entries");
+    }
+    Ok(s)
+}
+
+fn parse_bool(s: &str) -> Result<bool, &'static str> {
+    match s {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err("expected true or false"),
+    }
+}
+
+fn copy(dst: &mut [u8], src: &[u8]) -> usize {
+    let n = core::cmp::min(dst.len(), src.len());
+    dst[..n].copy_from_slice(&src[..n]);
+    n
+}
+
+fn utf8_or_fail(origin: &[u8], v: &'static [u8]) -> &'static str {
+    core::str::from_utf8(v).unwrap_or_else(|_| fail(origin, b"", "not valid UTF-8"))
+}
+
+fn out(msg: &[u8]) {
+    let _ = sys::fs::write(1, msg);
+}
+
+fn fail(origin: &[u8], detail: &[u8], msg: &str) -> ! {
+    let _ = sys::fs::write(2, b"config error: ");
+    let _ = sys::fs::write(2, origin);
+    if !detail.is_empty() {
+        let _ = sys::fs::write(2, b": ");
+        let _ = sys::fs::write(2, detail);
+    }
+    let _ = sys::fs::write(2, b": ");
+    let _ = sys::fs::write(2, msg.as_bytes());
+    let _ = sys::fs::write(2, b"\n");
+    crate::runtime::exit_now(2)
+}
@@ -0,0 +1,188 @@
+//! WebSocket route table: which program a session on a given path runs.
+//!
+//! Routes are configured with dotted keys, e.g. in the config file
+//!
+//! ```text
+//! route.top.path = /term/top
+//! route.top.command = /usr/bin/top
+//! route.top.args = -d 1
+//! route.top.cwd = /tmp
+//! route.top.env = TERM=xterm
+//! ```
+//!
+//! `args` is split on whitespace (no quoting); `env` may be repeated. Unless
+//! a route claims it, `/term` runs the configured `shell`.
+
+use super::{EnvList, MAX_PATH_LEN, out};
+use crate::sys;
+
+const MAX_ROUTES: usize = 16;
+const MAX_NAME_LEN: usize = 32;
+const DEFAULT_PATH: &str = "/term";
+
+#[derive(Copy, Clone)]
+pub(crate) struct Route {
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    pub(crate) path: &'static str,
+    pub(crate) command: &'static str,
+    pub(crate) args: &'static str,
+    /// Working directory; empty keeps the server's.
+    pub(crate) cwd: &'static str,
+    pub(crate) env: EnvList,
+}
+
+impl Route {
+    const fn new() -> Self {
+        Self {
+            name: [0; MAX_NAME_LEN],
+            name_len: 0,
+            path: "",
+            command: "",
+            args: "",
+            cwd: "",
+            env: EnvList::new(),
+        }
+    }
+
+    fn named(name: &str) -> Self {
+        let mut r = Self::new();
+        r.name[..name.len()].copy_from_slice(name.as_bytes());
+        r.name_len = name.len();
+        r
+    }
+
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+}
+
+pub(crate) struct Routes {
+    table: [Route; MAX_ROUTES],
+    len: usize,
+}
+
+impl Routes {
+    pub(super) const fn new() -> Self {
+        Self {
+            table: [Route::new(); MAX_ROUTES],
+            len: 0,
+        }
+    }
+
+    /// Apply `route.<name>.<field> = value`; `rest` is `<name>.<field>`.
+    pub(super) fn set(&mut self, rest: &str, value: &'static str) -> Result<(), &'static str> {
+        let (name, field) = rest.split_once('.').ok_or("expected route.NAME.FIELD")?;
+        if name.is_empty()
+            || name.len() > MAX_NAME_LEN
+            || !name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        {
+            return Err("bad route name");
+        }
+        let route = match self.table[..self.len].iter().position(|r| r.name() == name) {
+            Some(i) => &mut self.table[i],
+            None if self.len < MAX_ROUTES => {
+                self.table[self.len] = Route::named(name);
+                self.len += 1;
+                &mut self.table[self.len - 1]
+            }
+            None => return Err("too many routes"),
+        };
+        match field {
+            "path" => {
+                if !value.starts_with('/') || value.contains(['?', ' ']) {
+                    return Err("expected a path starting with /");
+                }
+                route.path = value;
+            }
+            "command" => route.command = absolute_path(value)?,
+            "args" => route.args = value,
+            "cwd" => route.cwd = absolute_path(value)?,
+            "env" => route.env.push(value)?,
+            _ => return Err("unknown route field"),
+        }
+        Ok(())
+    }
+
+    /// Check every route is complete and add the default `/term` route
+    /// running `shell` unless one was configured for it.
+    pub(super) fn finish(&mut self, shell: &'static str) -> Result<(), &'static str> {
+        for (i, r) in self.table[..self.len].iter().enumerate() {
+            if r.path.is_empty() {
+                return Err("route has no path");
+            }
+            if r.command.is_empty() {
+                return Err("route has no command");
+            }
+            if self.table[..i].iter().any(|o| o.path == r.path) {
+                return Err("duplicate route path");
+            }
+            if !check_access(r.command, sys::fs::X_OK) {
+                return Err("route command is not an executable file");
+            }
+            if !r.cwd.is_empty() && !check_access(r.cwd, sys::fs::X_OK) {
+                return Err("route cwd is not an accessible directory");
+            }
+        }
+        if self.find(DEFAULT_PATH).is_none() {
+            if self.len == MAX_ROUTES {
+                return Err("too many routes");
+            }
+            let mut term = Route::named("term");
+            term.path = DEFAULT_PATH;
+            term.command = shell;
+            self.table[self.len] = term;
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    /// Route serving the request path `path` (query string already removed).
+    pub(crate) fn find(&self, path: &str) -> Option<&Route> {
+        self.table[..self.len].iter().find(|r| r.path == path)
+    }
+
+    pub(super) fn print(&self) {
+        for r in &self.table[..self.len] {
+            for (field, value) in [
+                ("path", r.path),
+                ("command", r.command),
+                ("args", r.args),
+                ("cwd", r.cwd),
+            ] {
+                if value.is_empty() {
+                    continue;
+                }
+                print_line(r.name(), field, value);
+            }
+            for entry in r.env.iter() {
+                print_line(r.name(), "env", entry);
+            }
+        }
+    }
+}
+
+fn print_line(name: &str, field: &str, value: &str) {
+    out(b"route.");
+    out(name.as_bytes());
+    out(b".");
+    out(field.as_bytes());
+    out(b" = ");
+    out(value.as_bytes());
+    out(b"\n");
+}
+
+fn absolute_path(value: &'static str) -> Result<&'static str, &'static str> {
+    if !value.starts_with('/') || value.len() > MAX_PATH_LEN {
+        return Err("expected an absolute path");
+    }
+    Ok(value)
+}
+
+fn check_access(path: &str, mode: usize) -> bool {
+    let mut cpath = [0u8; MAX_PATH_LEN + 1];
+    cpath[..path.len()].copy_from_slice(path.as_bytes());
+    sys::fs::access(cpath.as_ptr(), mode).is_ok()
+}
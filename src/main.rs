@@ -1,20 +1,47 @@
 #![no_std]
 #![no_main]
 
+mod config;
 mod net;
 mod pty;
 mod runtime;
 mod server;
 mod sys;
 
-fn main() -> ! {
-    let (listen_fd, epfd, sfd) = crate::server::setup_listener();
+fn main(args: crate::runtime::args::Args) -> ! {
+    let cfg = crate::config::load(args);
+    let (listeners, epfd, sfd) = crate::server::setup_listener(&cfg);
 
     let mut events = [crate::sys::epoll::EpollEvent::default(); 8];
-    let mut active_workers: i32 = 0;
-    const MAX_WORKERS: i32 = 15;
+    let mut workers = match crate::server::Workers::new(cfg.max_workers as usize) {
+        Ok(w) => w,
+        Err(_) => crate::server::exit_now(1),
+    };
+    let mut sessions = match crate::server::Sessions::new(cfg.max_workers as usize) {
+        Ok(s) => s,
+        Err(_) => crate::server::exit_now(1),
+    };
+    let mut pending = match crate::server::Pending::new() {
+        Ok(p) => p,
+        Err(_) => crate::server::exit_now(1),
+    };
+    let mut auth = match crate::server::Auth::new(&cfg) {
+        Ok(a) => a,
+        Err((key, msg)) => {
+            crate::server::log(key.as_bytes());
+            crate::server::log(b": ");
+            crate::server::log(msg.as_bytes());
+            crate::server::log(b"\n");
+            crate::server::exit_now(1)
+        }
+    };
     loop {
-        let n = match crate::sys::epoll::epoll_wait(epfd, &mut events, -1) {
+        // Wake up in time to expire the oldest unfinished request head.
+        let timeout = match pending.next_deadline() {
+            Some(d) => d.saturating_sub(crate::sys::timer::monotonic_ms()) as isize,
+            None => -1,
+        };
+        let n = match crate::sys::epoll::epoll_wait(epfd, &mut events, timeout) {
             Ok(v) => v,
             Err(_) => continue,
         };
@@ -22,31 +49,39 @@ fn main() -> ! {
         for event in events.iter().take(n) {
             let fd = event.fd();
             if fd == sfd {
-                if crate::server::handle_signal_event(sfd, &mut active_workers) {
+                if crate::server::handle_signal_event(sfd, &mut workers, &mut sessions) {
                     shutdown = true;
                     break;
                 }
                 continue;
             }
-            if fd == listen_fd
-                && crate::server::handle_listener_event(
-                    listen_fd,
-                    &mut active_workers,
-                    MAX_WORKERS,
+            if listeners.contains(fd) {
+                // errors are logged inside handler; continue accepting
+                let _ =
+                    crate::server::handle_listener_event(fd, &listeners, &mut pending, &cfg, epfd);
+            } else if pending.contains(fd) {
+                let _ = crate::server::handle_connection_event(
+                    fd,
+                    &listeners,
+                    &mut pending,
+                    &mut workers,
+                    &mut sessions,
+                    &mut auth,
+                    &cfg,
                     sfd,
                     epfd,
-                )
-                .is_err()
-            {
-                // errors are logged inside handler; continue accepting
-                continue;
+                );
             }
         }
+        crate::server::expire_connections(&mut pending, epfd);
         if shutdown {
             break;
         }
     }
-    let _ = crate::sys::fs::close(listen_fd);
+    // Workers answer SIGTERM by closing their WebSocket with 1001.
+    workers.signal_all(15);
+    listeners.close_all();
+    listeners.remove_files();
     if sfd != usize::MAX {
         let _ = crate::sys::fs::close(sfd);
     }
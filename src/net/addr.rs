@@ -0,0 +1,275 @@
+//! IP and socket address parsing and formatting.
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum IpAddr {
+    V4([u8; 4]),
+    V6([u8; 16]),
+}
+
+/// Address and port to listen on.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) struct SocketAddr {
+    pub(crate) ip: IpAddr,
+    pub(crate) port: u16,
+}
+
+/// Something the server listens on.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum ListenAddr {
+    Tcp(SocketAddr),
+    /// TCP with TLS in front of HTTP.
+    Tls(SocketAddr),
+    /// Socket file path, or an abstract-namespace name when it starts with
+    /// `@`.
+    Unix(&'static str),
+}
+
+/// Longest `sun_path` contents, leaving room for the terminating NUL.
+pub(crate) const MAX_UNIX_PATH: usize = 107;
+/// Longest formatted [`ListenAddr`]: `unix:` and a full path (a TCP address
+/// is at most `[` + 39-char IPv6 + `]:65535`).
+pub(crate) const MAX_LISTEN_ADDR_LEN: usize = 5 + MAX_UNIX_PATH;
+
+impl IpAddr {
+    /// Parse a dotted IPv4 address or an IPv6 address, the latter optionally
+    /// in brackets.
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        if let Some(inner) = s.strip_prefix('[') {
+            return parse_ipv6(inner.strip_suffix(']')?).map(IpAddr::V6);
+        }
+        if s.contains(':') {
+            return parse_ipv6(s).map(IpAddr::V6);
+        }
+        parse_ipv4(s).map(IpAddr::V4)
+    }
+
+    /// Write the textual form into `out` (IPv6 without brackets), returning
+    /// the length.
+    pub(crate) fn format(&self, out: &mut [u8]) -> usize {
+        let mut w = Writer { out, len: 0 };
+        match self {
+            IpAddr::V4(ip) => {
+                for (i, octet) in ip.iter().enumerate() {
+                    if i > 0 {
+                        w.push(b".");
+                    }
+                    w.dec(*octet as u16);
+                }
+            }
+            IpAddr::V6(ip) => {
+                let mut groups = [0u16; 8];
+                for (i, g) in groups.iter_mut().enumerate() {
+                    *g = u16::from_be_bytes([ip[2 * i], ip[2 * i + 1]]);
+                }
+                // Compress the longest run of two or more zero groups.
+                let (mut best, mut best_len) = (8, 1);
+                let mut i = 0;
+                while i < 8 {
+                    let start = i;
+                    while i < 8 && groups[i] == 0 {
+                        i += 1;
+                    }
+                    if i - start > best_len {
+                        best = start;
+                        best_len = i - start;
+                    }
+                    i += 1;
+                }
+                let mut i = 0;
+                while i < 8 {
+                    if i == best {
+                        w.push(b"::");
+                        i += best_len;
+                        continue;
+                    }
+                    if i > 0 && i != best + best_len {
+                        w.push(b":");
+                    }
+                    w.hex(groups[i]);
+                    i += 1;
+                }
+            }
+        }
+        w.len
+    }
+}
+
+impl SocketAddr {
+    /// Parse `ADDR:PORT`, with IPv6 addresses in brackets (`[::]:8000`).
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let (host, port) = s.rsplit_once(':')?;
+        if host.contains(':') && !host.starts_with('[') {
+            return None;
+        }
+        let port: u16 = port.parse().ok()?;
+        if port == 0 {
+            return None;
+        }
+        Some(Self {
+            ip: IpAddr::parse(host)?,
+            port,
+        })
+    }
+
+    pub(crate) fn format(&self, out: &mut [u8]) -> usize {
+        let mut w = Writer { out, len: 0 };
+        let v6 = matches!(self.ip, IpAddr::V6(_));
+        if v6 {
+            w.push(b"[");
+        }
+        w.len += self.ip.format(&mut w.out[w.len..]);
+        if v6 {
+            w.push(b"]");
+        }
+        w.push(b":");
+        w.dec(self.port);
+        w.len
+    }
+}
+
+impl ListenAddr {
+    /// Parse `ADDR:PORT`, `tls:ADDR:PORT`, `unix:/path/to.sock` or
+    /// `unix:@abstract-name`.
+    pub(crate) fn parse(s: &'static str) -> Option<Self> {
+        if let Some(addr) = s.strip_prefix("tls:") {
+            return SocketAddr::parse(addr).map(ListenAddr::Tls);
+        }
+        let Some(path) = s.strip_prefix("unix:") else {
+            return SocketAddr::parse(s).map(ListenAddr::Tcp);
+        };
+        let valid = match path.strip_prefix('@') {
+            Some(name) => !name.is_empty(),
+            None => path.starts_with('/'),
+        };
+        if !valid || path.len() > MAX_UNIX_PATH || path.contains('\0') {
+            return None;
+        }
+        Some(ListenAddr::Unix(path))
+    }
+
+    pub(crate) fn format(&self, out: &mut [u8]) -> usize {
+        match self {
+            ListenAddr::Tcp(addr) => addr.format(out),
+            ListenAddr::Tls(addr) => {
+                out[..4].copy_from_slice(b"tls:");
+                4 + addr.format(&mut out[4..])
+            }
+            ListenAddr::Unix(path) => {
+                let mut w = Writer { out, len: 0 };
+                w.push(b"unix:");
+                w.push(path.as_bytes());
+                w.len
+            }
+        }
+    }
+}
+
+fn parse_ipv4(s: &str) -> Option<[u8; 4]> {
+    let mut out = [0u8; 4];
+    let mut parts = s.split('.');
+    for octet in out.iter_mut() {
+        let p = parts.next()?;
+        if p.is_empty() || p.len() > 3 || !p.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        *octet = p.parse().ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(out)
+}
+
+/// RFC 4291 text form: up to eight hex groups, at most one `::`, and an
+/// optional trailing dotted IPv4 address.
+fn parse_ipv6(s: &str) -> Option<[u8; 16]> {
+    let (head, tail) = match s.split_once("::") {
+        Some((h, t)) => (h, Some(t)),
+        None => (s, None),
+    };
+    let mut groups = [0u16; 8];
+    let n_head = parse_groups(head, &mut groups, tail.is_none())?;
+    let ip = match tail {
+        None => {
+            if n_head != 8 {
+                return None;
+            }
+            groups
+        }
+        Some(tail) => {
+            let mut rest = [0u16; 8];
+            let n_tail = parse_groups(tail, &mut rest, true)?;
+            if n_head + n_tail > 7 {
+                return None;
+            }
+            groups[8 - n_tail..].copy_from_slice(&rest[..n_tail]);
+            groups
+        }
+    };
+    let mut out = [0u8; 16];
+    for (i, g) in ip.iter().enumerate() {
+        out[2 * i..2 * i + 2].copy_from_slice(&g.to_be_bytes());
+    }
+    Some(out)
+}
+
+/// Parse colon-separated groups into `out`, returning how many were read.
+/// A dotted IPv4 address is accepted as the last element when `last` is set.
+fn parse_groups(s: &str, out: &mut [u16; 8], last: bool) -> Option<usize> {
+    if s.is_empty() {
+        return Some(0);
+    }
+    let mut n = 0;
+    let mut parts = s.split(':').peekable();
+    while let Some(p) = parts.next() {
+        if last && parts.peek().is_none() && p.contains('.') {
+            let v4 = parse_ipv4(p)?;
+            if n + 2 > 8 {
+                return None;
+            }
+            out[n] = u16::from_be_bytes([v4[0], v4[1]]);
+            out[n + 1] = u16::from_be_bytes([v4[2], v4[3]]);
+            return Some(n + 2);
+        }
+        if n == 8 || p.is_empty() || p.len() > 4 {
+            return None;
+        }
+        out[n] = u16::from_str_radix(p, 16).ok()?;
+        n += 1;
+    }
+    Some(n)
+}
+
+struct Writer<'a> {
+    out: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn push(&mut self, bytes: &[u8]) {
+        let n = core::cmp::min(bytes.len(), self.out.len() - self.len);
+        self.out[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+        self.len += n;
+    }
+
+    fn dec(&mut self, n: u16) {
+        let mut num = itoa::Buffer::new();
+        self.push(num.format(n).as_bytes());
+    }
+
+    fn hex(&mut self, n: u16) {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+        let mut buf = [0u8; 4];
+        let mut i = buf.len();
+        let mut v = n;
+        loop {
+            i -= 1;
+            buf[i] = DIGITS[(v & 0xF) as usize];
+            v >>= 4;
+            if v == 0 {
+                break;
+            }
+        }
+        self.push(&buf[i..]);
+    }
+}
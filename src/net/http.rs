@@ -1,3 +1,9 @@
+use crate::net::stream::Stream;
+use crate::sys;
+
+/// Longest path, after decoding, looked up under the document root.
+const MAX_PATH: usize = 1024;
+
 pub(crate) fn is_websocket_upgrade(req: &[u8]) -> bool {
     let upgrade = header(req, "Upgrade");
     let connection = header(req, "Connection");
@@ -12,11 +18,229 @@ pub(crate) fn is_websocket_upgrade(req: &[u8]) -> bool {
     }
 }
 
-pub(crate) fn path_is_term(req: &[u8]) -> bool {
-    if let Some(line) = first_line(req) {
-        line.starts_with("GET /term ")
-    } else {
-        false
+/// A request head with its request line taken apart.
+pub(crate) struct Request<'a> {
+    pub(crate) method: &'a str,
+    /// Path of the request target, percent-decoded.
+    pub(crate) path: &'a str,
+    /// Query string of the request target as sent, without the `?`.
+    pub(crate) query: Option<&'a str>,
+    /// Minor version of HTTP/1.x.
+    pub(crate) minor: u8,
+    /// Whether the connection stays open after the response; the server
+    /// may clear it (e.g. at its per-connection request cap).
+    pub(crate) keep_alive: bool,
+    /// The whole head, for header and query lookups.
+    pub(crate) head: &'a [u8],
+}
+
+impl<'a> Request<'a> {
+    /// Check `head` and take its request line apart, decoding the path into
+    /// `path_buf`. The error is the status line to answer with.
+    pub(crate) fn parse(head: &'a [u8], path_buf: &'a mut [u8]) -> Result<Self, &'static [u8]> {
+        const BAD: &[u8] = b"400 Bad Request";
+        let text = core::str::from_utf8(head).map_err(|_| BAD)?;
+        let mut lines = text.split("\r\n");
+        let mut parts = lines.next().ok_or(BAD)?.split(' ');
+        let (Some(method), Some(target), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(BAD);
+        };
+        // Origin form only; no fragments, controls or stray whitespace.
+        if method.is_empty()
+            || !method.bytes().all(is_tchar)
+            || !target.starts_with('/')
+            || target.bytes().any(|b| b <= b' ' || b == 0x7f || b == b'#')
+        {
+            return Err(BAD);
+        }
+        let minor = match version.as_bytes() {
+            b"HTTP/1.1" => 1,
+            b"HTTP/1.0" => 0,
+            [b'H', b'T', b'T', b'P', b'/', major, b'.', minor]
+                if major.is_ascii_digit() && minor.is_ascii_digit() =>
+            {
+                return Err(b"505 HTTP Version Not Supported");
+            }
+            _ => return Err(BAD),
+        };
+        // Field names are tokens, which also rules out whitespace before
+        // the colon and obsolete line folding (RFC 9112 section 5).
+        let mut hosts = 0;
+        for line in lines.take_while(|l| !l.is_empty()) {
+            let (name, _) = line.split_once(':').ok_or(BAD)?;
+            if name.is_empty() || !name.bytes().all(is_tchar) {
+                return Err(BAD);
+            }
+            hosts += eq_case_insensitive(name, "Host") as u32;
+        }
+        if hosts > 1 || (minor == 1 && hosts == 0) {
+            return Err(BAD);
+        }
+        let (raw_path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (target, None),
+        };
+        let n = path_decode(raw_path, path_buf).ok_or(BAD)?;
+        let path = core::str::from_utf8(&path_buf[..n]).map_err(|_| BAD)?;
+        if path.contains('\0') {
+            return Err(BAD);
+        }
+        let connection = |token| {
+            header(head, "Connection").is_some_and(|v| {
+                v.split(',')
+                    .any(|part| eq_case_insensitive(part.trim(), token))
+            })
+        };
+        // Bodies are never read, so a request with one ends the connection
+        // rather than have it taken for the next request.
+        let has_body = header(head, "Transfer-Encoding").is_some()
+            || header(head, "Content-Length").is_some_and(|v| v != "0");
+        let keep_alive = !has_body
+            && if minor == 1 {
+                !connection("close")
+            } else {
+                connection("keep-alive")
+            };
+        Ok(Self {
+            method,
+            path,
+            query,
+            minor,
+            keep_alive,
+            head,
+        })
+    }
+
+    pub(crate) fn header(&self, name: &str) -> Option<&'a str> {
+        header(self.head, name)
+    }
+
+    /// Credentials of an `Authorization` header using `scheme`.
+    pub(crate) fn authorization(&self, scheme: &str) -> Option<&'a str> {
+        let (name, credentials) = self.header("Authorization")?.split_once(' ')?;
+        eq_case_insensitive(name, scheme).then(|| credentials.trim())
+    }
+
+    /// Whether this asks for a WebSocket; `Upgrade` means nothing in
+    /// HTTP/1.0.
+    pub(crate) fn is_websocket_upgrade(&self) -> bool {
+        self.minor > 0 && is_websocket_upgrade(self.head)
+    }
+
+    pub(crate) fn is_head(&self) -> bool {
+        self.method == "HEAD"
+    }
+
+    /// `Connection` line, and the blank line ending the head, for a
+    /// response to this request.
+    fn connection(&self) -> &'static [u8] {
+        if self.keep_alive {
+            b"Connection: keep-alive\r\n\r\n"
+        } else {
+            b"Connection: close\r\n\r\n"
+        }
+    }
+}
+
+/// `tchar` of RFC 9110: what methods and field names are made of.
+fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// What a request path names, for the purpose of method rules.
+pub(crate) enum Resource<W, D> {
+    /// A WebSocket endpoint: `GET` with an upgrade, nothing else.
+    WebSocket(W),
+    /// A document: `GET` and `HEAD`.
+    Document(D),
+}
+
+/// How to answer a request.
+pub(crate) enum Routed<W, D> {
+    Upgrade(W),
+    /// Serve the document; only its head for `HEAD`.
+    Serve(D),
+    /// An empty error response: status and extra header lines.
+    Reject(&'static [u8], &'static [u8]),
+}
+
+/// Apply the method and upgrade rules to `resource`, what the request's
+/// path was found to name (`None` if nothing).
+pub(crate) fn route<W, D>(req: &Request, resource: Option<Resource<W, D>>) -> Routed<W, D> {
+    match resource {
+        None => Routed::Reject(b"404 Not Found", b""),
+        Some(Resource::WebSocket(_)) if req.method != "GET" => {
+            Routed::Reject(b"405 Method Not Allowed", b"Allow: GET\r\n")
+        }
+        Some(Resource::WebSocket(t)) if req.is_websocket_upgrade() => Routed::Upgrade(t),
+        Some(Resource::WebSocket(_)) => {
+            Routed::Reject(b"426 Upgrade Required", b"Upgrade: websocket\r\n")
+        }
+        Some(Resource::Document(t)) if req.method == "GET" || req.is_head() => Routed::Serve(t),
+        Some(Resource::Document(_)) => {
+            Routed::Reject(b"405 Method Not Allowed", b"Allow: GET, HEAD\r\n")
+        }
+    }
+}
+
+/// Value of `name` in the request-target query string, if present.
+pub(crate) fn query_param<'a>(req: &'a [u8], name: &str) -> Option<&'a str> {
+    query_pairs(req).find(|&(k, _)| k == name).map(|(_, v)| v)
+}
+
+/// Raw (still percent-encoded) `key=value` pairs of the request-target
+/// query string.
+pub(crate) fn query_pairs(req: &[u8]) -> impl Iterator<Item = (&str, &str)> {
+    let query = first_line(req)
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|target| target.split_once('?'))
+        .map_or("", |(_, q)| q);
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+}
+
+/// Decode `%XX` escapes and `+` into `dst`, returning the decoded length.
+/// `None` on a malformed escape or if `dst` is too small.
+pub(crate) fn percent_decode(src: &str, dst: &mut [u8]) -> Option<usize> {
+    decode(src, dst, true)
+}
+
+/// As [`percent_decode`] for a URL path, where `+` is literal.
+fn path_decode(src: &str, dst: &mut [u8]) -> Option<usize> {
+    decode(src, dst, false)
+}
+
+fn decode(src: &str, dst: &mut [u8], plus_is_space: bool) -> Option<usize> {
+    let src = src.as_bytes();
+    let (mut i, mut n) = (0, 0);
+    while i < src.len() {
+        let b = match src[i] {
+            b'+' if plus_is_space => b' ',
+            b'%' => {
+                let hi = hex_digit(*src.get(i + 1)?)?;
+                let lo = hex_digit(*src.get(i + 2)?)?;
+                i += 2;
+                (hi << 4) | lo
+            }
+            b => b,
+        };
+        *dst.get_mut(n)? = b;
+        n += 1;
+        i += 1;
+    }
+    Some(n)
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
     }
 }
 
@@ -42,7 +266,43 @@ pub(crate) fn header<'a>(req: &'a [u8], name: &str) -> Option<&'a str> {
     None
 }
 
-fn eq_case_insensitive(a: &str, b: &str) -> bool {
+/// User name and password of `Authorization: Basic` (RFC 7617), decoded
+/// into `buf`.
+pub(crate) fn basic_credentials<'b>(
+    req: &Request,
+    buf: &'b mut [u8],
+) -> Option<(&'b [u8], &'b [u8])> {
+    let n = crate::net::ws::base64_decode(req.authorization("Basic")?.as_bytes(), buf)?;
+    let credentials = &buf[..n];
+    let colon = credentials.iter().position(|&b| b == b':')?;
+    Some((&credentials[..colon], &credentials[colon + 1..]))
+}
+
+/// `WWW-Authenticate` lines for a 401 answer: a Basic challenge (RFC 7617)
+/// and/or a Bearer one (RFC 6750), the latter saying when a token was
+/// presented but refused.
+pub(crate) fn challenge(basic: bool, bearer: bool, invalid_token: bool) -> &'static [u8] {
+    match (basic, bearer, invalid_token) {
+        (false, false, _) => b"",
+        (true, false, _) => {
+            b"WWW-Authenticate: Basic realm=\"xterm-backend\", charset=\"UTF-8\"\r\n"
+        }
+        (false, true, false) => b"WWW-Authenticate: Bearer realm=\"xterm-backend\"\r\n",
+        (false, true, true) => {
+            b"WWW-Authenticate: Bearer realm=\"xterm-backend\", error=\"invalid_token\"\r\n"
+        }
+        (true, true, false) => {
+            b"WWW-Authenticate: Basic realm=\"xterm-backend\", charset=\"UTF-8\"\r\n\
+              WWW-Authenticate: Bearer realm=\"xterm-backend\"\r\n"
+        }
+        (true, true, true) => {
+            b"WWW-Authenticate: Basic realm=\"xterm-backend\", charset=\"UTF-8\"\r\n\
+              WWW-Authenticate: Bearer realm=\"xterm-backend\", error=\"invalid_token\"\r\n"
+        }
+    }
+}
+
+pub(crate) fn eq_case_insensitive(a: &str, b: &str) -> bool {
     if a.len() != b.len() {
         return false;
     }
@@ -51,14 +311,447 @@ fn eq_case_insensitive(a: &str, b: &str) -> bool {
         .all(|(x, y)| x.eq_ignore_ascii_case(&y))
 }
 
-pub(crate) fn serve_html(fd: usize, body: &[u8]) {
+/// Send an empty response with `status` (e.g. `b"431 Request Header Fields
+/// Too Large"`) and close the connection.
+pub(crate) fn serve_error(mut stream: Stream, status: &[u8]) {
+    let mut resp = [0u8; 128];
+    let n = empty_response(&mut resp, status, b"", b"Connection: close\r\n\r\n");
+    let _ = stream.send_all(&resp[..n]);
+    stream.close();
+}
+
+/// Answer `req` with an empty `status` response plus `headers` (complete
+/// lines, e.g. `b"Allow: GET\r\n"`), leaving the connection to the caller.
+pub(crate) fn serve_status(stream: &mut Stream, req: &Request, status: &[u8], headers: &[u8]) {
+    let mut resp = [0u8; 1024];
+    let n = empty_response(&mut resp, status, headers, req.connection());
+    let _ = stream.send_all(&resp[..n]);
+}
+
+fn empty_response(out: &mut [u8], status: &[u8], headers: &[u8], connection: &[u8]) -> usize {
+    let mut n = 0;
+    for part in [
+        &b"HTTP/1.1 "[..],
+        status,
+        b"\r\n",
+        headers,
+        b"Content-Length: 0\r\n",
+        connection,
+    ] {
+        out[n..n + part.len()].copy_from_slice(part);
+        n += part.len();
+    }
+    n
+}
+
+pub(crate) fn serve_html(stream: &mut Stream, req: &Request, body: &[u8]) {
     let head = b"HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: ";
     let mut lenbuf = itoa::Buffer::new();
     let len_str = lenbuf.format(body.len() as u64);
-    let tail = b"\r\nConnection: close\r\n\r\n";
-    let _ = crate::sys::net::send_all(fd, head);
-    let _ = crate::sys::net::send_all(fd, len_str.as_bytes());
-    let _ = crate::sys::net::send_all(fd, tail);
-    let _ = crate::sys::net::send_all(fd, body);
-    let _ = crate::sys::fs::close(fd);
+    let _ = stream.send_all(head);
+    let _ = stream.send_all(len_str.as_bytes());
+    let _ = stream.send_all(b"\r\n");
+    let _ = stream.send_all(req.connection());
+    if !req.is_head() {
+        let _ = stream.send_all(body);
+    }
+}
+
+/// `Content-Type` for a file name, by extension.
+pub(crate) fn content_type(path: &str) -> &'static str {
+    let ext = path.rsplit_once('.').map_or("", |(_, e)| e);
+    match ext {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Whether an `If-None-Match` value lists `etag` (weak comparison, as
+/// RFC 9110 prescribes for this header) or is `*`.
+fn etag_matches(if_none_match: &str, etag: &[u8]) -> bool {
+    if_none_match.split(',').any(|t| {
+        let t = t.trim();
+        t == "*" || t.strip_prefix("W/").unwrap_or(t).as_bytes() == etag
+    })
+}
+
+/// Serve an embedded file with a strong `etag`, answering a matching
+/// `If-None-Match` with 304. Its path names no version, so browsers are
+/// told to revalidate rather than keep it.
+pub(crate) fn serve_asset(
+    stream: &mut Stream,
+    req: &Request,
+    body: &[u8],
+    content_type: &str,
+    etag: &[u8],
+) {
+    let not_modified = req
+        .header("If-None-Match")
+        .is_some_and(|v| etag_matches(v, etag));
+    let mut lenbuf = itoa::Buffer::new();
+    let mut head = [0u8; 512];
+    let mut n = 0;
+    let mut push = |part: &[u8]| {
+        head[n..n + part.len()].copy_from_slice(part);
+        n += part.len();
+    };
+    push(if not_modified {
+        b"HTTP/1.1 304 Not Modified"
+    } else {
+        b"HTTP/1.1 200 OK"
+    });
+    push(b"\r\nETag: ");
+    push(etag);
+    push(b"\r\nCache-Control: no-cache\r\n");
+    if !not_modified {
+        push(b"Content-Type: ");
+        push(content_type.as_bytes());
+        push(b"\r\nContent-Length: ");
+        push(lenbuf.format(body.len()).as_bytes());
+        push(b"\r\n");
+    }
+    push(req.connection());
+    if stream.send_all(&head[..n]).is_ok() && !not_modified && !req.is_head() {
+        let _ = stream.send_all(body);
+    }
+}
+
+/// What a request path names under the document root.
+pub(crate) enum Found {
+    /// A directory, asked for without its trailing slash.
+    Directory,
+    /// An open regular file: the one named, or a directory's index.
+    File(StaticFile),
+}
+
+/// An open file under the document root; closed when dropped.
+pub(crate) struct StaticFile {
+    fd: usize,
+    stat: sys::fs::Stat,
+    content_type: &'static str,
+}
+
+impl Drop for StaticFile {
+    fn drop(&mut self) {
+        let _ = sys::fs::close(self.fd);
+    }
+}
+
+/// Look the decoded `path` up under the directory `root`, falling back to
+/// the first existing `index_files` entry for a directory. `None` if
+/// nothing servable is there.
+pub(crate) fn find_file(path: &str, root: usize, index_files: &'static str) -> Option<Found> {
+    let mut rel = [0u8; MAX_PATH + 1];
+    if !normalize_path(path, &mut rel) {
+        return None;
+    }
+    let (mut fd, mut stat) = open_beneath(root, &rel)?;
+    let mut content_type = content_type(path);
+    if stat.mode & sys::fs::S_IFMT == sys::fs::S_IFDIR {
+        let dir = fd;
+        if !path.ends_with('/') {
+            let _ = sys::fs::close(dir);
+            return Some(Found::Directory);
+        }
+        let index = index_files.split(',').find_map(|name| {
+            let mut cname = [0u8; MAX_PATH + 1];
+            cname[..name.len()].copy_from_slice(name.as_bytes());
+            let (fd, stat) = open_beneath(dir, &cname)?;
+            Some((fd, stat, self::content_type(name)))
+        });
+        let _ = sys::fs::close(dir);
+        (fd, stat, content_type) = index?;
+    }
+    if stat.mode & sys::fs::S_IFMT != sys::fs::S_IFREG {
+        let _ = sys::fs::close(fd);
+        return None;
+    }
+    Some(Found::File(StaticFile {
+        fd,
+        stat,
+        content_type,
+    }))
+}
+
+/// Turn `path` into a relative, NUL-terminated path in `out`, resolving
+/// `.` and `..` segments. `false` if it would climb above the root, holds
+/// a NUL or is too long.
+fn normalize_path(path: &str, out: &mut [u8; MAX_PATH + 1]) -> bool {
+    if path.contains('\0') {
+        return false;
+    }
+    let mut len = 0;
+    for seg in path.as_bytes().split(|&b| b == b'/') {
+        match seg {
+            b"" | b"." => {}
+            b".." => {
+                if len == 0 {
+                    return false;
+                }
+                len = out[..len].iter().rposition(|&b| b == b'/').unwrap_or(0);
+            }
+            _ => {
+                let sep = (len > 0) as usize;
+                if len + sep + seg.len() > MAX_PATH {
+                    return false;
+                }
+                out[len] = b'/';
+                out[len + sep..len + sep + seg.len()].copy_from_slice(seg);
+                len += sep + seg.len();
+            }
+        }
+    }
+    if len == 0 {
+        out[0] = b'.';
+        len = 1;
+    }
+    out[len] = 0;
+    true
+}
+
+/// Open `path` (NUL-terminated) below `dir` without following anything out
+/// of it, and stat it.
+fn open_beneath(dir: usize, path: &[u8]) -> Option<(usize, sys::fs::Stat)> {
+    let fd = sys::fs::openat2(
+        dir,
+        path.as_ptr(),
+        sys::fs::O_RDONLY | sys::fs::O_CLOEXEC | sys::fs::O_NONBLOCK,
+        sys::fs::RESOLVE_BENEATH | sys::fs::RESOLVE_NO_MAGICLINKS,
+    )
+    .ok()?;
+    match sys::fs::fstat(fd) {
+        Ok(st) => Some((fd, st)),
+        Err(_) => {
+            let _ = sys::fs::close(fd);
+            None
+        }
+    }
+}
+
+/// Point a directory requested without its trailing slash at the slashed
+/// form, so relative links in its index resolve. The target is built from
+/// the resolved path, not the one sent: `//host/..` names the root here,
+/// but as a `Location` it would send the browser to `host`.
+pub(crate) fn redirect_to_dir(stream: &mut Stream, req: &Request) {
+    let mut rel = [0u8; MAX_PATH + 1];
+    if !normalize_path(req.path, &mut rel) {
+        serve_status(stream, req, b"404 Not Found", b"");
+        return;
+    }
+    let len = rel.iter().position(|&b| b == 0).unwrap_or(0);
+    let rel = if &rel[..len] == b"." {
+        &[][..]
+    } else {
+        &rel[..len]
+    };
+    let mut location = [0u8; 3 * MAX_PATH + 2];
+    location[0] = b'/';
+    let mut n = 1;
+    for &b in rel {
+        if b.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@/".contains(&b) {
+            location[n] = b;
+            n += 1;
+        } else {
+            location[n] = b'%';
+            location[n + 1] = b"0123456789ABCDEF"[(b >> 4) as usize];
+            location[n + 2] = b"0123456789ABCDEF"[(b & 15) as usize];
+            n += 3;
+        }
+    }
+    if n > 1 {
+        location[n] = b'/';
+        n += 1;
+    }
+    let _ = stream.send_all(b"HTTP/1.1 301 Moved Permanently\r\nLocation: ");
+    let _ = stream.send_all(&location[..n]);
+    if let Some(query) = req.query {
+        let _ = stream.send_all(b"?");
+        let _ = stream.send_all(query.as_bytes());
+    }
+    let _ = stream.send_all(b"\r\nContent-Length: 0\r\n");
+    let _ = stream.send_all(req.connection());
+}
+
+/// Answer with `file`: 304 for a satisfied `If-Modified-Since`, 206/416
+/// for a single byte `Range`, 200 otherwise. Closes the connection.
+pub(crate) fn serve_file(mut stream: Stream, req: &Request, file: &StaticFile) {
+    let st = &file.stat;
+    let mut modified = [0u8; 29];
+    format_http_date(st.mtime, &mut modified);
+    let not_modified = req
+        .header("If-Modified-Since")
+        .and_then(parse_http_date)
+        .is_some_and(|since| st.mtime <= since);
+    // A stale If-Range (anything but our Last-Modified) asks for the whole
+    // file instead.
+    let if_range_ok = req
+        .header("If-Range")
+        .is_none_or(|v| v.as_bytes() == modified);
+    let range = match req.header("Range") {
+        Some(v) if if_range_ok && !not_modified => parse_range(v, st.size),
+        _ => None,
+    };
+
+    let mut num = itoa::Buffer::new();
+    let mut head = [0u8; 512];
+    let mut n = 0;
+    let mut push = |part: &[u8]| {
+        head[n..n + part.len()].copy_from_slice(part);
+        n += part.len();
+    };
+    let (start, len) = match range {
+        _ if not_modified => {
+            push(b"HTTP/1.1 304 Not Modified\r\n");
+            (0, 0)
+        }
+        Some(Err(())) => {
+            push(b"HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */");
+            push(num.format(st.size).as_bytes());
+            push(b"\r\nContent-Length: 0\r\n");
+            (0, 0)
+        }
+        Some(Ok((first, last))) => {
+            push(b"HTTP/1.1 206 Partial Content\r\nContent-Range: bytes ");
+            push(num.format(first).as_bytes());
+            push(b"-");
+            push(num.format(last).as_bytes());
+            push(b"/");
+            push(num.format(st.size).as_bytes());
+            push(b"\r\n");
+            (first, last - first + 1)
+        }
+        None => {
+            push(b"HTTP/1.1 200 OK\r\n");
+            (0, st.size)
+        }
+    };
+    if !not_modified && range != Some(Err(())) {
+        push(b"Content-Type: ");
+        push(file.content_type.as_bytes());
+        push(b"\r\nContent-Length: ");
+        push(num.format(len).as_bytes());
+        push(b"\r\n");
+    }
+    push(b"Last-Modified: ");
+    push(&modified);
+    push(b"\r\nAccept-Ranges: bytes\r\nConnection: close\r\n\r\n");
+    if stream.send_all(&head[..n]).is_ok() && len > 0 && !req.is_head() {
+        let _ = stream.send_file(file.fd, start, len);
+    }
+    stream.close();
+}
+
+/// A single `bytes=` range of a `size`-byte file as inclusive offsets;
+/// `Some(Err)` if unsatisfiable. Anything else (other units, several
+/// ranges, bad syntax) is `None`, meaning the whole file is sent.
+fn parse_range(value: &str, size: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = value.strip_prefix("bytes=")?.trim();
+    if spec.contains(',') {
+        return None;
+    }
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = match (first.trim(), last.trim()) {
+        ("", suffix) => {
+            let n: u64 = suffix.parse().ok()?;
+            if n == 0 || size == 0 {
+                return Some(Err(()));
+            }
+            (size.saturating_sub(n), size - 1)
+        }
+        (first, last) => {
+            let first: u64 = first.parse().ok()?;
+            let last: u64 = match last {
+                "" => u64::MAX,
+                l => l.parse().ok()?,
+            };
+            if last < first {
+                return None;
+            }
+            if first >= size {
+                return Some(Err(()));
+            }
+            (first, core::cmp::min(last, size - 1))
+        }
+    };
+    Some(Ok((first, last)))
+}
+
+const WEEKDAYS: [&[u8; 3]; 7] = [b"Thu", b"Fri", b"Sat", b"Sun", b"Mon", b"Tue", b"Wed"];
+const MONTHS: [&[u8; 3]; 12] = [
+    b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun", b"Jul", b"Aug", b"Sep", b"Oct", b"Nov", b"Dec",
+];
+
+/// IMF-fixdate (`Sun, 06 Nov 1994 08:49:37 GMT`) for `secs` since the epoch.
+fn format_http_date(secs: i64, out: &mut [u8; 29]) {
+    let days = secs.div_euclid(86400);
+    let tod = secs.rem_euclid(86400);
+    let (y, m, d) = civil_from_days(days);
+    let two = |v: i64| [b'0' + (v / 10) as u8, b'0' + (v % 10) as u8];
+    let y = y.clamp(0, 9999);
+    out[..3].copy_from_slice(WEEKDAYS[days.rem_euclid(7) as usize]);
+    out[3..5].copy_from_slice(b", ");
+    out[5..7].copy_from_slice(&two(d));
+    out[7] = b' ';
+    out[8..11].copy_from_slice(MONTHS[m as usize - 1]);
+    out[11] = b' ';
+    out[12..14].copy_from_slice(&two(y / 100));
+    out[14..16].copy_from_slice(&two(y % 100));
+    out[16] = b' ';
+    out[17..19].copy_from_slice(&two(tod / 3600));
+    out[19] = b':';
+    out[20..22].copy_from_slice(&two(tod / 60 % 60));
+    out[22] = b':';
+    out[23..25].copy_from_slice(&two(tod % 60));
+    out[25..].copy_from_slice(b" GMT");
+}
+
+/// Seconds since the epoch for an IMF-fixdate; the obsolete RFC 850 and
+/// asctime forms are not accepted.
+fn parse_http_date(s: &str) -> Option<i64> {
+    let b = s.as_bytes();
+    if b.len() != 29 || &b[3..5] != b", " || &b[25..] != b" GMT" {
+        return None;
+    }
+    let num = |r: core::ops::Range<usize>| core::str::from_utf8(&b[r]).ok()?.parse::<i64>().ok();
+    let d = num(5..7)?;
+    let m = MONTHS.iter().position(|&name| name == &b[8..11])? as i64 + 1;
+    let y = num(12..16)?;
+    let (hh, mm, ss) = (num(17..19)?, num(20..22)?, num(23..25)?);
+    if !(1..=31).contains(&d) || hh > 23 || mm > 59 || ss > 60 {
+        return None;
+    }
+    Some(days_from_civil(y, m, d) * 86400 + hh * 3600 + mm * 60 + ss)
+}
+
+/// Gregorian date for a day count since 1970-01-01 (H. Hinnant's
+/// `civil_from_days`).
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + (m <= 2) as i64, m, d)
+}
+
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if m > 2 { m - 3 } else { m + 9 };
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
 }
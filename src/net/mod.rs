@@ -1,2 +1,5 @@
+pub mod addr;
 pub mod http;
+pub mod stream;
+pub mod tls;
 pub mod ws;
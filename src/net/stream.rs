@@ -0,0 +1,133 @@
+//! A client connection, plain or wrapped in TLS. HTTP and WebSocket code
+//! reads and writes through [`Stream`] and never sees which one it is.
+
+use super::tls::Session;
+use crate::sys::{self, SysResult};
+
+pub(crate) struct Stream {
+    pub(crate) fd: usize,
+    tls: Option<Session>,
+}
+
+impl Stream {
+    /// Longest [`Stream::handoff_state`].
+    pub(crate) const MAX_HANDOFF_STATE: usize = Session::STATE_LEN;
+
+    pub(crate) fn plain(fd: usize) -> Self {
+        Self { fd, tls: None }
+    }
+
+    pub(crate) fn tls(fd: usize, session: Session) -> Self {
+        Self {
+            fd,
+            tls: Some(session),
+        }
+    }
+
+    pub(crate) fn is_tls(&self) -> bool {
+        self.tls.is_some()
+    }
+
+    /// TLS state to pass along with the descriptor when another process
+    /// takes over the connection; empty for a plain one.
+    pub(crate) fn handoff_state(&mut self) -> &[u8] {
+        match &mut self.tls {
+            Some(tls) => tls.export(),
+            None => &[],
+        }
+    }
+
+    /// The receiving end of [`Stream::handoff_state`].
+    pub(crate) fn adopt(fd: usize, state: &[u8]) -> Result<Self, &'static str> {
+        if state.is_empty() {
+            return Ok(Self::plain(fd));
+        }
+        Ok(Self::tls(fd, Session::import(state)?))
+    }
+
+    /// Like `recv(2)`. Over TLS this may also fail with `EAGAIN` on a
+    /// blocking socket while a record is only partly received.
+    pub(crate) fn recv(&mut self, buf: &mut [u8]) -> SysResult<usize> {
+        match &mut self.tls {
+            Some(tls) => tls.recv(self.fd, buf),
+            None => sys::net::recv(self.fd, buf),
+        }
+    }
+
+    pub(crate) fn send_all(&mut self, buf: &[u8]) -> SysResult<()> {
+        match &mut self.tls {
+            Some(tls) => tls.send_all(self.fd, buf),
+            None => sys::net::send_all(self.fd, buf),
+        }
+    }
+
+    /// Encode `buf` as [`Stream::send_all`] would put it on the wire, but
+    /// hand the bytes to `sink` instead of the socket.
+    pub(crate) fn seal(
+        &mut self,
+        buf: &[u8],
+        sink: &mut dyn FnMut(&[u8]) -> SysResult<()>,
+    ) -> SysResult<()> {
+        match &mut self.tls {
+            Some(tls) => tls.seal(buf, sink),
+            None => sink(buf),
+        }
+    }
+
+    /// Send `len` bytes of the file `file` starting at `offset`: with
+    /// `sendfile(2)` on plain sockets, through the record layer over TLS.
+    pub(crate) fn send_file(&mut self, file: usize, mut offset: u64, len: u64) -> SysResult<()> {
+        let end = offset + len;
+        while offset < end {
+            let want = core::cmp::min(end - offset, 1 << 20) as usize;
+            let n = match &mut self.tls {
+                None => sys::fs::sendfile(self.fd, file, &mut offset, want)?,
+                Some(tls) => {
+                    let mut buf = [0u8; 16 * 1024];
+                    let want = core::cmp::min(want, buf.len());
+                    let n = sys::fs::pread(file, &mut buf[..want], offset)?;
+                    tls.send_all(self.fd, &buf[..n])?;
+                    offset += n as u64;
+                    n
+                }
+            };
+            if n == 0 {
+                // The file shrank underneath us.
+                return Err(-5);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether [`Stream::recv`] has data without the socket becoming
+    /// readable: epoll cannot see what TLS already pulled in.
+    pub(crate) fn buffered(&mut self) -> bool {
+        self.tls.as_mut().is_some_and(|tls| tls.buffered())
+    }
+
+    /// End the connection in both directions, even if the descriptor is
+    /// shared with another process.
+    pub(crate) fn shutdown(&mut self) {
+        if let Some(tls) = &mut self.tls {
+            tls.close_notify(self.fd);
+        }
+        let _ = sys::net::shutdown(self.fd, sys::net::SHUT_RDWR);
+    }
+
+    pub(crate) fn close(mut self) {
+        if let Some(tls) = &mut self.tls {
+            tls.close_notify(self.fd);
+        }
+        self.abandon();
+    }
+
+    /// Release this process's copy without telling the peer anything; for
+    /// a parent that handed the connection to a worker, and for workers
+    /// dropping the connections they inherited.
+    pub(crate) fn abandon(self) {
+        if let Some(tls) = self.tls {
+            tls.free();
+        }
+        let _ = sys::fs::close(self.fd);
+    }
+}
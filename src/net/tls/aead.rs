@@ -0,0 +1,223 @@
+//! ChaCha20-Poly1305 AEAD (RFC 8439), the record cipher of
+//! `TLS_CHACHA20_POLY1305_SHA256`.
+
+pub(crate) const TAG_LEN: usize = 16;
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+fn le32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn chacha_block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
+    let mut init = [0u32; 16];
+    init[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for i in 0..8 {
+        init[4 + i] = le32(&key[4 * i..]);
+    }
+    init[12] = counter;
+    for i in 0..3 {
+        init[13 + i] = le32(&nonce[4 * i..]);
+    }
+    let mut s = init;
+    for _ in 0..10 {
+        quarter_round(&mut s, 0, 4, 8, 12);
+        quarter_round(&mut s, 1, 5, 9, 13);
+        quarter_round(&mut s, 2, 6, 10, 14);
+        quarter_round(&mut s, 3, 7, 11, 15);
+        quarter_round(&mut s, 0, 5, 10, 15);
+        quarter_round(&mut s, 1, 6, 11, 12);
+        quarter_round(&mut s, 2, 7, 8, 13);
+        quarter_round(&mut s, 3, 4, 9, 14);
+    }
+    let mut out = [0u8; 64];
+    for (i, chunk) in out.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&s[i].wrapping_add(init[i]).to_le_bytes());
+    }
+    out
+}
+
+fn chacha_xor(key: &[u8; 32], nonce: &[u8; 12], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let ks = chacha_block(key, 1 + i as u32, nonce);
+        for (b, k) in chunk.iter_mut().zip(ks) {
+            *b ^= k;
+        }
+    }
+}
+
+/// Poly1305 with 26-bit limbs.
+struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+}
+
+impl Poly1305 {
+    fn new(key: &[u8; 32]) -> Self {
+        Self {
+            r: [
+                le32(&key[0..]) & 0x3ffffff,
+                (le32(&key[3..]) >> 2) & 0x3ffff03,
+                (le32(&key[6..]) >> 4) & 0x3ffc0ff,
+                (le32(&key[9..]) >> 6) & 0x3f03fff,
+                (le32(&key[12..]) >> 8) & 0x00fffff,
+            ],
+            h: [0; 5],
+            pad: [
+                le32(&key[16..]),
+                le32(&key[20..]),
+                le32(&key[24..]),
+                le32(&key[28..]),
+            ],
+        }
+    }
+
+    /// Absorb `data` zero-padded to a multiple of 16 bytes, as the AEAD
+    /// construction does for both the associated data and the ciphertext.
+    fn update_padded(&mut self, data: &[u8]) {
+        for chunk in data.chunks(16) {
+            let mut block = [0u8; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            self.block(&block);
+        }
+    }
+
+    fn block(&mut self, m: &[u8; 16]) {
+        let [r0, r1, r2, r3, r4] = self.r.map(|x| x as u64);
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+        let h = &mut self.h;
+        let h0 = (h[0] + (le32(&m[0..]) & 0x3ffffff)) as u64;
+        let h1 = (h[1] + ((le32(&m[3..]) >> 2) & 0x3ffffff)) as u64;
+        let h2 = (h[2] + ((le32(&m[6..]) >> 4) & 0x3ffffff)) as u64;
+        let h3 = (h[3] + ((le32(&m[9..]) >> 6) & 0x3ffffff)) as u64;
+        let h4 = (h[4] + ((le32(&m[12..]) >> 8) | (1 << 24))) as u64;
+
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+
+        d1 += d0 >> 26;
+        h[0] = (d0 & 0x3ffffff) as u32;
+        d2 += d1 >> 26;
+        h[1] = (d1 & 0x3ffffff) as u32;
+        d3 += d2 >> 26;
+        h[2] = (d2 & 0x3ffffff) as u32;
+        d4 += d3 >> 26;
+        h[3] = (d3 & 0x3ffffff) as u32;
+        h[4] = (d4 & 0x3ffffff) as u32;
+        h[0] += ((d4 >> 26) * 5) as u32;
+        h[1] += h[0] >> 26;
+        h[0] &= 0x3ffffff;
+    }
+
+    fn finish(self) -> [u8; 16] {
+        let mut h = self.h;
+        let mut c;
+        c = h[1] >> 26;
+        h[1] &= 0x3ffffff;
+        h[2] += c;
+        c = h[2] >> 26;
+        h[2] &= 0x3ffffff;
+        h[3] += c;
+        c = h[3] >> 26;
+        h[3] &= 0x3ffffff;
+        h[4] += c;
+        c = h[4] >> 26;
+        h[4] &= 0x3ffffff;
+        h[0] += c * 5;
+        c = h[0] >> 26;
+        h[0] &= 0x3ffffff;
+        h[1] += c;
+
+        // g = h + 5 - 2^130; use it instead of h when it does not go negative.
+        let mut g = [0u32; 5];
+        g[0] = h[0] + 5;
+        c = g[0] >> 26;
+        g[0] &= 0x3ffffff;
+        g[1] = h[1] + c;
+        c = g[1] >> 26;
+        g[1] &= 0x3ffffff;
+        g[2] = h[2] + c;
+        c = g[2] >> 26;
+        g[2] &= 0x3ffffff;
+        g[3] = h[3] + c;
+        c = g[3] >> 26;
+        g[3] &= 0x3ffffff;
+        g[4] = h[4].wrapping_add(c).wrapping_sub(1 << 26);
+        let use_g = (g[4] >> 31).wrapping_sub(1);
+        for i in 0..5 {
+            h[i] = (h[i] & !use_g) | (g[i] & use_g);
+        }
+
+        let words = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8),
+        ];
+        let mut out = [0u8; 16];
+        let mut carry = 0u64;
+        for i in 0..4 {
+            let f = words[i] as u64 + self.pad[i] as u64 + carry;
+            out[4 * i..4 * i + 4].copy_from_slice(&(f as u32).to_le_bytes());
+            carry = f >> 32;
+        }
+        out
+    }
+}
+
+fn tag(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> [u8; 16] {
+    let block = chacha_block(key, 0, nonce);
+    let mut otk = [0u8; 32];
+    otk.copy_from_slice(&block[..32]);
+    let mut mac = Poly1305::new(&otk);
+    mac.update_padded(aad);
+    mac.update_padded(ciphertext);
+    let mut lens = [0u8; 16];
+    lens[..8].copy_from_slice(&(aad.len() as u64).to_le_bytes());
+    lens[8..].copy_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    mac.block(&lens);
+    mac.finish()
+}
+
+/// Encrypt `data` in place and return its tag.
+pub(crate) fn seal(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], data: &mut [u8]) -> [u8; 16] {
+    chacha_xor(key, nonce, data);
+    tag(key, nonce, aad, data)
+}
+
+/// Check `expected` and decrypt `data` in place; `false` (and `data`
+/// untouched) on a forged or corrupted record.
+pub(crate) fn open(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    data: &mut [u8],
+    expected: &[u8],
+) -> bool {
+    if !ct_eq(&tag(key, nonce, aad, data), expected) {
+        return false;
+    }
+    chacha_xor(key, nonce, data);
+    true
+}
+
+/// Constant-time equality of two byte strings of public length.
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,194 @@
+//! 256-bit modular arithmetic in Montgomery form (R = 2^256) for odd
+//! moduli: the P-256 field and group order and the Ed25519 group order.
+//! Limbs are little-endian `u64`s; every operation runs in constant time.
+
+pub(crate) type Limbs = [u64; 4];
+
+pub(crate) struct Modulus {
+    m: Limbs,
+    /// `-m^-1 mod 2^64`.
+    m0inv: u64,
+    /// `R^2 mod m`, to move values into Montgomery form.
+    r2: Limbs,
+}
+
+impl Modulus {
+    pub(crate) fn new(m: Limbs) -> Self {
+        // Newton iteration doubles the correct low bits of m[0]^-1 each step.
+        let mut inv = 1u64;
+        for _ in 0..6 {
+            inv = inv.wrapping_mul(2u64.wrapping_sub(m[0].wrapping_mul(inv)));
+        }
+        let mut md = Self {
+            m,
+            m0inv: inv.wrapping_neg(),
+            r2: [0; 4],
+        };
+        let mut r2 = [1, 0, 0, 0];
+        for _ in 0..512 {
+            r2 = md.add(&r2, &r2);
+        }
+        md.r2 = r2;
+        md
+    }
+
+    pub(crate) fn add(&self, a: &Limbs, b: &Limbs) -> Limbs {
+        let (sum, carry) = add_carry(a, b);
+        self.reduce_once(sum, carry)
+    }
+
+    pub(crate) fn sub(&self, a: &Limbs, b: &Limbs) -> Limbs {
+        let (diff, borrow) = sub_borrow(a, b);
+        let (fixed, _) = add_carry(&diff, &self.m);
+        select(borrow, &fixed, &diff)
+    }
+
+    /// `a * b * R^-1 mod m`, for `a * b < m * R`.
+    pub(crate) fn mont_mul(&self, a: &Limbs, b: &Limbs) -> Limbs {
+        let mut t = [0u64; 6];
+        for &bi in b {
+            let mut c = 0u128;
+            for j in 0..4 {
+                let v = t[j] as u128 + a[j] as u128 * bi as u128 + c;
+                t[j] = v as u64;
+                c = v >> 64;
+            }
+            let v = t[4] as u128 + c;
+            t[4] = v as u64;
+            t[5] = (v >> 64) as u64;
+
+            let q = t[0].wrapping_mul(self.m0inv);
+            let mut c = (t[0] as u128 + q as u128 * self.m[0] as u128) >> 64;
+            for j in 1..4 {
+                let v = t[j] as u128 + q as u128 * self.m[j] as u128 + c;
+                t[j - 1] = v as u64;
+                c = v >> 64;
+            }
+            let v = t[4] as u128 + c;
+            t[3] = v as u64;
+            t[4] = t[5] + (v >> 64) as u64;
+        }
+        self.reduce_once([t[0], t[1], t[2], t[3]], t[4])
+    }
+
+    pub(crate) fn to_mont(&self, a: &Limbs) -> Limbs {
+        self.mont_mul(a, &self.r2)
+    }
+
+    pub(crate) fn out_of_mont(&self, a: &Limbs) -> Limbs {
+        self.mont_mul(a, &[1, 0, 0, 0])
+    }
+
+    /// Plain `a * b mod m`.
+    pub(crate) fn mul(&self, a: &Limbs, b: &Limbs) -> Limbs {
+        self.mont_mul(&self.mont_mul(a, b), &self.r2)
+    }
+
+    /// `a mod m` for any 256-bit `a`.
+    pub(crate) fn reduce(&self, a: &Limbs) -> Limbs {
+        self.out_of_mont(&self.to_mont(a))
+    }
+
+    /// A 512-bit big-endian value modulo `m`.
+    pub(crate) fn reduce_wide_be(&self, bytes: &[u8; 64]) -> Limbs {
+        let hi = from_be(&bytes[..32]);
+        let lo = from_be(&bytes[32..]);
+        // hi * 2^256 + lo: mont_mul(hi, R^2) = hi * R.
+        self.add(&self.to_mont(&hi), &self.reduce(&lo))
+    }
+
+    /// `a^(m-2)`, the inverse of `a` for prime `m`; `a` in Montgomery form
+    /// and so is the result.
+    pub(crate) fn mont_inv(&self, a: &Limbs) -> Limbs {
+        let (e, _) = sub_borrow(&self.m, &[2, 0, 0, 0]);
+        let mut r = self.to_mont(&[1, 0, 0, 0]);
+        for i in (0..256).rev() {
+            r = self.mont_mul(&r, &r);
+            // The exponent is public, so branching on it is fine.
+            if (e[i / 64] >> (i % 64)) & 1 == 1 {
+                r = self.mont_mul(&r, a);
+            }
+        }
+        r
+    }
+
+    /// Subtract `m` once if `carry:value` is at least `m`.
+    fn reduce_once(&self, value: Limbs, carry: u64) -> Limbs {
+        let (diff, borrow) = sub_borrow(&value, &self.m);
+        // Keep `value` only if it was below m: a borrow with no carry.
+        select(borrow & !carry & 1, &value, &diff)
+    }
+}
+
+fn add_carry(a: &Limbs, b: &Limbs) -> (Limbs, u64) {
+    let mut out = [0u64; 4];
+    let mut c = 0u128;
+    for i in 0..4 {
+        let v = a[i] as u128 + b[i] as u128 + c;
+        out[i] = v as u64;
+        c = v >> 64;
+    }
+    (out, c as u64)
+}
+
+pub(crate) fn sub_borrow(a: &Limbs, b: &Limbs) -> (Limbs, u64) {
+    let mut out = [0u64; 4];
+    let mut borrow = 0u64;
+    for i in 0..4 {
+        let (d1, b1) = a[i].overflowing_sub(b[i]);
+        let (d2, b2) = d1.overflowing_sub(borrow);
+        out[i] = d2;
+        borrow = (b1 | b2) as u64;
+    }
+    (out, borrow)
+}
+
+/// `if bit { a } else { b }` without branching; `bit` is 0 or 1.
+pub(crate) fn select(bit: u64, a: &Limbs, b: &Limbs) -> Limbs {
+    let mask = bit.wrapping_neg();
+    let mut out = [0u64; 4];
+    for i in 0..4 {
+        out[i] = (a[i] & mask) | (b[i] & !mask);
+    }
+    out
+}
+
+pub(crate) fn is_zero(a: &Limbs) -> bool {
+    (a[0] | a[1] | a[2] | a[3]) == 0
+}
+
+pub(crate) fn from_be(b: &[u8]) -> Limbs {
+    let mut out = [0u64; 4];
+    for (i, limb) in out.iter_mut().enumerate() {
+        let mut w = [0u8; 8];
+        w.copy_from_slice(&b[24 - 8 * i..32 - 8 * i]);
+        *limb = u64::from_be_bytes(w);
+    }
+    out
+}
+
+pub(crate) fn to_be(a: &Limbs) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    for i in 0..4 {
+        out[24 - 8 * i..32 - 8 * i].copy_from_slice(&a[i].to_be_bytes());
+    }
+    out
+}
+
+pub(crate) fn from_le(b: &[u8]) -> Limbs {
+    let mut out = [0u64; 4];
+    for (i, limb) in out.iter_mut().enumerate() {
+        let mut w = [0u8; 8];
+        w.copy_from_slice(&b[8 * i..8 * i + 8]);
+        *limb = u64::from_le_bytes(w);
+    }
+    out
+}
+
+pub(crate) fn to_le(a: &Limbs) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    for i in 0..4 {
+        out[8 * i..8 * i + 8].copy_from_slice(&a[i].to_le_bytes());
+    }
+    out
+}
@@ -0,0 +1,217 @@
+//! Server certificate chain and private key, read from PEM.
+//!
+//! Keys may be Ed25519 (`PRIVATE KEY`, PKCS#8) or ECDSA P-256 (`PRIVATE KEY`
+//! or `EC PRIVATE KEY`, SEC1). Certificates are sent as they are; the only
+//! check is that the leaf carries the key's public half.
+
+use super::curve25519::Ed25519Key;
+use super::p256::{self, P256Key};
+use crate::runtime::{allocator, util};
+
+/// Room for the encoded chain; the whole server flight must fit in one
+/// handshake buffer.
+const MAX_CHAIN: usize = 12 * 1024;
+
+const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_PRIME256V1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+
+/// TLS `SignatureScheme` values.
+pub(crate) const ED25519: u16 = 0x0807;
+pub(crate) const ECDSA_SECP256R1_SHA256: u16 = 0x0403;
+
+/// Longest signature [`Credentials::sign`] produces.
+pub(crate) const MAX_SIG_LEN: usize = p256::MAX_SIG_LEN;
+
+#[derive(Copy, Clone)]
+enum SigningKey {
+    Ed25519(Ed25519Key),
+    P256(P256Key),
+}
+
+#[derive(Copy, Clone)]
+pub(crate) struct Credentials {
+    /// Body of the `certificate_list` of a TLS 1.3 Certificate message:
+    /// each DER certificate with its 3-byte length and no extensions.
+    pub(crate) chain: &'static [u8],
+    key: SigningKey,
+}
+
+impl Credentials {
+    /// Parse a PEM certificate chain (leaf first) and a PEM private key.
+    pub(crate) fn parse(cert_pem: &str, key_pem: &str) -> Result<Self, &'static str> {
+        let key = parse_key(key_pem)?;
+
+        let ptr = allocator::page_alloc(MAX_CHAIN).map_err(|_| "out of memory")?;
+        let chain = util::ptr_to_mut_slice(ptr, MAX_CHAIN);
+        let mut len = 0;
+        let mut leaf: &[u8] = &[];
+        let mut rest = cert_pem;
+        while let Some((label, body, after)) = next_block(rest) {
+            rest = after;
+            if label != "CERTIFICATE" {
+                continue;
+            }
+            if len + 3 + 2 > MAX_CHAIN {
+                return Err("certificate chain too large");
+            }
+            let n = base64_decode(body, &mut chain[len + 3..MAX_CHAIN - 2])
+                .ok_or("bad certificate PEM")?;
+            chain[len..len + 3].copy_from_slice(&(n as u32).to_be_bytes()[1..]);
+            chain[len + 3 + n..len + 5 + n].copy_from_slice(&[0, 0]);
+            if leaf.is_empty() {
+                leaf = util::ptr_to_slice(ptr, len + 3 + n);
+            }
+            len += 5 + n;
+        }
+        if len == 0 {
+            return Err("no CERTIFICATE block");
+        }
+        let public: &[u8] = match &key {
+            SigningKey::Ed25519(k) => &k.public,
+            SigningKey::P256(k) => &k.public,
+        };
+        if !leaf.windows(public.len()).any(|w| w == public) {
+            return Err("certificate does not match the private key");
+        }
+        Ok(Self {
+            chain: util::ptr_to_slice(ptr, len),
+            key,
+        })
+    }
+
+    pub(crate) fn scheme(&self) -> u16 {
+        match self.key {
+            SigningKey::Ed25519(_) => ED25519,
+            SigningKey::P256(_) => ECDSA_SECP256R1_SHA256,
+        }
+    }
+
+    /// Sign `msg` with the scheme from [`Credentials::scheme`], returning the
+    /// signature length.
+    pub(crate) fn sign(&self, msg: &[u8], out: &mut [u8; MAX_SIG_LEN]) -> usize {
+        match &self.key {
+            SigningKey::Ed25519(k) => {
+                out[..64].copy_from_slice(&k.sign(msg));
+                64
+            }
+            SigningKey::P256(k) => k.sign(msg, out),
+        }
+    }
+}
+
+fn parse_key(pem: &str) -> Result<SigningKey, &'static str> {
+    let mut der = [0u8; 1024];
+    let mut rest = pem;
+    while let Some((label, body, after)) = next_block(rest) {
+        rest = after;
+        let key: fn(&[u8]) -> Option<SigningKey> = match label {
+            "PRIVATE KEY" => parse_pkcs8,
+            "EC PRIVATE KEY" => |d| parse_sec1(d, true),
+            "ENCRYPTED PRIVATE KEY" => return Err("encrypted private keys are not supported"),
+            _ => continue,
+        };
+        let n = base64_decode(body, &mut der).ok_or("bad private key PEM")?;
+        return key(&der[..n]).ok_or("unsupported private key (need Ed25519 or P-256)");
+    }
+    Err("no PRIVATE KEY block")
+}
+
+/// PKCS#8 `PrivateKeyInfo` (RFC 5208, RFC 8410).
+fn parse_pkcs8(der: &[u8]) -> Option<SigningKey> {
+    let mut info = Der(Der(der).read(0x30)?);
+    info.read(0x02)?;
+    let mut alg = Der(info.read(0x30)?);
+    let oid = alg.read(0x06)?;
+    let private = info.read(0x04)?;
+    if oid == OID_ED25519 {
+        let seed = Der(private).read(0x04)?;
+        return Some(SigningKey::Ed25519(Ed25519Key::from_seed(
+            seed.try_into().ok()?,
+        )));
+    }
+    if oid == OID_EC_PUBLIC_KEY && alg.read(0x06)? == OID_PRIME256V1 {
+        return parse_sec1(private, false);
+    }
+    None
+}
+
+/// SEC1 `ECPrivateKey` (RFC 5915). Standalone keys must name P-256; inside
+/// PKCS#8 the curve was already checked.
+fn parse_sec1(der: &[u8], need_curve: bool) -> Option<SigningKey> {
+    let mut key = Der(Der(der).read(0x30)?);
+    key.read(0x02)?;
+    let d = key.read(0x04)?;
+    if need_curve && Der(key.read(0xa0)?).read(0x06)? != OID_PRIME256V1 {
+        return None;
+    }
+    if d.len() > 32 {
+        return None;
+    }
+    let mut secret = [0u8; 32];
+    secret[32 - d.len()..].copy_from_slice(d);
+    P256Key::from_secret(&secret).map(SigningKey::P256)
+}
+
+/// Cursor over DER TLVs.
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+    /// Contents of the next element, which must have tag `tag`.
+    fn read(&mut self, tag: u8) -> Option<&'a [u8]> {
+        let d = self.0;
+        if d.len() < 2 || d[0] != tag {
+            return None;
+        }
+        let (len, hdr) = match d[1] {
+            n if n < 0x80 => (n as usize, 2),
+            0x81 => (*d.get(2)? as usize, 3),
+            0x82 => (((*d.get(2)? as usize) << 8) | *d.get(3)? as usize, 4),
+            _ => return None,
+        };
+        let body = d.get(hdr..hdr + len)?;
+        self.0 = &d[hdr + len..];
+        Some(body)
+    }
+}
+
+/// Next `-----BEGIN label-----` block: its label, base64 body and the text
+/// after its END line.
+fn next_block(text: &str) -> Option<(&str, &str, &str)> {
+    let start = text.find("-----BEGIN ")? + "-----BEGIN ".len();
+    let text = &text[start..];
+    let label_end = text.find("-----")?;
+    let label = &text[..label_end];
+    let body = &text[label_end + 5..];
+    let end = body.find("-----END ")?;
+    let after = &body[end + "-----END ".len()..];
+    let after = &after[after.find("-----")? + 5..];
+    Some((label, &body[..end], after))
+}
+
+/// Decode base64, skipping whitespace; `None` on bad input or overflow.
+fn base64_decode(src: &str, dst: &mut [u8]) -> Option<usize> {
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut n = 0;
+    for c in src.bytes() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            b' ' | b'\t' | b'\r' | b'\n' => continue,
+            _ => return None,
+        };
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            *dst.get_mut(n)? = (acc >> bits) as u8;
+            n += 1;
+        }
+    }
+    Some(n)
+}
@@ -0,0 +1,351 @@
+//! Arithmetic modulo 2^255 - 19 with X25519 key exchange (RFC 7748) and
+//! Ed25519 signing (RFC 8032). Secret-dependent choices use masks, never
+//! branches or table lookups.
+
+use super::bignum::{self, Limbs, Modulus};
+use super::sha2::Sha512;
+
+const MASK: u64 = (1 << 51) - 1;
+
+/// Field element in five 51-bit limbs, kept below 2^52 after each
+/// operation.
+#[derive(Copy, Clone)]
+struct Fe([u64; 5]);
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    fn from_bytes(b: &[u8; 32]) -> Fe {
+        let le = |i: usize| {
+            let mut w = [0u8; 8];
+            w.copy_from_slice(&b[i..i + 8]);
+            u64::from_le_bytes(w)
+        };
+        Fe([
+            le(0) & MASK,
+            (le(6) >> 3) & MASK,
+            (le(12) >> 6) & MASK,
+            (le(19) >> 1) & MASK,
+            (le(24) >> 12) & MASK,
+        ])
+    }
+
+    fn to_bytes(self) -> [u8; 32] {
+        let mut t = self.carry().0;
+        // q = 1 iff t >= p, found by propagating t + 19 through the limbs.
+        let mut q = (t[0] + 19) >> 51;
+        for limb in &t[1..] {
+            q = (limb + q) >> 51;
+        }
+        t[0] += 19 * q;
+        for i in 0..4 {
+            t[i + 1] += t[i] >> 51;
+            t[i] &= MASK;
+        }
+        t[4] &= MASK;
+        let words = [
+            t[0] | (t[1] << 51),
+            (t[1] >> 13) | (t[2] << 38),
+            (t[2] >> 26) | (t[3] << 25),
+            (t[3] >> 39) | (t[4] << 12),
+        ];
+        let mut out = [0u8; 32];
+        for (chunk, w) in out.chunks_exact_mut(8).zip(words) {
+            chunk.copy_from_slice(&w.to_le_bytes());
+        }
+        out
+    }
+
+    fn carry(self) -> Fe {
+        let mut l = self.0;
+        for i in 0..4 {
+            l[i + 1] += l[i] >> 51;
+            l[i] &= MASK;
+        }
+        l[0] += 19 * (l[4] >> 51);
+        l[4] &= MASK;
+        Fe(l)
+    }
+
+    fn add(&self, b: &Fe) -> Fe {
+        let mut l = self.0;
+        for (x, y) in l.iter_mut().zip(b.0) {
+            *x += y;
+        }
+        Fe(l).carry()
+    }
+
+    fn sub(&self, b: &Fe) -> Fe {
+        // Add 4p first so no limb goes negative.
+        const FOUR_P: [u64; 5] = [
+            0x1fffffffffffb4,
+            0x1ffffffffffffc,
+            0x1ffffffffffffc,
+            0x1ffffffffffffc,
+            0x1ffffffffffffc,
+        ];
+        let mut l = [0u64; 5];
+        for i in 0..5 {
+            l[i] = self.0[i] + FOUR_P[i] - b.0[i];
+        }
+        Fe(l).carry()
+    }
+
+    fn mul(&self, b: &Fe) -> Fe {
+        let [a0, a1, a2, a3, a4] = self.0.map(|x| x as u128);
+        let [b0, b1, b2, b3, b4] = b.0.map(|x| x as u128);
+        let (b1_19, b2_19, b3_19, b4_19) = (b1 * 19, b2 * 19, b3 * 19, b4 * 19);
+        let r0 = a0 * b0 + a1 * b4_19 + a2 * b3_19 + a3 * b2_19 + a4 * b1_19;
+        let mut r1 = a0 * b1 + a1 * b0 + a2 * b4_19 + a3 * b3_19 + a4 * b2_19;
+        let mut r2 = a0 * b2 + a1 * b1 + a2 * b0 + a3 * b4_19 + a4 * b3_19;
+        let mut r3 = a0 * b3 + a1 * b2 + a2 * b1 + a3 * b0 + a4 * b4_19;
+        let mut r4 = a0 * b4 + a1 * b3 + a2 * b2 + a3 * b1 + a4 * b0;
+        r1 += r0 >> 51;
+        r2 += r1 >> 51;
+        r3 += r2 >> 51;
+        r4 += r3 >> 51;
+        let l0 = (r0 & MASK as u128) + 19 * (r4 >> 51);
+        let l1 = (r1 & MASK as u128) + (l0 >> 51);
+        Fe([
+            (l0 & MASK as u128) as u64,
+            l1 as u64,
+            (r2 & MASK as u128) as u64,
+            (r3 & MASK as u128) as u64,
+            (r4 & MASK as u128) as u64,
+        ])
+    }
+
+    fn square(&self) -> Fe {
+        self.mul(self)
+    }
+
+    fn small(n: u64) -> Fe {
+        Fe([n, 0, 0, 0, 0])
+    }
+
+    /// `self^(p-2)`; p - 2 = 2^255 - 21 has bits 254..5 set and ends in
+    /// 01011.
+    fn invert(&self) -> Fe {
+        let mut r = Fe::ONE;
+        for i in (0..255).rev() {
+            r = r.square();
+            if i >= 5 || (0b01011 >> i) & 1 == 1 {
+                r = r.mul(self);
+            }
+        }
+        r
+    }
+
+    /// Swap `a` and `b` when `swap` is 1.
+    fn cswap(a: &mut Fe, b: &mut Fe, swap: u64) {
+        let mask = swap.wrapping_neg();
+        for i in 0..5 {
+            let t = mask & (a.0[i] ^ b.0[i]);
+            a.0[i] ^= t;
+            b.0[i] ^= t;
+        }
+    }
+}
+
+/// X25519 of the scalar `k` and the u-coordinate `u`.
+pub(crate) fn x25519(k: &[u8; 32], u: &[u8; 32]) -> [u8; 32] {
+    let mut k = *k;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+    let x1 = Fe::from_bytes(u);
+    let a24 = Fe::small(121665);
+    let (mut x2, mut z2, mut x3, mut z3) = (Fe::ONE, Fe::ZERO, x1, Fe::ONE);
+    let mut swap = 0u64;
+    for t in (0..255).rev() {
+        let bit = ((k[t / 8] >> (t % 8)) & 1) as u64;
+        swap ^= bit;
+        Fe::cswap(&mut x2, &mut x3, swap);
+        Fe::cswap(&mut z2, &mut z3, swap);
+        swap = bit;
+
+        let a = x2.add(&z2);
+        let aa = a.square();
+        let b = x2.sub(&z2);
+        let bb = b.square();
+        let e = aa.sub(&bb);
+        let c = x3.add(&z3);
+        let d = x3.sub(&z3);
+        let da = d.mul(&a);
+        let cb = c.mul(&b);
+        x3 = da.add(&cb).square();
+        z3 = x1.mul(&da.sub(&cb).square());
+        x2 = aa.mul(&bb);
+        z2 = e.mul(&aa.add(&a24.mul(&e)));
+    }
+    Fe::cswap(&mut x2, &mut x3, swap);
+    Fe::cswap(&mut z2, &mut z3, swap);
+    x2.mul(&z2.invert()).to_bytes()
+}
+
+/// X25519 public key for the secret `k`.
+pub(crate) fn x25519_base(k: &[u8; 32]) -> [u8; 32] {
+    let mut nine = [0u8; 32];
+    nine[0] = 9;
+    x25519(k, &nine)
+}
+
+/// Point on edwards25519 in extended coordinates (X:Y:Z:T), x = X/Z,
+/// y = Y/Z, xy = T/Z.
+#[derive(Copy, Clone)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+/// Base point coordinates, little-endian.
+const BASE_X: [u8; 32] = [
+    0x1a, 0xd5, 0x25, 0x8f, 0x60, 0x2d, 0x56, 0xc9, 0xb2, 0xa7, 0x25, 0x95, 0x60, 0xc7, 0x2c, 0x69,
+    0x5c, 0xdc, 0xd6, 0xfd, 0x31, 0xe2, 0xa4, 0xc0, 0xfe, 0x53, 0x6e, 0xcd, 0xd3, 0x36, 0x69, 0x21,
+];
+const BASE_Y: [u8; 32] = [
+    0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+];
+/// 2d, little-endian, with d = -121665/121666.
+const D2: [u8; 32] = [
+    0x59, 0xf1, 0xb2, 0x26, 0x94, 0x9b, 0xd6, 0xeb, 0x56, 0xb1, 0x83, 0x82, 0x9a, 0x14, 0xe0, 0x00,
+    0x30, 0xd1, 0xf3, 0xee, 0xf2, 0x80, 0x8e, 0x19, 0xe7, 0xfc, 0xdf, 0x56, 0xdc, 0xd9, 0x06, 0x24,
+];
+/// Group order L = 2^252 + 27742317777372353535851937790883648493.
+const ORDER: Limbs = [
+    0x5812631a5cf5d3ed,
+    0x14def9dea2f79cd6,
+    0x0000000000000000,
+    0x1000000000000000,
+];
+
+impl Point {
+    const IDENTITY: Point = Point {
+        x: Fe::ZERO,
+        y: Fe::ONE,
+        z: Fe::ONE,
+        t: Fe::ZERO,
+    };
+
+    fn base() -> Point {
+        let x = Fe::from_bytes(&BASE_X);
+        let y = Fe::from_bytes(&BASE_Y);
+        Point {
+            x,
+            y,
+            z: Fe::ONE,
+            t: x.mul(&y),
+        }
+    }
+
+    /// Unified addition (add-2008-hwcd-3), also valid for doubling.
+    fn add(&self, q: &Point, d2: &Fe) -> Point {
+        let a = self.y.sub(&self.x).mul(&q.y.sub(&q.x));
+        let b = self.y.add(&self.x).mul(&q.y.add(&q.x));
+        let c = self.t.mul(d2).mul(&q.t);
+        let d = self.z.add(&self.z).mul(&q.z);
+        let e = b.sub(&a);
+        let f = d.sub(&c);
+        let g = d.add(&c);
+        let h = b.add(&a);
+        Point {
+            x: e.mul(&f),
+            y: g.mul(&h),
+            z: f.mul(&g),
+            t: e.mul(&h),
+        }
+    }
+
+    /// `[k]B` for the base point B with a fixed double-and-add sequence.
+    fn mul_base(k: &[u8; 32]) -> Point {
+        let d2 = Fe::from_bytes(&D2);
+        let b = Point::base();
+        let mut r = Point::IDENTITY;
+        for i in (0..256).rev() {
+            r = r.add(&r, &d2);
+            let sum = r.add(&b, &d2);
+            let bit = ((k[i / 8] >> (i % 8)) & 1) as u64;
+            for (dst, src) in [
+                (&mut r.x, sum.x),
+                (&mut r.y, sum.y),
+                (&mut r.z, sum.z),
+                (&mut r.t, sum.t),
+            ] {
+                let mut s = src;
+                Fe::cswap(dst, &mut s, bit);
+            }
+        }
+        r
+    }
+
+    fn encode(&self) -> [u8; 32] {
+        let zinv = self.z.invert();
+        let x = self.x.mul(&zinv).to_bytes();
+        let mut out = self.y.mul(&zinv).to_bytes();
+        out[31] |= (x[0] & 1) << 7;
+        out
+    }
+}
+
+/// Ed25519 keys derived from a 32-byte seed.
+#[derive(Copy, Clone)]
+pub(crate) struct Ed25519Key {
+    scalar: [u8; 32],
+    prefix: [u8; 32],
+    pub(crate) public: [u8; 32],
+}
+
+impl Ed25519Key {
+    pub(crate) fn from_seed(seed: &[u8; 32]) -> Self {
+        let mut h = Sha512::new();
+        h.update(seed);
+        let h = h.finish();
+        let mut scalar = [0u8; 32];
+        scalar.copy_from_slice(&h[..32]);
+        scalar[0] &= 248;
+        scalar[31] &= 127;
+        scalar[31] |= 64;
+        let mut prefix = [0u8; 32];
+        prefix.copy_from_slice(&h[32..]);
+        Self {
+            scalar,
+            prefix,
+            public: Point::mul_base(&scalar).encode(),
+        }
+    }
+
+    pub(crate) fn sign(&self, msg: &[u8]) -> [u8; 64] {
+        let l = Modulus::new(ORDER);
+        let mut h = Sha512::new();
+        h.update(&self.prefix);
+        h.update(msg);
+        let r = reduce_le(&l, &h.finish());
+        let big_r = Point::mul_base(&bignum::to_le(&r)).encode();
+
+        let mut h = Sha512::new();
+        h.update(&big_r);
+        h.update(&self.public);
+        h.update(msg);
+        let k = reduce_le(&l, &h.finish());
+        let a = bignum::from_le(&self.scalar);
+        let s = l.add(&r, &l.mul(&k, &a));
+
+        let mut sig = [0u8; 64];
+        sig[..32].copy_from_slice(&big_r);
+        sig[32..].copy_from_slice(&bignum::to_le(&s));
+        sig
+    }
+}
+
+/// A 64-byte little-endian hash modulo L.
+fn reduce_le(l: &Modulus, h: &[u8; 64]) -> Limbs {
+    let mut be = [0u8; 64];
+    for (d, s) in be.iter_mut().zip(h.iter().rev()) {
+        *d = *s;
+    }
+    l.reduce_wide_be(&be)
+}
@@ -0,0 +1,67 @@
+//! HMAC-SHA256 and the TLS 1.3 HKDF key schedule helpers (RFC 5869,
+//! RFC 8446 section 7.1).
+
+use super::sha2::Sha256;
+
+/// HMAC-SHA256 over the concatenation of `parts`.
+pub(crate) fn hmac(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut k = [0u8; 64];
+    if key.len() > 64 {
+        k[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        k[..key.len()].copy_from_slice(key);
+    }
+    let mut pad = [0u8; 64];
+    for (p, b) in pad.iter_mut().zip(k) {
+        *p = b ^ 0x36;
+    }
+    let mut inner = Sha256::new();
+    inner.update(&pad);
+    for part in parts {
+        inner.update(part);
+    }
+    let inner = inner.finish();
+    for (p, b) in pad.iter_mut().zip(k) {
+        *p = b ^ 0x5c;
+    }
+    let mut outer = Sha256::new();
+    outer.update(&pad);
+    outer.update(&inner);
+    outer.finish()
+}
+
+pub(crate) fn extract(salt: &[u8], ikm: &[u8]) -> [u8; 32] {
+    hmac(salt, &[ikm])
+}
+
+/// `HKDF-Expand-Label(secret, label, context, out.len())`; `out` is at most
+/// 255 hash lengths.
+pub(crate) fn expand_label(secret: &[u8; 32], label: &[u8], context: &[u8], out: &mut [u8]) {
+    let mut info = [0u8; 2 + 1 + 6 + 32 + 1 + 64];
+    let mut n = 0;
+    for part in [
+        &(out.len() as u16).to_be_bytes()[..],
+        &[(6 + label.len()) as u8],
+        b"tls13 ",
+        label,
+        &[context.len() as u8],
+        context,
+    ] {
+        info[n..n + part.len()].copy_from_slice(part);
+        n += part.len();
+    }
+    let mut t = [0u8; 32];
+    let mut t_len = 0;
+    for (i, chunk) in out.chunks_mut(32).enumerate() {
+        t = hmac(secret, &[&t[..t_len], &info[..n], &[i as u8 + 1]]);
+        t_len = 32;
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+}
+
+/// `Derive-Secret(secret, label, messages)` given the transcript hash.
+pub(crate) fn derive_secret(secret: &[u8; 32], label: &[u8], hash: &[u8; 32]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    expand_label(secret, label, hash, &mut out);
+    out
+}
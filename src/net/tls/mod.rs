@@ -0,0 +1,20 @@
+//! Optional TLS 1.3 server layer, between `sys::net` and `net::http` /
+//! `net::ws` (reached through [`crate::net::stream::Stream`]).
+//!
+//! Everything is implemented here without libc: SHA-2, HKDF,
+//! ChaCha20-Poly1305, X25519, Ed25519 and ECDSA P-256 signing, plus the
+//! handshake and record layer. Only what a TLS 1.3 server needs for
+//! `TLS_CHACHA20_POLY1305_SHA256` over X25519 is present.
+
+mod aead;
+mod bignum;
+mod credentials;
+mod curve25519;
+mod hkdf;
+mod p256;
+mod session;
+mod sha2;
+
+pub(crate) use credentials::Credentials;
+pub(crate) use session::{PROTOCOL_ERROR, Session};
+pub(crate) use sha2::{Sha256, Sha512};
@@ -0,0 +1,237 @@
+//! ECDSA signing over NIST P-256 with SHA-256 (FIPS 186-4), nonces derived
+//! deterministically per RFC 6979. Point arithmetic uses the complete
+//! projective formulas of Renes, Costello and Batina (a = -3), so there
+//! are no exceptional cases to branch on.
+
+use super::bignum::{self, Limbs, Modulus};
+use super::hkdf::hmac;
+use super::sha2::Sha256;
+
+const P: Limbs = [
+    0xffffffffffffffff,
+    0x00000000ffffffff,
+    0x0000000000000000,
+    0xffffffff00000001,
+];
+const N: Limbs = [
+    0xf3b9cac2fc632551,
+    0xbce6faada7179e84,
+    0xffffffffffffffff,
+    0xffffffff00000000,
+];
+const B: Limbs = [
+    0x3bce3c3e27d2604b,
+    0x651d06b0cc53b0f6,
+    0xb3ebbd55769886bc,
+    0x5ac635d8aa3a93e7,
+];
+const GX: Limbs = [
+    0xf4a13945d898c296,
+    0x77037d812deb33a0,
+    0xf8bce6e563a440f2,
+    0x6b17d1f2e12c4247,
+];
+const GY: Limbs = [
+    0xcbb6406837bf51f5,
+    0x2bce33576b315ece,
+    0x8ee7eb4a7c0f9e16,
+    0x4fe342e2fe1a7f9b,
+];
+
+/// Projective point (X:Y:Z) with coordinates in Montgomery form.
+#[derive(Copy, Clone)]
+struct Point {
+    x: Limbs,
+    y: Limbs,
+    z: Limbs,
+}
+
+struct Curve {
+    f: Modulus,
+    b: Limbs,
+}
+
+impl Curve {
+    fn new() -> Self {
+        let f = Modulus::new(P);
+        let b = f.to_mont(&B);
+        Self { f, b }
+    }
+
+    fn generator(&self) -> Point {
+        Point {
+            x: self.f.to_mont(&GX),
+            y: self.f.to_mont(&GY),
+            z: self.f.to_mont(&[1, 0, 0, 0]),
+        }
+    }
+
+    /// Complete addition for a = -3 (RCB 2015, algorithm 4); also doubles.
+    fn add(&self, p: &Point, q: &Point) -> Point {
+        let f = &self.f;
+        let mul = |a: &Limbs, b: &Limbs| f.mont_mul(a, b);
+        let add = |a: &Limbs, b: &Limbs| f.add(a, b);
+        let sub = |a: &Limbs, b: &Limbs| f.sub(a, b);
+        let (x1, y1, z1) = (&p.x, &p.y, &p.z);
+        let (x2, y2, z2) = (&q.x, &q.y, &q.z);
+
+        let mut t0 = mul(x1, x2);
+        let mut t1 = mul(y1, y2);
+        let mut t2 = mul(z1, z2);
+        let mut t3 = add(x1, y1);
+        let mut t4 = add(x2, y2);
+        t3 = mul(&t3, &t4);
+        t4 = add(&t0, &t1);
+        t3 = sub(&t3, &t4);
+        t4 = add(y1, z1);
+        let mut x3 = add(y2, z2);
+        t4 = mul(&t4, &x3);
+        x3 = add(&t1, &t2);
+        t4 = sub(&t4, &x3);
+        x3 = add(x1, z1);
+        let mut y3 = add(x2, z2);
+        x3 = mul(&x3, &y3);
+        y3 = add(&t0, &t2);
+        y3 = sub(&x3, &y3);
+        let mut z3 = mul(&self.b, &t2);
+        x3 = sub(&y3, &z3);
+        z3 = add(&x3, &x3);
+        x3 = add(&x3, &z3);
+        z3 = sub(&t1, &x3);
+        x3 = add(&t1, &x3);
+        y3 = mul(&self.b, &y3);
+        t1 = add(&t2, &t2);
+        t2 = add(&t1, &t2);
+        y3 = sub(&y3, &t2);
+        y3 = sub(&y3, &t0);
+        t1 = add(&y3, &y3);
+        y3 = add(&t1, &y3);
+        t1 = add(&t0, &t0);
+        t0 = add(&t1, &t0);
+        t0 = sub(&t0, &t2);
+        t1 = mul(&t4, &y3);
+        t2 = mul(&t0, &y3);
+        y3 = mul(&x3, &z3);
+        y3 = add(&y3, &t2);
+        x3 = mul(&t3, &x3);
+        x3 = sub(&x3, &t1);
+        z3 = mul(&t4, &z3);
+        t1 = mul(&t3, &t0);
+        z3 = add(&z3, &t1);
+        Point {
+            x: x3,
+            y: y3,
+            z: z3,
+        }
+    }
+
+    /// `[k]G` with a fixed double-and-add sequence.
+    fn mul_base(&self, k: &Limbs) -> Point {
+        let g = self.generator();
+        let mut r = Point {
+            x: [0; 4],
+            y: self.f.to_mont(&[1, 0, 0, 0]),
+            z: [0; 4],
+        };
+        for i in (0..256).rev() {
+            r = self.add(&r, &r);
+            let sum = self.add(&r, &g);
+            let bit = (k[i / 64] >> (i % 64)) & 1;
+            r = Point {
+                x: bignum::select(bit, &sum.x, &r.x),
+                y: bignum::select(bit, &sum.y, &r.y),
+                z: bignum::select(bit, &sum.z, &r.z),
+            };
+        }
+        r
+    }
+
+    /// Affine coordinates (out of Montgomery form).
+    fn affine(&self, p: &Point) -> (Limbs, Limbs) {
+        let zinv = self.f.mont_inv(&p.z);
+        (
+            self.f.out_of_mont(&self.f.mont_mul(&p.x, &zinv)),
+            self.f.out_of_mont(&self.f.mont_mul(&p.y, &zinv)),
+        )
+    }
+}
+
+/// A P-256 private key and its uncompressed public point.
+#[derive(Copy, Clone)]
+pub(crate) struct P256Key {
+    d: Limbs,
+    /// `04 || X || Y`.
+    pub(crate) public: [u8; 65],
+}
+
+/// Longest DER-encoded signature: two 33-byte INTEGERs in a SEQUENCE.
+pub(crate) const MAX_SIG_LEN: usize = 72;
+
+impl P256Key {
+    /// Key from the big-endian secret scalar; `None` unless 0 < d < n.
+    pub(crate) fn from_secret(secret: &[u8; 32]) -> Option<Self> {
+        let d = bignum::from_be(secret);
+        let (_, below_n) = bignum::sub_borrow(&d, &N);
+        if bignum::is_zero(&d) || below_n == 0 {
+            return None;
+        }
+        let curve = Curve::new();
+        let (x, y) = curve.affine(&curve.mul_base(&d));
+        let mut public = [4u8; 65];
+        public[1..33].copy_from_slice(&bignum::to_be(&x));
+        public[33..].copy_from_slice(&bignum::to_be(&y));
+        Some(Self { d, public })
+    }
+
+    /// Sign SHA-256(`msg`), writing the DER `Ecdsa-Sig-Value` into `out`
+    /// and returning its length.
+    pub(crate) fn sign(&self, msg: &[u8], out: &mut [u8; MAX_SIG_LEN]) -> usize {
+        let n = Modulus::new(N);
+        let curve = Curve::new();
+        let z = n.reduce(&bignum::from_be(&Sha256::digest(msg)));
+        let x = bignum::to_be(&self.d);
+        let h1 = bignum::to_be(&z);
+
+        // RFC 6979 section 3.2 with HMAC-SHA256.
+        let mut v = [1u8; 32];
+        let mut k = hmac(&[0u8; 32], &[&v, &[0], &x, &h1]);
+        v = hmac(&k, &[&v]);
+        k = hmac(&k, &[&v, &[1], &x, &h1]);
+        v = hmac(&k, &[&v]);
+        loop {
+            v = hmac(&k, &[&v]);
+            let nonce = bignum::from_be(&v);
+            let (_, below_n) = bignum::sub_borrow(&nonce, &N);
+            if !bignum::is_zero(&nonce) && below_n == 1 {
+                let (rx, _) = curve.affine(&curve.mul_base(&nonce));
+                let r = n.reduce(&rx);
+                // s = k^-1 (z + r d)
+                let kinv = n.out_of_mont(&n.mont_inv(&n.to_mont(&nonce)));
+                let s = n.mul(&kinv, &n.add(&z, &n.mul(&r, &self.d)));
+                if !bignum::is_zero(&r) && !bignum::is_zero(&s) {
+                    return encode_sig(&r, &s, out);
+                }
+            }
+            k = hmac(&k, &[&v, &[0]]);
+            v = hmac(&k, &[&v]);
+        }
+    }
+}
+
+fn encode_sig(r: &Limbs, s: &Limbs, out: &mut [u8; MAX_SIG_LEN]) -> usize {
+    let mut n = 2;
+    for v in [r, s] {
+        let bytes = bignum::to_be(v);
+        let skip = bytes.iter().take_while(|&&b| b == 0).count().min(31);
+        let digits = &bytes[skip..];
+        let pad = (digits[0] >> 7) as usize;
+        out[n] = 0x02;
+        out[n + 1] = (digits.len() + pad) as u8;
+        out[n + 2] = 0;
+        out[n + 2 + pad..n + 2 + pad + digits.len()].copy_from_slice(digits);
+        n += 2 + pad + digits.len();
+    }
+    out[0] = 0x30;
+    out[1] = (n - 2) as u8;
+    n
+}
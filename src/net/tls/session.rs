@@ -0,0 +1,781 @@
+//! Server side of one TLS 1.3 connection (RFC 8446): the handshake and
+//! record protection, with one cipher suite
+//! (`TLS_CHACHA20_POLY1305_SHA256`) and one key exchange group (X25519).
+//! There is no resumption, 0-RTT or client authentication.
+//!
+//! [`Session::recv`] reads the socket at most once per call and returns
+//! `EAGAIN` while a record is incomplete, so the parent can drive
+//! handshakes from its epoll loop and a forked worker never blocks on a
+//! half-received record.
+
+use super::aead::{self, TAG_LEN};
+use super::credentials::{Credentials, MAX_SIG_LEN};
+use super::curve25519::{x25519, x25519_base};
+use super::hkdf::{self, derive_secret, expand_label};
+use super::sha2::Sha256;
+use crate::runtime::util;
+use crate::sys::{self, SysResult};
+
+/// Returned by [`Session::recv`] when no complete record is available yet.
+pub(crate) const EAGAIN: isize = -11;
+
+/// Returned (as an errno) when the peer breaks the protocol or the session
+/// has failed: `EPROTO`.
+pub(crate) const PROTOCOL_ERROR: isize = -71;
+
+const CHANGE_CIPHER_SPEC: u8 = 20;
+const ALERT: u8 = 21;
+const HANDSHAKE: u8 = 22;
+const APPLICATION_DATA: u8 = 23;
+
+const CLIENT_HELLO: u8 = 1;
+const SERVER_HELLO: u8 = 2;
+const ENCRYPTED_EXTENSIONS: u8 = 8;
+const CERTIFICATE: u8 = 11;
+const CERTIFICATE_VERIFY: u8 = 15;
+const FINISHED: u8 = 20;
+const KEY_UPDATE: u8 = 24;
+const MESSAGE_HASH: u8 = 254;
+
+const EXT_SUPPORTED_GROUPS: u16 = 10;
+const EXT_SIGNATURE_ALGORITHMS: u16 = 13;
+const EXT_SUPPORTED_VERSIONS: u16 = 43;
+const EXT_KEY_SHARE: u16 = 51;
+
+const TLS13: u16 = 0x0304;
+const CHACHA20_POLY1305_SHA256: u16 = 0x1303;
+const X25519: u16 = 0x001d;
+
+/// `ServerHello.random` marking a HelloRetryRequest: SHA-256 of
+/// "HelloRetryRequest".
+const HRR_RANDOM: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+// Alert descriptions.
+const CLOSE_NOTIFY: u8 = 0;
+const UNEXPECTED_MESSAGE: u8 = 10;
+const BAD_RECORD_MAC: u8 = 20;
+const RECORD_OVERFLOW: u8 = 22;
+const HANDSHAKE_FAILURE: u8 = 40;
+const ILLEGAL_PARAMETER: u8 = 47;
+const DECODE_ERROR: u8 = 50;
+const DECRYPT_ERROR: u8 = 51;
+const PROTOCOL_VERSION: u8 = 70;
+const INTERNAL_ERROR: u8 = 80;
+const USER_CANCELED: u8 = 90;
+/// Failure that must not be answered with an alert (the peer sent one, or
+/// the socket is gone).
+const NO_ALERT: u8 = 0xff;
+
+const MAX_PLAINTEXT: usize = 16 * 1024;
+const MAX_CIPHERTEXT: usize = MAX_PLAINTEXT + 256;
+const RAW_LEN: usize = 5 + MAX_CIPHERTEXT;
+const OUT_LEN: usize = 5 + MAX_PLAINTEXT + 1 + TAG_LEN;
+/// Handshake reassembly, and the server flight on the way out.
+const HS_LEN: usize = 16 * 1024;
+
+// Session phases.
+const EXPECT_HELLO: u8 = 0;
+const EXPECT_FINISHED: u8 = 1;
+const CONNECTED: u8 = 2;
+const CLOSED: u8 = 3;
+const FAILED: u8 = 4;
+
+/// Traffic key, IV and record sequence number for one direction.
+#[derive(Copy, Clone)]
+struct Keys {
+    key: [u8; 32],
+    iv: [u8; 12],
+    seq: u64,
+}
+
+impl Keys {
+    fn from_secret(secret: &[u8; 32]) -> Self {
+        let mut keys = Keys {
+            key: [0; 32],
+            iv: [0; 12],
+            seq: 0,
+        };
+        expand_label(secret, b"key", b"", &mut keys.key);
+        expand_label(secret, b"iv", b"", &mut keys.iv);
+        keys
+    }
+
+    fn next_nonce(&mut self) -> [u8; 12] {
+        let mut nonce = self.iv;
+        for (n, s) in nonce[4..].iter_mut().zip(self.seq.to_be_bytes()) {
+            *n ^= s;
+        }
+        self.seq += 1;
+        nonce
+    }
+}
+
+/// Per-connection state. It lives in its own zero-filled mapping so it can
+/// be returned to the kernel when the connection ends; the parent's arena
+/// never reclaims memory.
+struct State {
+    creds: Credentials,
+    phase: u8,
+    /// A HelloRetryRequest was sent.
+    retried: bool,
+    /// A ChangeCipherSpec was sent for middlebox compatibility.
+    sent_ccs: bool,
+    sent_close_notify: bool,
+    /// The peer asked for a KeyUpdate; ours goes out ahead of the next
+    /// application data, as RFC 8446 allows, so it never overtakes records
+    /// a caller has sealed but not yet sent.
+    key_update_due: bool,
+    transcript: Sha256,
+    read: Keys,
+    read_on: bool,
+    write: Keys,
+    write_on: bool,
+    /// Client Finished verify_data we expect.
+    client_finished: [u8; 32],
+    client_secret: [u8; 32],
+    server_secret: [u8; 32],
+    /// Received bytes; `head..len` not yet processed.
+    raw: [u8; RAW_LEN],
+    head: usize,
+    len: usize,
+    /// Decrypted application data not yet returned, inside `raw`.
+    plain_off: usize,
+    plain_len: usize,
+    hs: [u8; HS_LEN],
+    hs_len: usize,
+    out: [u8; OUT_LEN],
+}
+
+pub(crate) struct Session {
+    state: *mut State,
+}
+
+impl Session {
+    /// Size of what [`Session::export`] returns.
+    pub(crate) const STATE_LEN: usize = core::mem::size_of::<State>();
+
+    pub(crate) fn new(creds: &Credentials) -> Result<Self, &'static str> {
+        let ptr = sys::mmap::mmap_alloc(core::mem::size_of::<State>()).map_err(|_| "mmap")?;
+        let mut s = Self {
+            state: ptr as *mut State,
+        };
+        s.st().creds = *creds;
+        s.st().transcript = Sha256::new();
+        Ok(s)
+    }
+
+    fn st(&mut self) -> &mut State {
+        &mut util::ptr_to_mut_slice_of(self.state, 1)[0]
+    }
+
+    /// The whole session state, for another process to carry on the
+    /// connection with [`Session::import`]. Only meaningful in a process
+    /// forked from the one that loaded the credentials: the certificate
+    /// chain is referenced, not copied.
+    pub(crate) fn export(&mut self) -> &[u8] {
+        util::ptr_to_slice(self.state as *mut u8, Self::STATE_LEN)
+    }
+
+    /// Take over a connection from the state [`Session::export`] returned.
+    pub(crate) fn import(state: &[u8]) -> Result<Self, &'static str> {
+        if state.len() != Self::STATE_LEN {
+            return Err("tls state size");
+        }
+        let ptr = sys::mmap::mmap_alloc(Self::STATE_LEN).map_err(|_| "mmap")?;
+        util::ptr_to_mut_slice(ptr, Self::STATE_LEN).copy_from_slice(state);
+        Ok(Self {
+            state: ptr as *mut State,
+        })
+    }
+
+    /// Return the state mapping to the kernel. The socket is not touched.
+    pub(crate) fn free(self) {
+        let _ = sys::mmap::munmap_free(self.state as *mut u8, core::mem::size_of::<State>());
+    }
+
+    /// Read application data, first completing the handshake. `Ok(0)`
+    /// after the peer's close_notify or hang-up.
+    pub(crate) fn recv(&mut self, fd: usize, buf: &mut [u8]) -> SysResult<usize> {
+        let st = self.st();
+        let creds = st.creds;
+        let mut read = false;
+        loop {
+            if st.plain_len > 0 {
+                let n = core::cmp::min(buf.len(), st.plain_len);
+                buf[..n].copy_from_slice(&st.raw[st.plain_off..st.plain_off + n]);
+                st.plain_off += n;
+                st.plain_len -= n;
+                return Ok(n);
+            }
+            match st.phase {
+                CLOSED => return Ok(0),
+                FAILED => return Err(PROTOCOL_ERROR),
+                _ => {}
+            }
+            match st.next_record(fd, &creds) {
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(alert) => {
+                    if alert != NO_ALERT {
+                        let _ = st.send_alert(fd, alert);
+                    }
+                    st.phase = FAILED;
+                    return Err(PROTOCOL_ERROR);
+                }
+            }
+            // One socket read per call, so a partial record never blocks a
+            // caller that was told the socket is readable.
+            if read {
+                return Err(EAGAIN);
+            }
+            read = true;
+            let len = st.len;
+            let n = sys::net::recv(fd, &mut st.raw[len..])?;
+            if n == 0 {
+                st.phase = CLOSED;
+                return Ok(0);
+            }
+            st.len += n;
+        }
+    }
+
+    /// Whether [`Session::recv`] can return data without the socket
+    /// becoming readable again.
+    pub(crate) fn buffered(&mut self) -> bool {
+        let st = self.st();
+        if st.plain_len > 0 {
+            return true;
+        }
+        let pending = &st.raw[st.head..st.len];
+        pending.len() >= 5
+            && pending.len() >= 5 + u16::from_be_bytes([pending[3], pending[4]]) as usize
+    }
+
+    /// Send `buf` as application data records.
+    pub(crate) fn send_all(&mut self, fd: usize, buf: &[u8]) -> SysResult<()> {
+        self.seal(buf, &mut |record| sys::net::send_all(fd, record))
+    }
+
+    /// Protect `buf` as application data records and hand them to `sink`,
+    /// for a caller that queues its output instead of blocking on the
+    /// socket.
+    pub(crate) fn seal(
+        &mut self,
+        buf: &[u8],
+        sink: &mut dyn FnMut(&[u8]) -> SysResult<()>,
+    ) -> SysResult<()> {
+        let st = self.st();
+        if st.phase != CONNECTED && st.phase != CLOSED {
+            return Err(PROTOCOL_ERROR);
+        }
+        if st.key_update_due {
+            st.key_update_due = false;
+            let update = [KEY_UPDATE, 0, 0, 1, 0];
+            seal_records(&mut st.write, &mut st.out, HANDSHAKE, &update, sink)?;
+            let mut next = [0u8; 32];
+            expand_label(&st.server_secret, b"traffic upd", b"", &mut next);
+            st.server_secret = next;
+            st.write = Keys::from_secret(&next);
+        }
+        seal_records(&mut st.write, &mut st.out, APPLICATION_DATA, buf, sink)
+    }
+
+    /// Tell the peer no more data follows, if the handshake completed.
+    pub(crate) fn close_notify(&mut self, fd: usize) {
+        let st = self.st();
+        if (st.phase == CONNECTED || st.phase == CLOSED) && !st.sent_close_notify {
+            st.sent_close_notify = true;
+            let _ = send_encrypted(fd, &mut st.write, &mut st.out, ALERT, &[1, CLOSE_NOTIFY]);
+        }
+    }
+}
+
+impl State {
+    /// Process one complete record if there is one: `Ok(false)` when more
+    /// bytes are needed, `Err(alert)` on a protocol violation.
+    fn next_record(&mut self, fd: usize, creds: &Credentials) -> Result<bool, u8> {
+        if self.head > 0 {
+            self.raw.copy_within(self.head..self.len, 0);
+            self.len -= self.head;
+            self.head = 0;
+        }
+        if self.len < 5 {
+            return Ok(false);
+        }
+        let typ = self.raw[0];
+        // Reject garbage (plain HTTP, say) without waiting for a length's
+        // worth of it.
+        if !(CHANGE_CIPHER_SPEC..=APPLICATION_DATA).contains(&typ) || self.raw[1] != 3 {
+            return Err(UNEXPECTED_MESSAGE);
+        }
+        let rlen = u16::from_be_bytes([self.raw[3], self.raw[4]]) as usize;
+        if rlen > MAX_CIPHERTEXT {
+            return Err(RECORD_OVERFLOW);
+        }
+        if self.len < 5 + rlen {
+            return Ok(false);
+        }
+        self.head = 5 + rlen;
+        match typ {
+            // Middlebox-compatibility noise during the handshake.
+            CHANGE_CIPHER_SPEC if self.phase != CONNECTED && self.raw[5..5 + rlen] == [1] => {
+                Ok(true)
+            }
+            HANDSHAKE if !self.read_on => {
+                let mut msg = [0u8; MAX_CIPHERTEXT];
+                msg[..rlen].copy_from_slice(&self.raw[5..5 + rlen]);
+                self.handshake_data(fd, creds, &msg[..rlen])?;
+                Ok(true)
+            }
+            ALERT if !self.read_on => self.alert(5, rlen),
+            APPLICATION_DATA if self.read_on => {
+                if rlen < TAG_LEN + 1 {
+                    return Err(DECODE_ERROR);
+                }
+                let mut aad = [0u8; 5];
+                aad.copy_from_slice(&self.raw[..5]);
+                let nonce = self.read.next_nonce();
+                let (body, tag) = self.raw[5..5 + rlen].split_at_mut(rlen - TAG_LEN);
+                if !aead::open(&self.read.key, &nonce, &aad, body, tag) {
+                    return Err(BAD_RECORD_MAC);
+                }
+                // Strip zero padding; the last non-zero byte is the real type.
+                let Some(end) = body.iter().rposition(|&b| b != 0) else {
+                    return Err(UNEXPECTED_MESSAGE);
+                };
+                if end > MAX_PLAINTEXT {
+                    return Err(RECORD_OVERFLOW);
+                }
+                match body[end] {
+                    APPLICATION_DATA if self.phase == CONNECTED => {
+                        self.plain_off = 5;
+                        self.plain_len = end;
+                        Ok(true)
+                    }
+                    HANDSHAKE => {
+                        let mut msg = [0u8; MAX_PLAINTEXT];
+                        msg[..end].copy_from_slice(&self.raw[5..5 + end]);
+                        self.handshake_data(fd, creds, &msg[..end])?;
+                        Ok(true)
+                    }
+                    ALERT => self.alert(5, end),
+                    _ => Err(UNEXPECTED_MESSAGE),
+                }
+            }
+            _ => Err(UNEXPECTED_MESSAGE),
+        }
+    }
+
+    fn alert(&mut self, off: usize, len: usize) -> Result<bool, u8> {
+        if len != 2 {
+            return Err(DECODE_ERROR);
+        }
+        match self.raw[off + 1] {
+            CLOSE_NOTIFY => {
+                self.phase = CLOSED;
+                Ok(true)
+            }
+            USER_CANCELED => Ok(true),
+            _ => Err(NO_ALERT),
+        }
+    }
+
+    /// Reassemble handshake messages and act on each complete one.
+    fn handshake_data(&mut self, fd: usize, creds: &Credentials, data: &[u8]) -> Result<(), u8> {
+        if self.hs_len + data.len() > HS_LEN {
+            return Err(INTERNAL_ERROR);
+        }
+        self.hs[self.hs_len..self.hs_len + data.len()].copy_from_slice(data);
+        self.hs_len += data.len();
+        while self.hs_len >= 4 {
+            let mlen = 4 + u32::from_be_bytes([0, self.hs[1], self.hs[2], self.hs[3]]) as usize;
+            if mlen > self.hs_len {
+                break;
+            }
+            let mut msg = [0u8; HS_LEN];
+            msg[..mlen].copy_from_slice(&self.hs[..mlen]);
+            self.hs.copy_within(mlen..self.hs_len, 0);
+            self.hs_len -= mlen;
+            // Keys change after every message we accept here, so none may
+            // share a record with whatever follows.
+            if self.hs_len != 0 {
+                return Err(UNEXPECTED_MESSAGE);
+            }
+            let msg = &msg[..mlen];
+            match (self.phase, msg[0]) {
+                (EXPECT_HELLO, CLIENT_HELLO) => self.client_hello(fd, creds, msg)?,
+                (EXPECT_FINISHED, FINISHED) => {
+                    if !aead::ct_eq(&msg[4..], &self.client_finished) {
+                        return Err(DECRYPT_ERROR);
+                    }
+                    self.read = Keys::from_secret(&self.client_secret);
+                    self.phase = CONNECTED;
+                }
+                (CONNECTED, KEY_UPDATE) => self.key_update(msg)?,
+                _ => return Err(UNEXPECTED_MESSAGE),
+            }
+        }
+        Ok(())
+    }
+
+    fn client_hello(&mut self, fd: usize, creds: &Credentials, msg: &[u8]) -> Result<(), u8> {
+        let hello = ClientHello::parse(&msg[4..], creds.scheme()).ok_or(DECODE_ERROR)?;
+        if !hello.tls13 {
+            return Err(PROTOCOL_VERSION);
+        }
+        if !hello.chacha || !hello.signature_ok {
+            return Err(HANDSHAKE_FAILURE);
+        }
+        if hello.compression != [0] {
+            return Err(ILLEGAL_PARAMETER);
+        }
+        let Some(share) = hello.share else {
+            if self.retried || !hello.x25519_offered {
+                return Err(if self.retried {
+                    ILLEGAL_PARAMETER
+                } else {
+                    HANDSHAKE_FAILURE
+                });
+            }
+            return self.hello_retry(fd, msg, hello.session_id);
+        };
+
+        let mut secret = [0u8; 32];
+        let mut random = [0u8; 32];
+        if sys::random::getrandom(&mut secret).is_err()
+            || sys::random::getrandom(&mut random).is_err()
+        {
+            return Err(INTERNAL_ERROR);
+        }
+        let shared = x25519(&secret, share);
+        if shared == [0; 32] {
+            return Err(ILLEGAL_PARAMETER);
+        }
+        self.transcript.update(msg);
+
+        let mut sh = Writer::new(&mut self.hs);
+        server_hello(&mut sh, &random, hello.session_id, &x25519_base(&secret));
+        let n = sh.len;
+        self.transcript.update(&self.hs[..n]);
+        send_plain(fd, &mut self.out, HANDSHAKE, &self.hs[..n])?;
+        if !hello.session_id.is_empty() && !self.sent_ccs {
+            send_plain(fd, &mut self.out, CHANGE_CIPHER_SPEC, &[1])?;
+        }
+
+        // Key schedule up to the handshake traffic secrets.
+        let zeros = [0u8; 32];
+        let empty_hash = Sha256::digest(b"");
+        let early = hkdf::extract(&zeros, &zeros);
+        let hs_secret = hkdf::extract(&derive_secret(&early, b"derived", &empty_hash), &shared);
+        let hash = self.transcript.finish();
+        let c_hs = derive_secret(&hs_secret, b"c hs traffic", &hash);
+        let s_hs = derive_secret(&hs_secret, b"s hs traffic", &hash);
+        let master = hkdf::extract(&derive_secret(&hs_secret, b"derived", &empty_hash), &zeros);
+        self.read = Keys::from_secret(&c_hs);
+        self.read_on = true;
+        self.write = Keys::from_secret(&s_hs);
+        self.write_on = true;
+
+        // EncryptedExtensions, Certificate, CertificateVerify, Finished.
+        let mut w = Writer::new(&mut self.hs);
+        w.push(&[ENCRYPTED_EXTENSIONS, 0, 0, 2, 0, 0]);
+        let cert_start = w.len;
+        w.push(&[CERTIFICATE]);
+        w.u24(1 + 3 + creds.chain.len());
+        w.push(&[0]);
+        w.u24(creds.chain.len());
+        w.push(creds.chain);
+        let n = w.len;
+        self.transcript.update(&self.hs[..n]);
+
+        let mut content = [0x20u8; 64 + 34 + 32];
+        content[64..98].copy_from_slice(b"TLS 1.3, server CertificateVerify\0");
+        content[98..].copy_from_slice(&self.transcript.finish());
+        let mut sig = [0u8; MAX_SIG_LEN];
+        let sig_len = creds.sign(&content, &mut sig);
+        let mut w = Writer {
+            buf: &mut self.hs,
+            len: n,
+        };
+        let cv_start = w.len;
+        w.push(&[CERTIFICATE_VERIFY]);
+        w.u24(4 + sig_len);
+        w.push(&creds.scheme().to_be_bytes());
+        w.push(&(sig_len as u16).to_be_bytes());
+        w.push(&sig[..sig_len]);
+        let n = w.len;
+        self.transcript.update(&self.hs[cv_start..n]);
+
+        let mut finished_key = [0u8; 32];
+        expand_label(&s_hs, b"finished", b"", &mut finished_key);
+        let verify = hkdf::hmac(&finished_key, &[&self.transcript.finish()]);
+        let mut w = Writer {
+            buf: &mut self.hs,
+            len: n,
+        };
+        let fin_start = w.len;
+        w.push(&[FINISHED, 0, 0, 32]);
+        w.push(&verify);
+        let n = w.len;
+        if n > HS_LEN || cert_start == 0 {
+            return Err(INTERNAL_ERROR);
+        }
+        self.transcript.update(&self.hs[fin_start..n]);
+        send_encrypted(fd, &mut self.write, &mut self.out, HANDSHAKE, &self.hs[..n])
+            .map_err(|_| NO_ALERT)?;
+
+        let hash = self.transcript.finish();
+        expand_label(&c_hs, b"finished", b"", &mut finished_key);
+        self.client_finished = hkdf::hmac(&finished_key, &[&hash]);
+        self.client_secret = derive_secret(&master, b"c ap traffic", &hash);
+        self.server_secret = derive_secret(&master, b"s ap traffic", &hash);
+        self.write = Keys::from_secret(&self.server_secret);
+        self.phase = EXPECT_FINISHED;
+        Ok(())
+    }
+
+    /// Ask the client to resend its hello with an X25519 key share.
+    fn hello_retry(&mut self, fd: usize, ch: &[u8], session_id: &[u8]) -> Result<(), u8> {
+        // The transcript restarts with a synthetic message standing in for
+        // the first ClientHello.
+        self.transcript = Sha256::new();
+        self.transcript.update(&[MESSAGE_HASH, 0, 0, 32]);
+        self.transcript.update(&Sha256::digest(ch));
+
+        let mut w = Writer::new(&mut self.hs);
+        w.push(&[SERVER_HELLO]);
+        w.u24(2 + 32 + 1 + session_id.len() + 2 + 1 + 2 + 6 + 6);
+        w.push(&[3, 3]);
+        w.push(&HRR_RANDOM);
+        w.push(&[session_id.len() as u8]);
+        w.push(session_id);
+        w.push(&CHACHA20_POLY1305_SHA256.to_be_bytes());
+        w.push(&[0, 0, 12]);
+        w.push(&EXT_SUPPORTED_VERSIONS.to_be_bytes());
+        w.push(&[0, 2]);
+        w.push(&TLS13.to_be_bytes());
+        w.push(&EXT_KEY_SHARE.to_be_bytes());
+        w.push(&[0, 2]);
+        w.push(&X25519.to_be_bytes());
+        let n = w.len;
+        self.transcript.update(&self.hs[..n]);
+        send_plain(fd, &mut self.out, HANDSHAKE, &self.hs[..n])?;
+        if !session_id.is_empty() {
+            send_plain(fd, &mut self.out, CHANGE_CIPHER_SPEC, &[1])?;
+            self.sent_ccs = true;
+        }
+        self.retried = true;
+        Ok(())
+    }
+
+    fn key_update(&mut self, msg: &[u8]) -> Result<(), u8> {
+        if msg.len() != 5 || msg[4] > 1 {
+            return Err(DECODE_ERROR);
+        }
+        let mut next = [0u8; 32];
+        expand_label(&self.client_secret, b"traffic upd", b"", &mut next);
+        self.client_secret = next;
+        self.read = Keys::from_secret(&next);
+        self.key_update_due |= msg[4] == 1;
+        Ok(())
+    }
+
+    fn send_alert(&mut self, fd: usize, alert: u8) -> SysResult<()> {
+        if self.write_on {
+            send_encrypted(fd, &mut self.write, &mut self.out, ALERT, &[2, alert])
+        } else {
+            send_plain(fd, &mut self.out, ALERT, &[2, alert]).map_err(|_| PROTOCOL_ERROR)
+        }
+    }
+}
+
+fn server_hello(w: &mut Writer, random: &[u8; 32], session_id: &[u8], public: &[u8; 32]) {
+    w.push(&[SERVER_HELLO]);
+    w.u24(2 + 32 + 1 + session_id.len() + 2 + 1 + 2 + 6 + 40);
+    w.push(&[3, 3]);
+    w.push(random);
+    w.push(&[session_id.len() as u8]);
+    w.push(session_id);
+    w.push(&CHACHA20_POLY1305_SHA256.to_be_bytes());
+    w.push(&[0, 0, 46]);
+    w.push(&EXT_SUPPORTED_VERSIONS.to_be_bytes());
+    w.push(&[0, 2]);
+    w.push(&TLS13.to_be_bytes());
+    w.push(&EXT_KEY_SHARE.to_be_bytes());
+    w.push(&[0, 36]);
+    w.push(&X25519.to_be_bytes());
+    w.push(&[0, 32]);
+    w.push(public);
+}
+
+/// Send an unprotected record (before keys exist).
+fn send_plain(fd: usize, out: &mut [u8; OUT_LEN], typ: u8, data: &[u8]) -> Result<(), u8> {
+    out[..5].copy_from_slice(&[typ, 3, 3, 0, 0]);
+    out[3..5].copy_from_slice(&(data.len() as u16).to_be_bytes());
+    out[5..5 + data.len()].copy_from_slice(data);
+    sys::net::send_all(fd, &out[..5 + data.len()]).map_err(|_| NO_ALERT)
+}
+
+/// Send `data` as records of inner type `typ`.
+fn send_encrypted(
+    fd: usize,
+    keys: &mut Keys,
+    out: &mut [u8; OUT_LEN],
+    typ: u8,
+    data: &[u8],
+) -> SysResult<()> {
+    seal_records(keys, out, typ, data, &mut |record| {
+        sys::net::send_all(fd, record)
+    })
+}
+
+/// Protect `data` as records of inner type `typ`, handing each to `sink`.
+fn seal_records(
+    keys: &mut Keys,
+    out: &mut [u8; OUT_LEN],
+    typ: u8,
+    data: &[u8],
+    sink: &mut dyn FnMut(&[u8]) -> SysResult<()>,
+) -> SysResult<()> {
+    for chunk in data.chunks(MAX_PLAINTEXT) {
+        let inner = chunk.len() + 1;
+        out[..3].copy_from_slice(&[APPLICATION_DATA, 3, 3]);
+        out[3..5].copy_from_slice(&((inner + TAG_LEN) as u16).to_be_bytes());
+        out[5..5 + chunk.len()].copy_from_slice(chunk);
+        out[5 + chunk.len()] = typ;
+        let mut aad = [0u8; 5];
+        aad.copy_from_slice(&out[..5]);
+        let nonce = keys.next_nonce();
+        let tag = aead::seal(&keys.key, &nonce, &aad, &mut out[5..5 + inner]);
+        out[5 + inner..5 + inner + TAG_LEN].copy_from_slice(&tag);
+        sink(&out[..5 + inner + TAG_LEN])?;
+    }
+    Ok(())
+}
+
+/// What the server needs from a ClientHello.
+struct ClientHello<'a> {
+    session_id: &'a [u8],
+    compression: &'a [u8],
+    tls13: bool,
+    chacha: bool,
+    signature_ok: bool,
+    x25519_offered: bool,
+    share: Option<&'a [u8; 32]>,
+}
+
+impl<'a> ClientHello<'a> {
+    fn parse(body: &'a [u8], scheme: u16) -> Option<Self> {
+        let mut r = Reader(body);
+        r.take(2 + 32)?;
+        let session_id = r.vec8()?;
+        if session_id.len() > 32 {
+            return None;
+        }
+        let suites = r.vec16()?;
+        let compression = r.vec8()?;
+        let mut hello = ClientHello {
+            session_id,
+            compression,
+            tls13: false,
+            chacha: u16s(suites).any(|s| s == CHACHA20_POLY1305_SHA256),
+            signature_ok: false,
+            x25519_offered: false,
+            share: None,
+        };
+        if r.0.is_empty() {
+            // No extensions: at most TLS 1.2.
+            return Some(hello);
+        }
+        let mut exts = Reader(r.vec16()?);
+        if !r.0.is_empty() {
+            return None;
+        }
+        while !exts.0.is_empty() {
+            let typ = exts.u16()?;
+            let mut data = Reader(exts.vec16()?);
+            match typ {
+                EXT_SUPPORTED_VERSIONS => {
+                    hello.tls13 = u16s(data.vec8()?).any(|v| v == TLS13);
+                }
+                EXT_SUPPORTED_GROUPS => {
+                    hello.x25519_offered = u16s(data.vec16()?).any(|g| g == X25519);
+                }
+                EXT_SIGNATURE_ALGORITHMS => {
+                    hello.signature_ok = u16s(data.vec16()?).any(|s| s == scheme);
+                }
+                EXT_KEY_SHARE => {
+                    let mut shares = Reader(data.vec16()?);
+                    while !shares.0.is_empty() {
+                        let group = shares.u16()?;
+                        let key = shares.vec16()?;
+                        if group == X25519 {
+                            hello.share = Some(key.try_into().ok()?);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(hello)
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn vec8(&mut self) -> Option<&'a [u8]> {
+        let n = self.take(1)?[0] as usize;
+        self.take(n)
+    }
+
+    fn vec16(&mut self) -> Option<&'a [u8]> {
+        let n = self.u16()? as usize;
+        self.take(n)
+    }
+}
+
+fn u16s(b: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    b.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]]))
+}
+
+/// Appends to the handshake buffer; writes past the end are dropped and
+/// caught by the caller's length check.
+struct Writer<'a> {
+    buf: &'a mut [u8; HS_LEN],
+    len: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn new(buf: &'a mut [u8; HS_LEN]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        let end = self.len + bytes.len();
+        if end <= HS_LEN {
+            self.buf[self.len..end].copy_from_slice(bytes);
+        }
+        self.len = end;
+    }
+
+    fn u24(&mut self, n: usize) {
+        self.push(&(n as u32).to_be_bytes()[1..]);
+    }
+}
@@ -134,3 +134,38 @@ pub(crate) fn base64_encode(src: &[u8], dst: &mut [u8]) -> usize {
     }
     o
 }
+
+/// Decode padded standard base64; `None` on anything malformed or if `dst`
+/// is too small.
+pub(crate) fn base64_decode(src: &[u8], dst: &mut [u8]) -> Option<usize> {
+    if !src.len().is_multiple_of(4) {
+        return None;
+    }
+    let pad = src.iter().rev().take_while(|&&c| c == b'=').count();
+    if pad > 2 {
+        return None;
+    }
+    let mut o = 0;
+    for (chunk_i, chunk) in src.chunks(4).enumerate() {
+        let last = chunk_i == src.len() / 4 - 1;
+        let mut v = 0u32;
+        for (j, &c) in chunk.iter().enumerate() {
+            let d = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                b'=' if last && j >= 4 - pad => 0,
+                _ => return None,
+            };
+            v = (v << 6) | d as u32;
+        }
+        let n = if last { 3 - pad } else { 3 };
+        for k in 0..n {
+            *dst.get_mut(o)? = (v >> (16 - 8 * k)) as u8;
+            o += 1;
+        }
+    }
+    Some(o)
+}
@@ -1,72 +1,296 @@
-use crate::sys::net as sysnet;
+use crate::net::stream::Stream;
+use crate::runtime::{allocator, util};
 
-pub(crate) fn write_binary_frame(fd: usize, payload: &[u8]) -> Result<(), &'static str> {
+pub(crate) const OPCODE_CONTINUATION: u8 = 0x0;
+pub(crate) const OPCODE_TEXT: u8 = 0x1;
+pub(crate) const OPCODE_BINARY: u8 = 0x2;
+pub(crate) const OPCODE_CLOSE: u8 = 0x8;
+pub(crate) const OPCODE_PING: u8 = 0x9;
+pub(crate) const OPCODE_PONG: u8 = 0xA;
+
+pub(crate) const CLOSE_NORMAL: u16 = 1000;
+pub(crate) const CLOSE_GOING_AWAY: u16 = 1001;
+pub(crate) const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub(crate) const CLOSE_INVALID_DATA: u16 = 1007;
+pub(crate) const CLOSE_TOO_BIG: u16 = 1009;
+pub(crate) const CLOSE_INTERNAL_ERROR: u16 = 1011;
+pub(crate) const CLOSE_TRY_AGAIN_LATER: u16 = 1013;
+
+/// Largest possible frame header: 2 bytes, 8-byte extended length, mask key.
+const MAX_HEADER: usize = 14;
+
+pub(crate) fn write_frame(
+    stream: &mut Stream,
+    opcode: u8,
+    payload: &[u8],
+) -> Result<(), &'static str> {
     let mut hdr = [0u8; 10];
-    hdr[0] = 0x80 | 0x2;
-    let off = if payload.len() < 126 {
-        hdr[1] = payload.len() as u8;
+    let off = frame_header(opcode, payload.len(), &mut hdr);
+    stream.send_all(&hdr[..off]).map_err(|_| "send hdr")?;
+    stream.send_all(payload).map_err(|_| "send payload")
+}
+
+/// Write the header of an unmasked, final server frame of `len` payload
+/// bytes into `hdr`, returning its length.
+pub(crate) fn frame_header(opcode: u8, len: usize, hdr: &mut [u8; 10]) -> usize {
+    hdr[0] = 0x80 | opcode;
+    if len < 126 {
+        hdr[1] = len as u8;
         2
-    } else if payload.len() <= 0xFFFF {
+    } else if len <= 0xFFFF {
         hdr[1] = 126;
-        hdr[2] = ((payload.len() >> 8) & 0xFF) as u8;
-        hdr[3] = (payload.len() & 0xFF) as u8;
+        hdr[2] = ((len >> 8) & 0xFF) as u8;
+        hdr[3] = (len & 0xFF) as u8;
         4
     } else {
         hdr[1] = 127;
         for i in 0..8 {
-            hdr[2 + i] = ((payload.len() as u64 >> (8 * (7 - i))) & 0xFF) as u8;
+            hdr[2 + i] = ((len as u64 >> (8 * (7 - i))) & 0xFF) as u8;
         }
         10
-    };
-    sysnet::send_all(fd, &hdr[..off]).map_err(|_| "send hdr")?;
-    sysnet::send_all(fd, payload).map_err(|_| "send payload")
+    }
 }
 
-pub(crate) fn parse_and_unmask_frames<'a>(
-    input: &[u8],
-    out: &'a mut [u8],
-) -> Result<&'a [u8], &'static str> {
-    if input.len() < 2 {
-        return Err("short");
+pub(crate) fn write_binary_frame(stream: &mut Stream, payload: &[u8]) -> Result<(), &'static str> {
+    write_frame(stream, OPCODE_BINARY, payload)
+}
+
+/// Send a close frame. The reason is cut to fit the 125-byte control frame
+/// limit (on a UTF-8 boundary, so the peer can still decode it).
+pub(crate) fn write_close_frame(
+    stream: &mut Stream,
+    code: u16,
+    reason: &[u8],
+) -> Result<(), &'static str> {
+    let mut payload = [0u8; 125];
+    let n = close_payload(code, reason, &mut payload);
+    write_frame(stream, OPCODE_CLOSE, &payload[..n])
+}
+
+/// Write a close frame's payload into `payload`, cutting `reason` at a
+/// character boundary to fit; returns its length.
+pub(crate) fn close_payload(code: u16, reason: &[u8], payload: &mut [u8; 125]) -> usize {
+    payload[..2].copy_from_slice(&code.to_be_bytes());
+    let mut n = core::cmp::min(reason.len(), payload.len() - 2);
+    while n > 0 && n < reason.len() && (reason[n] & 0xC0) == 0x80 {
+        n -= 1;
     }
-    let b0 = input[0];
-    let b1 = input[1];
-    let masked = (b1 & 0x80) != 0;
-    let mut idx = 2;
-    let mut len: usize = (b1 & 0x7F) as usize;
-    if len == 126 {
-        if input.len() < idx + 2 {
-            return Err("short");
-        }
-        len = ((input[idx] as usize) << 8) | (input[idx + 1] as usize);
-        idx += 2;
-    } else if len == 127 {
-        if input.len() < idx + 8 {
-            return Err("short");
-        }
-        let mut l: u64 = 0;
-        for i in 0..8 {
-            l = (l << 8) | (input[idx + i] as u64);
+    payload[2..2 + n].copy_from_slice(&reason[..n]);
+    2 + n
+}
+
+/// Validate a received close payload. `Ok` carries the status code to echo
+/// back; `Err` the code to fail the connection with instead.
+pub(crate) fn parse_close(payload: &[u8]) -> Result<u16, u16> {
+    if payload.is_empty() {
+        return Ok(CLOSE_NORMAL);
+    }
+    if payload.len() < 2 {
+        return Err(CLOSE_PROTOCOL_ERROR);
+    }
+    let code = u16::from_be_bytes([payload[0], payload[1]]);
+    let valid = matches!(code, 1000..=1003 | 1007..=1011 | 3000..=4999);
+    if !valid {
+        return Err(CLOSE_PROTOCOL_ERROR);
+    }
+    if core::str::from_utf8(&payload[2..]).is_err() {
+        return Err(CLOSE_INVALID_DATA);
+    }
+    Ok(code)
+}
+
+/// Close status matching a [`FrameDecoder`] error.
+pub(crate) fn close_code_for(err: &str) -> u16 {
+    match err {
+        "too big" => CLOSE_TOO_BIG,
+        "invalid utf-8" => CLOSE_INVALID_DATA,
+        _ => CLOSE_PROTOCOL_ERROR,
+    }
+}
+
+/// A complete message (or control frame) decoded from the client.
+pub(crate) enum Message<'a> {
+    Text(&'a [u8]),
+    Binary(&'a [u8]),
+    Close(&'a [u8]),
+    Ping(&'a [u8]),
+    Pong(&'a [u8]),
+}
+
+/// Incremental RFC 6455 decoder for client-to-server traffic.
+///
+/// Bytes are received straight into [`FrameDecoder::spare`] and committed
+/// with [`FrameDecoder::fill`]; [`FrameDecoder::next_message`] then yields
+/// every complete frame, however the stream was split across reads.
+/// Fragmented data messages are reassembled, with control frames allowed in
+/// between. Errors are `"too big"` when a message exceeds the configured
+/// limit, `"invalid utf-8"` for a text message that is not UTF-8, and
+/// protocol violations otherwise; see [`close_code_for`].
+pub(crate) struct FrameDecoder {
+    raw: *mut u8,
+    raw_cap: usize,
+    start: usize,
+    end: usize,
+    msg: *mut u8,
+    msg_cap: usize,
+    msg_len: usize,
+    /// Opcode of the fragmented message being reassembled, if any.
+    msg_opcode: Option<u8>,
+}
+
+impl FrameDecoder {
+    pub(crate) fn new(max_message: usize) -> Result<Self, &'static str> {
+        let raw_cap = max_message + MAX_HEADER;
+        let raw = allocator::page_alloc(raw_cap).map_err(|_| "mmap frame buf")?;
+        let msg = match allocator::page_alloc(max_message) {
+            Ok(p) => p,
+            Err(_) => {
+                let _ = allocator::page_free(raw, raw_cap);
+                return Err("mmap message buf");
+            }
+        };
+        Ok(Self {
+            raw,
+            raw_cap,
+            start: 0,
+            end: 0,
+            msg,
+            msg_cap: max_message,
+            msg_len: 0,
+            msg_opcode: None,
+        })
+    }
+
+    /// Free space to receive into; compacts buffered bytes to the front.
+    pub(crate) fn spare(&mut self) -> &mut [u8] {
+        if self.start > 0 {
+            let raw = util::ptr_to_mut_slice(self.raw, self.raw_cap);
+            raw.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
         }
-        idx += 8;
-        len = l as usize;
+        &mut util::ptr_to_mut_slice(self.raw, self.raw_cap)[self.end..]
     }
-    if !masked {
-        return Err("client not masked");
+
+    /// Commit `n` bytes written into the slice returned by `spare`.
+    pub(crate) fn fill(&mut self, n: usize) {
+        self.end = core::cmp::min(self.end + n, self.raw_cap);
     }
-    if input.len() < idx + 4 + len {
-        return Err("short");
+
+    /// Decode the next complete message, or `Ok(None)` if more input is
+    /// needed.
+    pub(crate) fn next_message(&mut self) -> Result<Option<Message<'_>>, &'static str> {
+        loop {
+            let raw = util::ptr_to_mut_slice(self.raw, self.raw_cap);
+            let input = &raw[self.start..self.end];
+            if input.len() < 2 {
+                return Ok(None);
+            }
+            let b0 = input[0];
+            let b1 = input[1];
+            let fin = (b0 & 0x80) != 0;
+            let opcode = b0 & 0x0F;
+            if (b0 & 0x70) != 0 {
+                return Err("reserved bits set");
+            }
+            if (b1 & 0x80) == 0 {
+                return Err("client not masked");
+            }
+            let mut idx = 2;
+            let mut len = (b1 & 0x7F) as u64;
+            if len == 126 {
+                if input.len() < idx + 2 {
+                    return Ok(None);
+                }
+                len = ((input[idx] as u64) << 8) | (input[idx + 1] as u64);
+                idx += 2;
+            } else if len == 127 {
+                if input.len() < idx + 8 {
+                    return Ok(None);
+                }
+                len = 0;
+                for i in 0..8 {
+                    len = (len << 8) | (input[idx + i] as u64);
+                }
+                idx += 8;
+            }
+            let is_control = (opcode & 0x8) != 0;
+            if is_control && (!fin || len > 125) {
+                return Err("bad control frame");
+            }
+            if len > self.msg_cap as u64 {
+                return Err("too big");
+            }
+            let len = len as usize;
+            if input.len() < idx + 4 + len {
+                return Ok(None);
+            }
+            let mut key = [0u8; 4];
+            key.copy_from_slice(&input[idx..idx + 4]);
+            idx += 4;
+            let payload_start = self.start + idx;
+            self.start = payload_start + len;
+            let payload = &mut raw[payload_start..payload_start + len];
+            for (i, b) in payload.iter_mut().enumerate() {
+                *b ^= key[i % 4];
+            }
+
+            match opcode {
+                OPCODE_CLOSE => return Ok(Some(Message::Close(payload))),
+                OPCODE_PING => return Ok(Some(Message::Ping(payload))),
+                OPCODE_PONG => return Ok(Some(Message::Pong(payload))),
+                OPCODE_TEXT | OPCODE_BINARY => {
+                    if self.msg_opcode.is_some() {
+                        return Err("expected continuation");
+                    }
+                    if fin {
+                        return data_message(opcode, payload).map(Some);
+                    }
+                    self.msg_opcode = Some(opcode);
+                    self.msg_len = 0;
+                    self.append(payload)?;
+                }
+                OPCODE_CONTINUATION => {
+                    let Some(first) = self.msg_opcode else {
+                        return Err("unexpected continuation");
+                    };
+                    self.append(payload)?;
+                    if fin {
+                        self.msg_opcode = None;
+                        let msg = util::ptr_to_slice(self.msg, self.msg_len);
+                        return data_message(first, msg).map(Some);
+                    }
+                }
+                _ => return Err("unknown opcode"),
+            }
+        }
+    }
+
+    fn append(&mut self, payload: &[u8]) -> Result<(), &'static str> {
+        if self.msg_len + payload.len() > self.msg_cap {
+            return Err("too big");
+        }
+        let msg = util::ptr_to_mut_slice(self.msg, self.msg_cap);
+        msg[self.msg_len..self.msg_len + payload.len()].copy_from_slice(payload);
+        self.msg_len += payload.len();
+        Ok(())
     }
-    let key = &input[idx..idx + 4];
-    idx += 4;
-    for i in 0..len {
-        out[i] = input[idx + i] ^ key[i % 4];
+}
+
+impl Drop for FrameDecoder {
+    fn drop(&mut self) {
+        let _ = allocator::page_free(self.raw, self.raw_cap);
+        let _ = allocator::page_free(self.msg, self.msg_cap);
     }
-    let opcode = b0 & 0x0F;
-    if opcode == 0x8 {
-        return Err("close");
+}
+
+fn data_message(opcode: u8, payload: &[u8]) -> Result<Message<'_>, &'static str> {
+    if opcode == OPCODE_TEXT {
+        core::str::from_utf8(payload).map_err(|_| "invalid utf-8")?;
+        Ok(Message::Text(payload))
+    } else {
+        Ok(Message::Binary(payload))
     }
-    Ok(&out[..len])
 }
 
 pub(crate) fn copy(dst: &mut [u8], src: &[u8]) -> usize {
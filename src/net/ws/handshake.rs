@@ -1,10 +1,80 @@
-use super::{WebSocket, crypto, frame};
-use crate::net::http;
-use crate::sys::net as sysnet;
+use super::{crypto, frame};
+use crate::net::http::{self, Request};
+use crate::net::stream::Stream;
 
 const WS_GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
 
-pub(crate) fn upgrade_to_websocket(fd: usize, req: &[u8]) -> Result<WebSocket, &'static str> {
+/// Why an upgrade was refused: response status, extra header lines and a
+/// reason for the log.
+pub(crate) struct Refusal {
+    pub(crate) status: &'static [u8],
+    pub(crate) headers: &'static [u8],
+    pub(crate) reason: &'static [u8],
+}
+
+/// Check an upgrade request before a worker is forked for it: protocol
+/// version 13, a key that decodes to a 16-byte nonce, and an `Origin` (if
+/// the client sent one; browsers always do) that `allowed_origins` lets in.
+pub(crate) fn check_upgrade(req: &Request, allowed_origins: &str) -> Result<(), Refusal> {
+    let refuse = |status, headers, reason| {
+        Err(Refusal {
+            status,
+            headers,
+            reason,
+        })
+    };
+    match req.header("Sec-WebSocket-Version") {
+        Some("13") => {}
+        Some(_) => {
+            return refuse(
+                b"426 Upgrade Required",
+                b"Sec-WebSocket-Version: 13\r\n",
+                b"unsupported Sec-WebSocket-Version",
+            );
+        }
+        None => return refuse(b"400 Bad Request", b"", b"no Sec-WebSocket-Version"),
+    }
+    let mut nonce = [0u8; 18];
+    let key = req.header("Sec-WebSocket-Key").unwrap_or("");
+    if crypto::base64_decode(key.as_bytes(), &mut nonce) != Some(16) {
+        return refuse(b"400 Bad Request", b"", b"bad Sec-WebSocket-Key");
+    }
+    if let Some(origin) = req.header("Origin")
+        && !origin_allowed(origin, req.header("Host"), allowed_origins)
+    {
+        return refuse(b"403 Forbidden", b"", b"origin not allowed");
+    }
+    Ok(())
+}
+
+/// Whether `origin` matches an entry of the comma-separated `allowed`:
+/// `*`, `same-origin` (the request's `Host`) or an origin spelled out.
+fn origin_allowed(origin: &str, host: Option<&str>, allowed: &str) -> bool {
+    allowed.split(',').any(|entry| match entry {
+        "*" => true,
+        "same-origin" => host.is_some_and(|host| names_host(origin, host)),
+        listed => http::eq_case_insensitive(listed, origin),
+    })
+}
+
+/// Whether `origin` (`scheme://host[:port]`) has the authority `host`, a
+/// `Host` value; either may leave out the scheme's default port.
+fn names_host(origin: &str, host: &str) -> bool {
+    let Some((scheme, authority)) = origin.split_once("://") else {
+        return false;
+    };
+    let default_port = if http::eq_case_insensitive(scheme, "http") {
+        ":80"
+    } else if http::eq_case_insensitive(scheme, "https") {
+        ":443"
+    } else {
+        return false;
+    };
+    let strip = |a| str::strip_suffix(a, default_port).unwrap_or(a);
+    http::eq_case_insensitive(strip(authority), strip(host))
+}
+
+pub(crate) fn upgrade_to_websocket(stream: &mut Stream, req: &[u8]) -> Result<(), &'static str> {
     let key = http::header(req, "Sec-WebSocket-Key").ok_or("no key")?;
     let mut concat = [0u8; 128];
     let key_b = key.as_bytes();
@@ -28,6 +98,5 @@ pub(crate) fn upgrade_to_websocket(fd: usize, req: &[u8]) -> Result<WebSocket, &
     off += frame::copy(&mut resp[off..], &accept[..acc_len]);
     off += frame::copy(&mut resp[off..], tail);
 
-    sysnet::send_all(fd, &resp[..off]).map_err(|_| "send")?;
-    Ok(WebSocket { fd })
+    stream.send_all(&resp[..off]).map_err(|_| "send")
 }
@@ -1,10 +1,14 @@
+mod blowfish;
 mod crypto;
 mod frame;
 mod handshake;
+mod passwd;
 
-pub(crate) struct WebSocket {
-    pub(crate) fd: usize,
-}
-
-pub(crate) use frame::{parse_and_unmask_frames, write_binary_frame};
-pub(crate) use handshake::upgrade_to_websocket;
+pub(crate) use crypto::base64_decode;
+pub(crate) use frame::{
+    CLOSE_GOING_AWAY, CLOSE_INTERNAL_ERROR, CLOSE_NORMAL, CLOSE_TRY_AGAIN_LATER, FrameDecoder,
+    Message, OPCODE_BINARY, OPCODE_CLOSE, OPCODE_PING, OPCODE_PONG, OPCODE_TEXT, close_code_for,
+    close_payload, frame_header, parse_close, write_binary_frame, write_close_frame, write_frame,
+};
+pub(crate) use handshake::{check_upgrade, upgrade_to_websocket};
+pub(crate) use passwd::{CostLimits, MAX_PASSWORD, verify_password};
@@ -0,0 +1,296 @@
+//! Password hashes as found in htpasswd files: bcrypt (`$2y$`, also `$2b$`
+//! and `$2a$`), SHA-512-crypt (`$6$`) and SHA-256-crypt (`$5$`).
+
+use super::blowfish::Blowfish;
+use crate::net::tls::{Sha256, Sha512};
+
+/// Longest password checked; anything longer never matches.
+pub(crate) const MAX_PASSWORD: usize = 512;
+/// Longest hash produced: `$6$rounds=999999999$`, a 16-byte salt, `$` and
+/// 86 characters.
+const MAX_HASH: usize = 128;
+
+/// Alphabet of the crypt(3) base64 variant used by the SHA-crypt hashes,
+/// least significant bits first.
+const CRYPT64: &[u8; 64] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+/// Alphabet of bcrypt's base64 variant, most significant bits first.
+const BCRYPT64: &[u8; 64] = b"./ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Most work a hash may ask for: verifying takes about `2^bcrypt_cost`
+/// or `sha_crypt_rounds` times some constant, on the caller's time.
+#[derive(Copy, Clone)]
+pub(crate) struct CostLimits {
+    pub(crate) bcrypt_cost: u32,
+    pub(crate) sha_crypt_rounds: u32,
+}
+
+impl CostLimits {
+    /// Whether checking a password against `hash` stays within the
+    /// limits. Formats not handled here and malformed hashes pass, since
+    /// `verify_password` refuses them without any work.
+    pub(crate) fn allow(&self, hash: &str) -> bool {
+        if hash.starts_with("$2y$") || hash.starts_with("$2b$") || hash.starts_with("$2a$") {
+            bcrypt_cost(hash.as_bytes()).is_none_or(|cost| cost <= self.bcrypt_cost)
+        } else if let Some(spec) = hash
+            .strip_prefix("$6$")
+            .or_else(|| hash.strip_prefix("$5$"))
+        {
+            sha_crypt_rounds(spec).map_or(true, |(rounds, _)| rounds <= self.sha_crypt_rounds)
+        } else {
+            true
+        }
+    }
+}
+
+/// Whether `password` hashes to `hash`. `Err` if `hash` is in a format not
+/// handled here or malformed.
+pub(crate) fn verify_password(password: &[u8], hash: &str) -> Result<bool, &'static str> {
+    if password.len() > MAX_PASSWORD {
+        return Ok(false);
+    }
+    let mut out = [0u8; MAX_HASH];
+    let n = if hash.starts_with("$2y$") || hash.starts_with("$2b$") || hash.starts_with("$2a$") {
+        bcrypt(password, hash, &mut out)?
+    } else if let Some(rest) = hash.strip_prefix("$6$") {
+        sha_crypt::<Sha512>(password, hash, rest, &mut out)?
+    } else if let Some(rest) = hash.strip_prefix("$5$") {
+        sha_crypt::<Sha256>(password, hash, rest, &mut out)?
+    } else {
+        return Err("unsupported hash");
+    };
+    let hash = hash.as_bytes();
+    Ok(n == hash.len()
+        && out[..n]
+            .iter()
+            .zip(hash)
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0)
+}
+
+/// `$2y$CC$` + 22 characters of salt + 31 of hash. The key is the password
+/// with its terminating NUL, cut at 72 bytes; the variants differ only in
+/// bugs of old implementations, so they are treated alike.
+fn bcrypt(password: &[u8], hash: &str, out: &mut [u8; MAX_HASH]) -> Result<usize, &'static str> {
+    let h = hash.as_bytes();
+    let cost = bcrypt_cost(h).ok_or("malformed bcrypt hash")?;
+    if !(4..=31).contains(&cost) {
+        return Err("bcrypt cost out of range");
+    }
+    let mut salt = [0u8; 16];
+    bcrypt64_decode(&h[7..29], &mut salt).ok_or("malformed bcrypt hash")?;
+
+    let mut key = [0u8; 72];
+    let klen = core::cmp::min(password.len() + 1, key.len());
+    let copied = core::cmp::min(password.len(), key.len());
+    key[..copied].copy_from_slice(&password[..copied]);
+    let bf = Blowfish::eks_setup(cost, &salt, &key[..klen]);
+
+    let mut ctext = [0u32; 6];
+    for (w, chunk) in ctext.iter_mut().zip(b"OrpheanBeholderScryDoubt".chunks(4)) {
+        *w = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    for _ in 0..64 {
+        for pair in ctext.chunks_exact_mut(2) {
+            (pair[0], pair[1]) = bf.encrypt(pair[0], pair[1]);
+        }
+    }
+    let mut raw = [0u8; 24];
+    for (chunk, w) in raw.chunks_exact_mut(4).zip(ctext) {
+        chunk.copy_from_slice(&w.to_be_bytes());
+    }
+    // The salt is copied as given, so a non-canonical last salt character
+    // still compares equal.
+    out[..29].copy_from_slice(&h[..29]);
+    Ok(29 + bcrypt64_encode(&raw[..23], &mut out[29..]))
+}
+
+/// Decode bcrypt base64 into exactly `dst.len()` bytes; leftover bits are
+/// ignored.
+fn bcrypt64_decode(src: &[u8], dst: &mut [u8]) -> Option<()> {
+    let (mut acc, mut bits, mut o) = (0u32, 0, 0);
+    for &c in src {
+        let d = BCRYPT64.iter().position(|&a| a == c)? as u32;
+        acc = (acc << 6) | d;
+        bits += 6;
+        if bits >= 8 && o < dst.len() {
+            bits -= 8;
+            dst[o] = (acc >> bits) as u8;
+            o += 1;
+        }
+    }
+    (o == dst.len()).then_some(())
+}
+
+fn bcrypt64_encode(src: &[u8], dst: &mut [u8]) -> usize {
+    let mut o = 0;
+    for chunk in src.chunks(3) {
+        let mut v = 0u32;
+        for (i, &b) in chunk.iter().enumerate() {
+            v |= (b as u32) << (16 - 8 * i);
+        }
+        for i in 0..chunk.len() + 1 {
+            dst[o] = BCRYPT64[((v >> (18 - 6 * i)) & 63) as usize];
+            o += 1;
+        }
+    }
+    o
+}
+
+/// SHA-2 hashes SHA-crypt can be built on.
+trait CryptHash {
+    const LEN: usize;
+    /// Order the digest's bytes are encoded in.
+    const ORDER: &'static [u8];
+    fn start() -> Self;
+    fn feed(&mut self, data: &[u8]);
+    /// The digest, in the first `LEN` bytes.
+    fn end(self) -> [u8; 64];
+}
+
+impl CryptHash for Sha512 {
+    const LEN: usize = 64;
+    const ORDER: &'static [u8] = &[
+        0, 21, 42, 22, 43, 1, 44, 2, 23, 3, 24, 45, 25, 46, 4, 47, 5, 26, 6, 27, 48, 28, 49, 7, 50,
+        8, 29, 9, 30, 51, 31, 52, 10, 53, 11, 32, 12, 33, 54, 34, 55, 13, 56, 14, 35, 15, 36, 57,
+        37, 58, 16, 59, 17, 38, 18, 39, 60, 40, 61, 19, 62, 20, 41, 63,
+    ];
+    fn start() -> Self {
+        Sha512::new()
+    }
+    fn feed(&mut self, data: &[u8]) {
+        self.update(data);
+    }
+    fn end(self) -> [u8; 64] {
+        self.finish()
+    }
+}
+
+impl CryptHash for Sha256 {
+    const LEN: usize = 32;
+    const ORDER: &'static [u8] = &[
+        0, 10, 20, 21, 1, 11, 12, 22, 2, 3, 13, 23, 24, 4, 14, 15, 25, 5, 6, 16, 26, 27, 7, 17, 18,
+        28, 8, 9, 19, 29, 31, 30,
+    ];
+    fn start() -> Self {
+        Sha256::new()
+    }
+    fn feed(&mut self, data: &[u8]) {
+        self.update(data);
+    }
+    fn end(self) -> [u8; 64] {
+        let mut out = [0u8; 64];
+        out[..32].copy_from_slice(&self.finish());
+        out
+    }
+}
+
+/// The cost in a `$2y$CC$...` hash, if it has the right shape.
+fn bcrypt_cost(h: &[u8]) -> Option<u32> {
+    if h.len() != 60 || h[6] != b'$' {
+        return None;
+    }
+    match (h[4], h[5]) {
+        (t @ b'0'..=b'3', u @ b'0'..=b'9') => Some(((t - b'0') * 10 + (u - b'0')) as u32),
+        _ => None,
+    }
+}
+
+/// Rounds a SHA-crypt `spec` (the hash after its `$N$`) asks for, and
+/// what follows them.
+fn sha_crypt_rounds(spec: &str) -> Result<(u32, &str), &'static str> {
+    match spec.strip_prefix("rounds=") {
+        Some(r) => {
+            let (n, rest) = r.split_once('$').ok_or("malformed SHA-crypt hash")?;
+            let n: u32 = n.parse().map_err(|_| "malformed SHA-crypt hash")?;
+            Ok((n.clamp(1000, 999_999_999), rest))
+        }
+        None => Ok((5000, spec)),
+    }
+}
+
+/// SHA-crypt (Drepper 2007): `$5$` or `$6$`, an optional `rounds=N$`
+/// (default 5000, clamped to 1000..=999999999), up to 16 characters of
+/// salt, `$` and the encoded digest. `spec` is `hash` after its `$N$`.
+fn sha_crypt<H: CryptHash>(
+    password: &[u8],
+    hash: &str,
+    spec: &str,
+    out: &mut [u8; MAX_HASH],
+) -> Result<usize, &'static str> {
+    let (rounds, salted) = sha_crypt_rounds(spec)?;
+    let salt_len = salted.find('$').unwrap_or(salted.len()).min(16);
+    let s = &salted.as_bytes()[..salt_len];
+    let p = password;
+    let n = H::LEN;
+
+    let mut b = H::start();
+    b.feed(p);
+    b.feed(s);
+    b.feed(p);
+    let b = b.end();
+    let mut a = H::start();
+    a.feed(p);
+    a.feed(s);
+    let mut left = p.len();
+    while left > n {
+        a.feed(&b[..n]);
+        left -= n;
+    }
+    a.feed(&b[..left]);
+    let mut bits = p.len();
+    while bits > 0 {
+        a.feed(if bits & 1 != 0 { &b[..n] } else { p });
+        bits >>= 1;
+    }
+    let mut a = a.end();
+
+    let mut dp = H::start();
+    for _ in 0..p.len() {
+        dp.feed(p);
+    }
+    let dp = dp.end();
+    let mut p_bytes = [0u8; MAX_PASSWORD];
+    for (i, x) in p_bytes[..p.len()].iter_mut().enumerate() {
+        *x = dp[i % n];
+    }
+    let p_bytes = &p_bytes[..p.len()];
+    let mut ds = H::start();
+    for _ in 0..16 + a[0] as usize {
+        ds.feed(s);
+    }
+    let ds = ds.end();
+    let s_bytes = &ds[..s.len()];
+
+    for i in 0..rounds {
+        let mut c = H::start();
+        c.feed(if i & 1 != 0 { p_bytes } else { &a[..n] });
+        if i % 3 != 0 {
+            c.feed(s_bytes);
+        }
+        if i % 7 != 0 {
+            c.feed(p_bytes);
+        }
+        c.feed(if i & 1 != 0 { &a[..n] } else { p_bytes });
+        a = c.end();
+    }
+
+    let prefix = hash.len() - salted.len() + salt_len;
+    if prefix + 1 + H::ORDER.len() * 4 / 3 + 1 > MAX_HASH {
+        return Err("malformed SHA-crypt hash");
+    }
+    out[..prefix].copy_from_slice(&hash.as_bytes()[..prefix]);
+    out[prefix] = b'$';
+    let mut o = prefix + 1;
+    for group in H::ORDER.chunks(3) {
+        let mut v = 0u32;
+        for &i in group {
+            v = (v << 8) | a[i as usize] as u32;
+        }
+        for _ in 0..group.len() + 1 {
+            out[o] = CRYPT64[(v & 63) as usize];
+            v >>= 6;
+            o += 1;
+        }
+    }
+    Ok(o)
+}
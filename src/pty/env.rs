@@ -0,0 +1,107 @@
+//! Environment block handed to the shell's `execve`.
+
+const BUF_LEN: usize = 16 * 1024;
+const MAX_VARS: usize = 64;
+
+/// Size of the array [`Environment::envp`] fills.
+pub const ENVP_LEN: usize = MAX_VARS + 1;
+
+/// `NAME=value` strings packed into one NUL-separated buffer. Setting a name
+/// again replaces its earlier value, so sources can be layered from lowest
+/// to highest precedence.
+pub struct Environment {
+    buf: [u8; BUF_LEN],
+    used: usize,
+    /// Offset and length (without the NUL) of each entry in `buf`.
+    vars: [(usize, usize); MAX_VARS],
+    count: usize,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self {
+            buf: [0; BUF_LEN],
+            used: 0,
+            vars: [(0, 0); MAX_VARS],
+            count: 0,
+        }
+    }
+
+    pub fn set(&mut self, name: &[u8], value: &[u8]) -> Result<(), &'static str> {
+        if !is_valid_name(name) {
+            return Err("bad variable name");
+        }
+        if value.contains(&0) {
+            return Err("NUL in value");
+        }
+        let len = name.len() + 1 + value.len();
+        if self.used + len + 1 > BUF_LEN {
+            return Err("environment too large");
+        }
+        let slot = match self.vars[..self.count]
+            .iter()
+            .position(|&(off, n)| self.name_at(off, n) == name)
+        {
+            Some(i) => i,
+            None if self.count < MAX_VARS => {
+                self.count += 1;
+                self.count - 1
+            }
+            None => return Err("too many variables"),
+        };
+        let off = self.used;
+        self.buf[off..off + name.len()].copy_from_slice(name);
+        self.buf[off + name.len()] = b'=';
+        self.buf[off + name.len() + 1..off + len].copy_from_slice(value);
+        self.buf[off + len] = 0;
+        self.used += len + 1;
+        self.vars[slot] = (off, len);
+        Ok(())
+    }
+
+    /// Set from a `NAME=value` entry.
+    pub fn set_entry(&mut self, entry: &[u8]) -> Result<(), &'static str> {
+        let eq = entry
+            .iter()
+            .position(|&b| b == b'=')
+            .ok_or("expected NAME=value")?;
+        self.set(&entry[..eq], &entry[eq + 1..])
+    }
+
+    /// The value `name` is set to, if any.
+    pub fn get(&self, name: &[u8]) -> Option<&[u8]> {
+        self.vars[..self.count]
+            .iter()
+            .find(|&&(off, n)| self.name_at(off, n) == name)
+            .map(|&(off, n)| &self.buf[off + name.len() + 1..off + n])
+    }
+
+    /// Fill `out` with a NULL-terminated `envp` array pointing into `self`.
+    pub fn envp(&self, out: &mut [*const u8; ENVP_LEN]) -> *const *const u8 {
+        for (slot, &(off, _)) in out.iter_mut().zip(&self.vars[..self.count]) {
+            *slot = self.buf[off..].as_ptr();
+        }
+        out[self.count] = core::ptr::null();
+        out.as_ptr()
+    }
+
+    fn name_at(&self, off: usize, len: usize) -> &[u8] {
+        let entry = &self.buf[off..off + len];
+        let eq = entry.iter().position(|&b| b == b'=').unwrap_or(len);
+        &entry[..eq]
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Portable variable name: letters, digits and `_`, not starting with a
+/// digit.
+pub fn is_valid_name(name: &[u8]) -> bool {
+    !name.is_empty()
+        && !name[0].is_ascii_digit()
+        && name.iter().all(|&b| b.is_ascii_alphanumeric() || b == b'_')
+}
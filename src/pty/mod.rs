@@ -1,3 +1,5 @@
+mod env;
 #[allow(clippy::module_inception)]
 mod pty;
-pub use pty::spawn_sh;
+pub use env::{Environment, is_valid_name};
+pub use pty::{Pty, spawn};
@@ -1,5 +1,6 @@
 #![allow(clippy::manual_c_str_literals)]
 
+use super::env::{ENVP_LEN, Environment};
 use crate::sys::pty as sys;
 
 pub struct Pty {
@@ -7,11 +8,45 @@ pub struct Pty {
     pub child_pid: i32,
 }
 
-pub fn spawn_sh() -> Result<Pty, &'static str> {
+const ARGV_BUF_LEN: usize = 4096;
+const MAX_ARGS: usize = 32;
+
+/// Run `program` on a new PTY with `args` (split on whitespace) in `cwd`
+/// (empty keeps the current directory).
+pub fn spawn(
+    program: &str,
+    args: &str,
+    cwd: &str,
+    env: &Environment,
+    size: Option<sys::WinSize>,
+) -> Result<Pty, &'static str> {
+    // argv strings and cwd, NUL-terminated, packed into one buffer before
+    // forking.
+    let mut strings = [0u8; ARGV_BUF_LEN];
+    let mut offsets = [0usize; MAX_ARGS];
+    let mut argc = 0;
+    let mut used = 0;
+    for arg in core::iter::once(program).chain(args.split_ascii_whitespace()) {
+        if argc == MAX_ARGS || used + arg.len() + 1 > strings.len() {
+            return Err("argv too long");
+        }
+        strings[used..used + arg.len()].copy_from_slice(arg.as_bytes());
+        offsets[argc] = used;
+        argc += 1;
+        used += arg.len() + 1;
+    }
+    let mut dir = [0u8; 256];
+    if cwd.len() >= dir.len() {
+        return Err("cwd path");
+    }
+    dir[..cwd.len()].copy_from_slice(cwd.as_bytes());
     let mfd = sys::open_ptmx().map_err(|_| "ptmx")?;
     let n = sys::pts_number(mfd).map_err(|_| "ptsnum")?;
     sys::grantpt(mfd).map_err(|_| "grant")?;
     sys::unlockpt(mfd).map_err(|_| "unlock")?;
+    if let Some(ws) = size {
+        sys::set_winsize(mfd, &ws).map_err(|_| "winsize")?;
+    }
     let sfd = sys::open_pts(n).map_err(|_| "open pts")?;
     let (rfd, wfd) = sys::pipe2(0).map_err(|_| "pipe")?;
     let pid = sys::fork().map_err(|_| "fork")?;
@@ -26,6 +61,11 @@ pub fn spawn_sh() -> Result<Pty, &'static str> {
         eb.copy_from_slice(&errno.to_le_bytes());
         let _ = crate::sys::fs::write(wfd, &eb);
         let _ = crate::sys::fs::close(wfd);
+        // The worker blocks SIGINT/SIGTERM/SIGCHLD for its signalfd; a blocked
+        // mask survives execve, so clear it or the line discipline's signals
+        // never reach the shell's foreground job.
+        crate::sys::signal::reset_signal_dispositions();
+        let _ = crate::sys::signal::unblock_all_signals();
         let _ = sys::setsid();
         let _ = sys::ioctl_set_ctty(sfd);
         let _ = sys::dup2(sfd, 0);
@@ -38,15 +78,25 @@ pub fn spawn_sh() -> Result<Pty, &'static str> {
             }
             fdc += 1;
         }
-        let argv = [b"/bin/sh\0".as_ptr(), core::ptr::null()];
-        let envp = [core::ptr::null()];
-        sys::execve(b"/bin/sh\0".as_ptr(), argv.as_ptr(), envp.as_ptr());
+        if !cwd.is_empty() && crate::sys::fs::chdir(dir.as_ptr()).is_err() {
+            let _ = crate::sys::fs::write(2, b"cannot change directory\r\n");
+            sys::exit(1);
+        }
+        let mut argv = [core::ptr::null(); MAX_ARGS + 1];
+        for (slot, &off) in argv.iter_mut().zip(&offsets[..argc]) {
+            *slot = strings[off..].as_ptr();
+        }
+        let mut envp = [core::ptr::null(); ENVP_LEN];
+        sys::execve(strings.as_ptr(), argv.as_ptr(), env.envp(&mut envp));
     }
     let _ = crate::sys::fs::close(wfd);
     let mut buf = [0u8; 8];
     let _ = crate::sys::fs::read(rfd, &mut buf);
     let _ = crate::sys::fs::close(rfd);
     let _ = sys::tcsetpgrp(sfd, pid as i32);
+    // Only the shell may hold the slave open: once it exits, reads on the
+    // master fail with EIO and the bridge knows the session is over.
+    let _ = crate::sys::fs::close(sfd);
     Ok(Pty {
         master_fd: mfd,
         child_pid: pid as i32,
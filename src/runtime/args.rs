@@ -0,0 +1,64 @@
+//! Process arguments and environment as laid out by the kernel on the
+//! initial stack: `argc`, `argv[0..argc]`, NULL, `envp[..]`, NULL.
+//!
+//! The strings live for the whole process lifetime, so they are handed out
+//! as `&'static [u8]` without the trailing NUL.
+
+#[derive(Copy, Clone)]
+pub struct Args {
+    argc: usize,
+    argv: *const *const u8,
+    envp: *const *const u8,
+}
+
+impl Args {
+    /// # Safety
+    /// `sp` must be the stack pointer the kernel handed to `_start`.
+    pub(crate) unsafe fn from_stack(sp: *const usize) -> Self {
+        unsafe {
+            let argc = *sp;
+            let argv = sp.add(1) as *const *const u8;
+            let envp = argv.add(argc + 1);
+            Self { argc, argv, envp }
+        }
+    }
+
+    /// The `i`-th command line argument.
+    pub fn arg(&self, i: usize) -> Option<&'static [u8]> {
+        if i >= self.argc {
+            return None;
+        }
+        Some(cstr(unsafe { *self.argv.add(i) }))
+    }
+
+    /// The `i`-th `NAME=value` environment entry.
+    pub fn env(&self, i: usize) -> Option<&'static [u8]> {
+        let p = unsafe { *self.envp.add(i) };
+        if p.is_null() {
+            return None;
+        }
+        Some(cstr(p))
+    }
+
+    /// Value of the environment variable `name`.
+    pub fn getenv(&self, name: &[u8]) -> Option<&'static [u8]> {
+        let mut i = 0;
+        while let Some(entry) = self.env(i) {
+            if entry.len() > name.len() && entry.starts_with(name) && entry[name.len()] == b'=' {
+                return Some(&entry[name.len() + 1..]);
+            }
+            i += 1;
+        }
+        None
+    }
+}
+
+fn cstr(p: *const u8) -> &'static [u8] {
+    let mut n = 0;
+    unsafe {
+        while *p.add(n) != 0 {
+            n += 1;
+        }
+        core::slice::from_raw_parts(p, n)
+    }
+}
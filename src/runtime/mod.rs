@@ -1,19 +1,25 @@
 pub mod allocator;
+pub mod args;
 pub mod panic;
 pub mod shims;
 pub mod syscall;
 pub mod util;
 
+/// Process entry. Nothing here applies relocations, so the binary must be
+/// linked without any (`relocation-model=static` in `.cargo/config.toml`).
+#[unsafe(naked)]
 #[unsafe(no_mangle)]
 pub extern "C" fn _start() -> ! {
-    unsafe {
-        core::arch::asm!(
-            "and rsp, ~0xF",
-            "call {main}",
-            main = sym crate::main,
-            options(noreturn)
-        );
-    }
+    core::arch::naked_asm!(
+        "mov rdi, rsp",
+        "and rsp, ~0xF",
+        "call {entry}",
+        entry = sym start_main,
+    );
+}
+
+extern "C" fn start_main(sp: *const usize) -> ! {
+    crate::main(unsafe { args::Args::from_stack(sp) })
 }
 
 #[inline(always)]
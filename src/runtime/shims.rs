@@ -8,6 +8,22 @@ pub extern "C" fn memcpy(dest: *mut u8, src: *const u8, n: usize) -> *mut u8 {
     dest
 }
 
+#[unsafe(no_mangle)]
+pub extern "C" fn memmove(dest: *mut u8, src: *const u8, n: usize) -> *mut u8 {
+    unsafe {
+        if (dest as usize) <= (src as usize) {
+            for i in 0..n {
+                *dest.add(i) = *src.add(i);
+            }
+        } else {
+            for i in (0..n).rev() {
+                *dest.add(i) = *src.add(i);
+            }
+        }
+    }
+    dest
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn memset(dest: *mut u8, c: i32, n: usize) -> *mut u8 {
     unsafe {
@@ -44,5 +60,18 @@ pub extern "C" fn bcmp(a: *const u8, b: *const u8, n: usize) -> i32 {
     0
 }
 
+#[unsafe(no_mangle)]
+pub extern "C" fn strlen(s: *const u8) -> usize {
+    let mut n = 0;
+    // Volatile reads keep the optimizer from turning this loop back into a
+    // call to `strlen`.
+    unsafe {
+        while core::ptr::read_volatile(s.add(n)) != 0 {
+            n += 1;
+        }
+    }
+    n
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn rust_eh_personality() {}
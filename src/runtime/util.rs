@@ -22,3 +22,12 @@ pub fn ptr_to_mut_slice<'a>(ptr: *mut u8, len: usize) -> &'a mut [u8] {
 pub fn ptr_to_slice<'a>(ptr: *mut u8, len: usize) -> &'a [u8] {
     unsafe { core::slice::from_raw_parts(ptr, len) }
 }
+
+/// Typed variant of [`ptr_to_mut_slice`] for page-allocated tables.
+///
+/// # Safety
+/// As for [`ptr_to_mut_slice`]; additionally `ptr` must be aligned for `T`
+/// and the memory must hold valid `T` values (all-zero for plain data).
+pub fn ptr_to_mut_slice_of<'a, T>(ptr: *mut T, len: usize) -> &'a mut [T] {
+    unsafe { core::slice::from_raw_parts_mut(ptr, len) }
+}
@@ -0,0 +1,48 @@
+//! Files the page loads, embedded in the binary so it works without
+//! internet access. `assets/static/` holds vendored xterm.js releases
+//! (refreshed with `scripts/vendor-xterm.sh`).
+
+pub(crate) struct Asset {
+    pub(crate) path: &'static str,
+    pub(crate) body: &'static [u8],
+}
+
+static ASSETS: &[Asset] = &[
+    Asset {
+        path: "/static/xterm.js",
+        body: include_bytes!("../../assets/static/xterm.js"),
+    },
+    Asset {
+        path: "/static/xterm.css",
+        body: include_bytes!("../../assets/static/xterm.css"),
+    },
+    Asset {
+        path: "/static/addon-fit.js",
+        body: include_bytes!("../../assets/static/addon-fit.js"),
+    },
+    Asset {
+        path: "/static/addon-webgl.js",
+        body: include_bytes!("../../assets/static/addon-webgl.js"),
+    },
+];
+
+pub(crate) fn find(path: &str) -> Option<&'static Asset> {
+    ASSETS.iter().find(|a| a.path == path)
+}
+
+impl Asset {
+    /// Strong validator: a quoted FNV-1a hash of the contents, so it only
+    /// changes when the embedded file does.
+    pub(crate) fn etag(&self) -> [u8; 18] {
+        let mut h: u64 = 0xcbf29ce484222325;
+        for &b in self.body {
+            h = (h ^ b as u64).wrapping_mul(0x100000001b3);
+        }
+        let mut tag = [b'"'; 18];
+        for (i, d) in tag[1..17].iter_mut().enumerate() {
+            let nibble = (h >> (60 - 4 * i)) & 0xf;
+            *d = b"0123456789abcdef"[nibble as usize];
+        }
+        tag
+    }
+}
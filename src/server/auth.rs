@@ -0,0 +1,530 @@
+//! Credentials guarding terminal sessions and, with an htpasswd file, the
+//! page itself.
+//!
+//! `token-file` holds one bearer token per line; `htpasswd-file` holds
+//! `user:hash` lines (bcrypt, SHA-512-crypt or SHA-256-crypt, as written
+//! by `htpasswd -B` or `mkpasswd`). Blank lines and `#` comments are
+//! skipped in both. Each file is looked at again on every check and
+//! re-read when it changed, so credentials can be added or revoked without
+//! a restart. A client presents a token as `Authorization: Bearer TOKEN`,
+//! as `?token=TOKEN` or in the cookie that `/login` sets, and a password as
+//! `Authorization: Basic`; either kind is enough when both are configured.
+//!
+//! Passwords are checked in the parent, so each check holds up the accept
+//! loop: hashes asking for more work than `bcrypt-max-cost` or
+//! `sha-crypt-max-rounds` are refused, and a peer that keeps getting a
+//! user's password wrong is turned away unchecked for a while. Failures
+//! are counted per peer and user name, so clients sharing an address (all
+//! of them, behind a proxy on the Unix socket) cannot lock each other out.
+
+use crate::config::Config;
+use crate::net::addr::IpAddr;
+use crate::net::http::{self, Request};
+use crate::net::stream::Stream;
+use crate::net::tls::Sha256;
+use crate::net::ws::CostLimits;
+use crate::runtime::{allocator, util};
+use crate::sys;
+
+const MAX_FILE_LEN: usize = 16 * 1024;
+const MAX_PATH_LEN: usize = 255;
+pub(crate) const MAX_TOKEN: usize = 256;
+pub(crate) const COOKIE: &str = "xterm_token";
+/// Basic credentials remembered after a successful check, so the page and
+/// its assets do not pay for a bcrypt run each.
+const VERIFIED_SLOTS: usize = 8;
+/// Wrong passwords a peer may send for one user within
+/// `FAILURE_WINDOW_MS` of its first; after that its Basic credentials for
+/// that user are refused unchecked until the window ends.
+const MAX_FAILURES: u32 = 5;
+const FAILURE_WINDOW_MS: u64 = 60_000;
+const RETRY_AFTER: &[u8] = b"Retry-After: 60\r\n";
+/// Peer and user pairs whose failures are counted; the one counting
+/// longest gives way.
+const FAILURE_SLOTS: usize = 64;
+
+/// Why a request's credentials were refused.
+pub(crate) enum Denied {
+    Missing,
+    InvalidToken,
+    InvalidPassword,
+    TooManyFailures,
+}
+
+impl Denied {
+    pub(crate) fn reason(&self) -> &'static [u8] {
+        match self {
+            Denied::Missing => b"no credentials",
+            Denied::InvalidToken => b"invalid token",
+            Denied::InvalidPassword => b"invalid user or password",
+            Denied::TooManyFailures => b"too many failed logins",
+        }
+    }
+
+    pub(crate) fn status(&self) -> &'static [u8] {
+        match self {
+            Denied::TooManyFailures => b"429 Too Many Requests",
+            _ => b"401 Unauthorized",
+        }
+    }
+}
+
+/// A configured credentials file and its contents as last read.
+struct Watched {
+    name: &'static [u8],
+    path: &'static str,
+    buf: *mut u8,
+    len: usize,
+    /// Inode, size and modification time of the file as last read.
+    stamp: Option<(u64, u64, i64, i64)>,
+    /// Checks a non-blank, non-comment line, for the load-time log: what
+    /// is wrong with it, if anything.
+    valid: fn(&[u8], &CostLimits) -> Result<(), &'static [u8]>,
+    limits: CostLimits,
+}
+
+impl Watched {
+    fn open(
+        name: &'static [u8],
+        path: &'static str,
+        valid: fn(&[u8], &CostLimits) -> Result<(), &'static [u8]>,
+        limits: CostLimits,
+    ) -> Result<Option<Self>, &'static str> {
+        if path.is_empty() {
+            return Ok(None);
+        }
+        if path.len() > MAX_PATH_LEN {
+            return Err("path too long");
+        }
+        let mut file = Self {
+            name,
+            path,
+            buf: allocator::page_alloc(MAX_FILE_LEN).map_err(|_| "out of memory")?,
+            len: 0,
+            stamp: None,
+            valid,
+            limits,
+        };
+        file.reload()?;
+        Ok(Some(file))
+    }
+
+    /// Re-read the file if it changed; `true` if it did. If it cannot be
+    /// read, forget its contents, so every credential is refused until it
+    /// can be again.
+    fn refresh(&mut self) -> bool {
+        match self.reload() {
+            Ok(changed) => changed,
+            Err(msg) => {
+                if self.stamp.take().is_some() {
+                    super::log(self.name);
+                    super::log(b": ");
+                    super::log(msg.as_bytes());
+                    super::log(b"; refusing every credential\n");
+                }
+                self.len = 0;
+                true
+            }
+        }
+    }
+
+    fn reload(&mut self) -> Result<bool, &'static str> {
+        let mut cpath = [0u8; MAX_PATH_LEN + 1];
+        cpath[..self.path.len()].copy_from_slice(self.path.as_bytes());
+        let fd = sys::fs::open(cpath.as_ptr(), sys::fs::O_RDONLY | sys::fs::O_CLOEXEC, 0)
+            .map_err(|_| "cannot open file")?;
+        let res = self.read_changed(fd);
+        let _ = sys::fs::close(fd);
+        res
+    }
+
+    fn read_changed(&mut self, fd: usize) -> Result<bool, &'static str> {
+        let st = sys::fs::fstat(fd).map_err(|_| "cannot stat file")?;
+        let stamp = (st.ino, st.size, st.mtime, st.mtime_nsec);
+        if self.stamp == Some(stamp) {
+            return Ok(false);
+        }
+        let buf = util::ptr_to_mut_slice(self.buf, MAX_FILE_LEN);
+        let mut len = 0;
+        loop {
+            if len == buf.len() {
+                return Err("file too large");
+            }
+            match sys::fs::read(fd, &mut buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(_) => return Err("read failed"),
+            }
+        }
+        let reloaded = self.stamp.is_some();
+        self.len = len;
+        self.stamp = Some(stamp);
+        for (i, line) in buf[..len].split(|&b| b == b'\n').enumerate() {
+            let line = line.trim_ascii();
+            if line.is_empty() || line.starts_with(b"#") {
+                continue;
+            }
+            if let Err(what) = (self.valid)(line, &self.limits) {
+                super::log(self.name);
+                super::log(b": ignoring ");
+                super::log(what);
+                super::log(b" ");
+                super::log_num(i as i32 + 1);
+                super::log(b"\n");
+            }
+        }
+        if reloaded {
+            super::log(self.name);
+            super::log(b" reloaded\n");
+        }
+        Ok(true)
+    }
+
+    /// Non-blank, non-comment lines, trimmed.
+    fn lines(&self) -> impl Iterator<Item = &[u8]> + Clone {
+        util::ptr_to_slice(self.buf, self.len)
+            .split(|&b| b == b'\n')
+            .map(|line| line.trim_ascii())
+            .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+    }
+}
+
+/// `token-file` and `htpasswd-file`, either of which may be unset.
+pub(crate) struct Auth {
+    tokens: Option<Watched>,
+    users: Option<Watched>,
+    /// SHA-256 of `user:password` for recently verified Basic credentials,
+    /// forgotten whenever the htpasswd file changes.
+    verified: [[u8; 32]; VERIFIED_SLOTS],
+    verified_len: usize,
+    verified_next: usize,
+    failures: [Failures; FAILURE_SLOTS],
+}
+
+/// Wrong passwords from one peer for one user name, known or not.
+#[derive(Copy, Clone)]
+struct Failures {
+    /// SHA-256 of the peer (see `failure_key`) and the name.
+    key: [u8; 32],
+    /// Zero for a free slot.
+    count: u32,
+    since: u64,
+}
+
+impl Auth {
+    /// Read the configured files. The error names the setting at fault.
+    pub(crate) fn new(cfg: &Config) -> Result<Self, (&'static str, &'static str)> {
+        let limits = cfg.hash_limits;
+        let tokens = Watched::open(b"token-file", cfg.token_file, check_token, limits)
+            .map_err(|msg| ("token-file", msg))?;
+        let users = Watched::open(b"htpasswd-file", cfg.htpasswd_file, check_user, limits)
+            .map_err(|msg| ("htpasswd-file", msg))?;
+        // Later edits are only logged: the server is up by then.
+        if let Some(users) = &users
+            && users
+                .lines()
+                .any(|l| check_user(l, &limits) == Err(TOO_COSTLY))
+        {
+            return Err((
+                "htpasswd-file",
+                "hash above bcrypt-max-cost or sha-crypt-max-rounds",
+            ));
+        }
+        Ok(Self {
+            tokens,
+            users,
+            verified: [[0; 32]; VERIFIED_SLOTS],
+            verified_len: 0,
+            verified_next: 0,
+            failures: [Failures {
+                key: [0; 32],
+                count: 0,
+                since: 0,
+            }; FAILURE_SLOTS],
+        })
+    }
+
+    /// Whether plain requests need credentials too, not just upgrades.
+    pub(crate) fn guards_documents(&self) -> bool {
+        self.users.is_some()
+    }
+
+    /// `WWW-Authenticate` lines to answer `denied` with, or when to come
+    /// back.
+    pub(crate) fn challenge(&self, denied: &Denied) -> &'static [u8] {
+        if matches!(denied, Denied::TooManyFailures) {
+            return RETRY_AFTER;
+        }
+        http::challenge(
+            self.users.is_some(),
+            self.tokens.is_some(),
+            matches!(denied, Denied::InvalidToken),
+        )
+    }
+
+    /// Check the credentials `req` presents on connection `fd`. On
+    /// success, returns the token if one came from the header or query
+    /// string, for `/login` to put in a cookie; `None` for the cookie
+    /// itself, a password, or when nothing is configured.
+    pub(crate) fn check<'b>(
+        &mut self,
+        req: &Request,
+        fd: usize,
+        buf: &'b mut [u8; MAX_TOKEN],
+    ) -> Result<Option<&'b [u8]>, Denied> {
+        if self.tokens.is_none() && self.users.is_none() {
+            return Ok(None);
+        }
+        if self.users.is_some() && req.authorization("Basic").is_some() {
+            return self.check_password(req, fd).map(|()| None);
+        }
+        let Some(tokens) = &mut self.tokens else {
+            return Err(Denied::Missing);
+        };
+        let (token, from_cookie) = presented(req, buf).ok_or(Denied::Missing)?;
+        tokens.refresh();
+        // Compare with every entry, so the time taken does not tell which
+        // one (if any) matched.
+        let found = tokens
+            .lines()
+            .filter_map(token_entry)
+            .fold(false, |found, expected| found | same(expected, token));
+        if !found {
+            return Err(Denied::InvalidToken);
+        }
+        Ok(if from_cookie { None } else { Some(token) })
+    }
+
+    fn check_password(&mut self, req: &Request, fd: usize) -> Result<(), Denied> {
+        let Some(users) = &mut self.users else {
+            return Err(Denied::Missing);
+        };
+        if users.refresh() {
+            self.verified_len = 0;
+            self.verified_next = 0;
+        }
+        let mut buf = [0u8; 2 * crate::net::ws::MAX_PASSWORD];
+        let (user, password) =
+            http::basic_credentials(req, &mut buf).ok_or(Denied::InvalidPassword)?;
+        let mut digest = Sha256::new();
+        digest.update(user);
+        digest.update(b":");
+        digest.update(password);
+        let digest = digest.finish();
+        let remembered = self.verified[..self.verified_len]
+            .iter()
+            .fold(false, |found, known| found | same(known, &digest));
+        if remembered {
+            return Ok(());
+        }
+        let key = failure_key(super::peer_ip(fd), user);
+        let now = sys::timer::monotonic_ms();
+        let slot = self
+            .failures
+            .iter()
+            .position(|f| f.count > 0 && f.key == key && now - f.since < FAILURE_WINDOW_MS);
+        if slot.is_some_and(|i| self.failures[i].count >= MAX_FAILURES) {
+            return Err(Denied::TooManyFailures);
+        }
+        // An unknown name is checked against some other entry's hash, so
+        // it takes about as long to refuse as a wrong password.
+        let (known, matched) = {
+            let limits = users.limits;
+            let mut entries = users
+                .lines()
+                .filter(|l| check_user(l, &limits).is_ok())
+                .filter_map(user_entry);
+            let (hash, known) = match entries.clone().find(|&(name, _)| name == user) {
+                Some((_, hash)) => (hash, true),
+                None => match entries.next() {
+                    Some((_, hash)) => (hash, false),
+                    None => return Err(Denied::InvalidPassword),
+                },
+            };
+            let matched = crate::net::ws::verify_password(password, hash).unwrap_or(false);
+            (known, matched)
+        };
+        if !(known && matched) {
+            self.count_failure(slot, key, now);
+            return Err(Denied::InvalidPassword);
+        }
+        if let Some(i) = slot {
+            self.failures[i].count = 0;
+        }
+        self.verified[self.verified_next] = digest;
+        self.verified_next = (self.verified_next + 1) % VERIFIED_SLOTS;
+        self.verified_len = core::cmp::min(self.verified_len + 1, VERIFIED_SLOTS);
+        Ok(())
+    }
+
+    /// Count a wrong password under `key`, in its live `slot` if it has
+    /// one; otherwise in a free or expired slot, or the oldest.
+    fn count_failure(&mut self, slot: Option<usize>, key: [u8; 32], now: u64) {
+        if let Some(i) = slot {
+            self.failures[i].count += 1;
+            return;
+        }
+        let i = self
+            .failures
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, f)| {
+                if f.count == 0 || now - f.since >= FAILURE_WINDOW_MS {
+                    0
+                } else {
+                    f.since + 1
+                }
+            })
+            .map_or(0, |(i, _)| i);
+        self.failures[i] = Failures {
+            key,
+            count: 1,
+            since: now,
+        };
+    }
+}
+
+/// Status and headers a request is refused with.
+type Refusal = (&'static [u8], &'static [u8]);
+
+/// Answer the page's `/login` check: 204, setting the cookie when a token
+/// came some other way, or the refusal (401 with a challenge, or 429).
+pub(crate) fn serve_login(
+    stream: &mut Stream,
+    req: &Request,
+    verdict: Result<Option<&[u8]>, Refusal>,
+) {
+    let mut headers = [0u8; 2 * MAX_TOKEN];
+    let mut n = 0;
+    let mut push = |part: &[u8]| {
+        headers[n..n + part.len()].copy_from_slice(part);
+        n += part.len();
+    };
+    let status: &[u8] = match verdict {
+        Ok(remember) => {
+            if let Some(token) = remember {
+                push(b"Set-Cookie: ");
+                push(COOKIE.as_bytes());
+                push(b"=");
+                push(token);
+                push(b"; Path=/; HttpOnly; SameSite=Strict");
+                if stream.is_tls() {
+                    push(b"; Secure");
+                }
+                push(b"\r\n");
+            }
+            b"204 No Content"
+        }
+        Err((status, challenge)) => {
+            push(challenge);
+            status
+        }
+    };
+    push(b"Cache-Control: no-store\r\n");
+    http::serve_status(stream, req, status, &headers[..n]);
+}
+
+const MALFORMED: &[u8] = b"malformed line";
+const TOO_COSTLY: &[u8] = b"too costly hash on line";
+
+fn check_token(line: &[u8], _: &CostLimits) -> Result<(), &'static [u8]> {
+    token_entry(line).map(|_| ()).ok_or(MALFORMED)
+}
+
+fn check_user(line: &[u8], limits: &CostLimits) -> Result<(), &'static [u8]> {
+    match user_entry(line) {
+        None => Err(MALFORMED),
+        Some((_, hash)) if !limits.allow(hash) => Err(TOO_COSTLY),
+        Some(_) => Ok(()),
+    }
+}
+
+/// The token on a line of the token file; `None` for anything that could
+/// not travel in a cookie.
+fn token_entry(line: &[u8]) -> Option<&[u8]> {
+    if line.len() > MAX_TOKEN {
+        return None;
+    }
+    line.iter().all(|&b| is_token_byte(b)).then_some(line)
+}
+
+/// Name and hash on a line of the htpasswd file, if the hash is one
+/// `verify_password` knows.
+fn user_entry(line: &[u8]) -> Option<(&[u8], &str)> {
+    let colon = line.iter().position(|&b| b == b':')?;
+    let hash = core::str::from_utf8(&line[colon + 1..]).ok()?;
+    let supported = ["$2y$", "$2b$", "$2a$", "$6$", "$5$"]
+        .iter()
+        .any(|p| hash.starts_with(p));
+    (colon > 0 && supported).then_some((&line[..colon], hash))
+}
+
+/// What wrong passwords for `user` are counted under: the peer, with IPv4
+/// addresses (plain or mapped) taken whole and IPv6 ones by /64, as a host
+/// may well have a whole /64 to pick from, or all zero for a Unix socket
+/// client; hashed with the name.
+fn failure_key(ip: Option<IpAddr>, user: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 16];
+    match ip {
+        Some(IpAddr::V4(v4)) => {
+            key[10..12].copy_from_slice(&[0xff, 0xff]);
+            key[12..].copy_from_slice(&v4);
+        }
+        Some(IpAddr::V6(v6)) if v6[..12] == [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff] => {
+            key = v6;
+        }
+        Some(IpAddr::V6(v6)) => key[..8].copy_from_slice(&v6[..8]),
+        None => {}
+    }
+    let mut digest = Sha256::new();
+    digest.update(&key);
+    digest.update(user);
+    digest.finish()
+}
+
+/// Printable ASCII other than space and the bytes a cookie value may not
+/// hold (`"`, `,`, `;`, `\`).
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_graphic() && !b"\",;\\".contains(&b)
+}
+
+/// Equality whose running time depends only on `presented`'s length.
+fn same(expected: &[u8], presented: &[u8]) -> bool {
+    let mut diff = (expected.len() != presented.len()) as u8;
+    for (i, &b) in presented.iter().enumerate() {
+        diff |= b ^ expected.get(i).copied().unwrap_or(0);
+    }
+    core::hint::black_box(diff) == 0
+}
+
+/// The token `req` carries, in order of preference: `Authorization:
+/// Bearer`, `?token=` or the cookie. The flag is set if it came from the
+/// cookie.
+fn presented<'b>(req: &Request, buf: &'b mut [u8; MAX_TOKEN]) -> Option<(&'b [u8], bool)> {
+    if let Some(token) = req.authorization("Bearer") {
+        return Some((keep(buf, token.as_bytes()), false));
+    }
+    if let Some(raw) = http::query_param(req.head, "token") {
+        let n = http::percent_decode(raw, buf).unwrap_or(0);
+        return Some((&buf[..n], false));
+    }
+    let cookies = req.header("Cookie")?;
+    let value = cookies
+        .split(';')
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|&(name, _)| name == COOKIE)?
+        .1;
+    Some((keep(buf, value.as_bytes()), true))
+}
+
+/// Copy `token` into `buf`; one too long to be an entry becomes empty,
+/// which matches nothing.
+fn keep<'b>(buf: &'b mut [u8; MAX_TOKEN], token: &[u8]) -> &'b [u8] {
+    let n = if token.len() <= MAX_TOKEN {
+        token.len()
+    } else {
+        0
+    };
+    buf[..n].copy_from_slice(&token[..n]);
+    &buf[..n]
+}
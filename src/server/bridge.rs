@@ -1,7 +1,53 @@
+use super::control;
+use super::recorder::Recorder;
+use super::scrollback::Scrollback;
+use super::sessions::{Handle, Join};
+use super::viewers::{Change, Viewers};
+use crate::config::Config;
+use crate::net::stream::Stream;
+use crate::net::ws::{self, FrameDecoder, Message};
+use crate::pty::Pty;
 use crate::runtime::util;
 use crate::sys;
+use crate::sys::pty::WaitStatus;
 
-pub(crate) fn run_bridge(ws_fd: usize, pty_fd: usize, child_pid: i32) -> Result<(), &'static str> {
+/// The browser currently attached to the session.
+struct Client {
+    stream: Stream,
+    decoder: FrameDecoder,
+    ping_seq: u64,
+    missed_pongs: u32,
+}
+
+/// How a client is let go: the close frame to send, if any, and whether to
+/// wait for the peer's answering close.
+struct Parting {
+    close: Option<(u16, &'static [u8])>,
+    await_peer: bool,
+}
+
+impl Parting {
+    /// The connection is unusable; just drop it.
+    const LOST: Parting = Parting {
+        close: None,
+        await_peer: false,
+    };
+}
+
+/// Bridge WebSocket clients and the shell's PTY until the shell exits, the
+/// server shuts down, or the client goes away and no other resumes the
+/// session within `session-grace`. Viewers get the same output meanwhile,
+/// participants' input is merged with the client's, and `recorder` keeps
+/// a copy of it all. Returns the shell's exit status if it terminated (and
+/// was reaped) while the session was running.
+pub(crate) fn run_bridge(
+    cfg: &Config,
+    stream: Stream,
+    shell: &Pty,
+    handle: &mut Handle,
+    mut recorder: Recorder,
+) -> Result<Option<WaitStatus>, &'static str> {
+    let pty_fd = shell.master_fd;
     let epfd = sys::epoll::epoll_create1().map_err(|_| "epoll")?;
     let mut mask: u64 = 0;
     mask |= 1u64 << (2 - 1);
@@ -11,14 +57,34 @@ pub(crate) fn run_bridge(ws_fd: usize, pty_fd: usize, child_pid: i32) -> Result<
         Ok(fd) => fd,
         Err(_) => usize::MAX,
     };
-    sys::epoll::epoll_add(epfd, ws_fd, sys::epoll::EPOLLIN).map_err(|_| "epoll add ws")?;
     sys::epoll::epoll_add(epfd, pty_fd, sys::epoll::EPOLLIN).map_err(|_| "epoll add pty")?;
     if sfd != usize::MAX {
         sys::epoll::epoll_add(epfd, sfd, sys::epoll::EPOLLIN).map_err(|_| "epoll add signalfd")?;
     }
+    sys::epoll::epoll_add(epfd, handle.ctl, sys::epoll::EPOLLIN).map_err(|_| "epoll add ctl")?;
 
-    let buf_len = 64 * 1024;
-    let scratch_len = 64 * 1024;
+    // Keepalive: one ping per interval; a client is dropped once
+    // `ping_max_missed` pings in a row went unanswered.
+    let tfd = if cfg.ping_interval > 0 {
+        let tfd = sys::timer::timerfd_create().map_err(|_| "timerfd")?;
+        sys::timer::timerfd_set_interval(tfd, cfg.ping_interval as u64 * 1000)
+            .map_err(|_| "timerfd set")?;
+        sys::epoll::epoll_add(epfd, tfd, sys::epoll::EPOLLIN).map_err(|_| "epoll add timer")?;
+        tfd
+    } else {
+        usize::MAX
+    };
+    // Armed while no client is attached; the shell is hung up when it
+    // fires.
+    let grace_fd = if cfg.session_grace > 0 {
+        let fd = sys::timer::timerfd_create().map_err(|_| "timerfd")?;
+        sys::epoll::epoll_add(epfd, fd, sys::epoll::EPOLLIN).map_err(|_| "epoll add timer")?;
+        fd
+    } else {
+        usize::MAX
+    };
+
+    let buf_len = cfg.buffer_size;
     let buf_ptr = match crate::runtime::allocator::page_alloc(buf_len) {
         Ok(p) => p,
         Err(_) => return Err("mmap buf"),
@@ -26,104 +92,481 @@ pub(crate) fn run_bridge(ws_fd: usize, pty_fd: usize, child_pid: i32) -> Result<
     if buf_ptr.is_null() {
         return Err("mmap buf null");
     }
-    let scratch_ptr = match crate::runtime::allocator::page_alloc(scratch_len) {
-        Ok(p) => p,
-        Err(_) => {
-            let _ = crate::runtime::allocator::page_free(buf_ptr, buf_len);
-            return Err("mmap scratch");
-        }
-    };
-    if scratch_ptr.is_null() {
-        let _ = crate::runtime::allocator::page_free(buf_ptr, buf_len);
-        return Err("mmap scratch null");
-    }
+    let mut scrollback = Scrollback::new(cfg.scrollback)?;
+    let mut viewers = Viewers::new(cfg);
 
+    let mut client = Some(attach(epfd, stream, cfg, handle, &scrollback, &viewers)?);
     let mut events = [sys::epoll::EpollEvent::default(); 32];
-    let mut should_exit = false;
-    let mut result: Result<(), &'static str> = Ok(());
+    // Close frame for the client still attached when the session ends.
+    let mut close: Option<(u16, &[u8])> = None;
+    let mut exit_status: Option<WaitStatus> = None;
 
-    loop {
+    'run: loop {
+        announce(epfd, &mut viewers, &mut client);
         let n = match sys::epoll::epoll_wait(epfd, &mut events, -1) {
             Ok(v) => v,
             Err(_) => {
-                result = Err("wait");
+                close = Some((ws::CLOSE_INTERNAL_ERROR, b"internal error"));
                 break;
             }
         };
         for event in events.iter().take(n) {
             let fd = event.fd();
             if fd == sfd {
-                let _ = crate::sys::pty::kill(child_pid, 2);
-                should_exit = true;
-                break;
+                close = Some((ws::CLOSE_GOING_AWAY, b"server shutting down"));
+                break 'run;
+            }
+            if fd == tfd {
+                let _ = sys::timer::timerfd_read(tfd);
+                viewers.ping(epfd, cfg.ping_max_missed);
+                if let Some(c) = &mut client
+                    && let Some(parting) = c.ping(cfg.ping_max_missed)
+                {
+                    if !detach(epfd, &mut client, parting, cfg, grace_fd) {
+                        break 'run;
+                    }
+                    continue 'run;
+                }
+                continue;
+            }
+            if fd == grace_fd {
+                let _ = sys::timer::timerfd_read(grace_fd);
+                if client.is_none() {
+                    super::log(b"session expired\n");
+                    close = Some((ws::CLOSE_GOING_AWAY, b"session expired"));
+                    break 'run;
+                }
+                continue;
+            }
+            if fd == handle.ctl {
+                match handle.receive() {
+                    Ok(Some((stream, join @ (Join::Watch | Join::Collaborate)))) => {
+                        let size = sys::pty::get_winsize(pty_fd).unwrap_or_default();
+                        let write = matches!(join, Join::Collaborate);
+                        viewers.add(epfd, stream, write, &size, &scrollback);
+                    }
+                    Ok(Some((stream, Join::Resume(size)))) => {
+                        if let Some(c) = client.take() {
+                            let close =
+                                Some((ws::CLOSE_GOING_AWAY, &b"session resumed elsewhere"[..]));
+                            part(epfd, c, close, false, cfg.close_timeout);
+                        }
+                        if let Some(ws) = size {
+                            resize(epfd, pty_fd, &ws, &mut viewers, &mut recorder);
+                        }
+                        match attach(epfd, stream, cfg, handle, &scrollback, &viewers) {
+                            Ok(c) => {
+                                client = Some(c);
+                                if grace_fd != usize::MAX {
+                                    let _ = sys::timer::timerfd_set_once(grace_fd, 0);
+                                }
+                                super::log(b"session resumed\n");
+                            }
+                            Err(_) => {
+                                if !detach(epfd, &mut client, Parting::LOST, cfg, grace_fd) {
+                                    break 'run;
+                                }
+                            }
+                        }
+                    }
+                    // The server is gone; nothing can resume the session now.
+                    Ok(None) => {
+                        let _ = sys::epoll::epoll_del(epfd, handle.ctl);
+                        let _ = sys::fs::close(handle.ctl);
+                        handle.ctl = usize::MAX;
+                    }
+                    Err(e) => {
+                        super::log(b"session handoff failed: ");
+                        super::log(e.as_bytes());
+                        super::log(b"\n");
+                    }
+                }
+                // The batch may name a descriptor number that now belongs
+                // to another connection; poll afresh.
+                continue 'run;
             }
             if fd == pty_fd {
                 let r = match sys::fs::read(pty_fd, util::ptr_to_mut_slice(buf_ptr, buf_len)) {
                     Ok(v) => v,
+                    // EIO: every slave fd is closed, i.e. the shell exited.
+                    Err(-5) => 0,
                     Err(_) => {
-                        result = Err("pty read");
-                        should_exit = true;
-                        break;
+                        close = Some((ws::CLOSE_INTERNAL_ERROR, b"pty read failed"));
+                        break 'run;
                     }
                 };
                 if r == 0 {
-                    should_exit = true;
-                    break;
-                }
-                let slice = util::ptr_to_slice(buf_ptr, r);
-                if crate::net::ws::write_binary_frame(ws_fd, slice).is_err() {
-                    result = Err("ws write");
-                    should_exit = true;
-                    break;
+                    exit_status = super::wait_shell(shell.child_pid, 500);
+                    close = Some((ws::CLOSE_NORMAL, b"shell exited"));
+                    break 'run;
                 }
-            } else if fd == ws_fd {
-                let r = match sys::net::recv(ws_fd, util::ptr_to_mut_slice(buf_ptr, buf_len)) {
-                    Ok(v) => v,
-                    Err(_) => {
-                        result = Err("ws read");
-                        should_exit = true;
-                        break;
+                let output = util::ptr_to_slice(buf_ptr, r);
+                scrollback.push(output);
+                recorder.output(output);
+                viewers.broadcast(epfd, ws::OPCODE_BINARY, output);
+                if let Some(c) = &mut client
+                    && ws::write_binary_frame(&mut c.stream, output).is_err()
+                {
+                    if !detach(epfd, &mut client, Parting::LOST, cfg, grace_fd) {
+                        break 'run;
                     }
-                };
-                if r == 0 {
-                    should_exit = true;
-                    break;
+                    continue 'run;
                 }
-                let input = util::ptr_to_slice(buf_ptr, r);
-                let out = util::ptr_to_mut_slice(scratch_ptr, scratch_len);
-                match crate::net::ws::parse_and_unmask_frames(input, out) {
-                    Ok(payload) => {
-                        let mut saw_sigint = false;
-                        for &b in payload {
-                            if b == 0x03 {
-                                saw_sigint = true;
-                                break;
+                continue;
+            }
+            if viewers.serve(epfd, fd, event.events(), pty_fd, &mut recorder) {
+                continue;
+            }
+            if let Some(c) = &mut client
+                && fd == c.stream.fd
+                && let Some(parting) = c.serve_input(epfd, pty_fd, &mut viewers, &mut recorder)
+            {
+                if !detach(epfd, &mut client, parting, cfg, grace_fd) {
+                    break 'run;
+                }
+                continue 'run;
+            }
+        }
+    }
+
+    let mut msg = [0u8; 96];
+    let mut reason = [0u8; 64];
+    let mut exit: &[u8] = &[];
+    if let Some(status) = exit_status {
+        let n = control::exit_message(status, &mut msg);
+        exit = &msg[..n];
+        let n = control::exit_reason(status, &mut reason);
+        close = Some((ws::CLOSE_NORMAL, &reason[..n]));
+    }
+    // Viewers first: they are not waited for, the client may be.
+    let ended = close.or(Some((ws::CLOSE_GOING_AWAY, &b"session ended"[..])));
+    viewers.close_all(epfd, exit, ended);
+    if let Some(mut c) = client.take() {
+        if !exit.is_empty() {
+            let _ = ws::write_frame(&mut c.stream, ws::OPCODE_TEXT, exit);
+        }
+        part(epfd, c, close, true, cfg.close_timeout);
+    }
+
+    let _ = crate::runtime::allocator::page_free(buf_ptr, buf_len);
+    for fd in [sfd, tfd, grace_fd] {
+        if fd != usize::MAX {
+            let _ = sys::fs::close(fd);
+        }
+    }
+    let _ = sys::fs::close(epfd);
+    recorder.finish();
+
+    Ok(exit_status)
+}
+
+/// Start serving `stream`, telling it how to resume, watch or join the
+/// session, replaying recent output to it and telling it who else is there.
+/// The stream is closed on failure.
+fn attach(
+    epfd: usize,
+    mut stream: Stream,
+    cfg: &Config,
+    handle: &Handle,
+    scrollback: &Scrollback,
+    viewers: &Viewers,
+) -> Result<Client, &'static str> {
+    let decoder = match FrameDecoder::new(cfg.max_message) {
+        Ok(d) => d,
+        Err(e) => {
+            stream.close();
+            return Err(e);
+        }
+    };
+    if cfg.session_grace > 0 || cfg.max_viewers > 0 {
+        let mut msg = [0u8; 160];
+        let n = control::session_message(
+            &handle.ids,
+            cfg.session_grace,
+            cfg.max_viewers > 0,
+            cfg.collaborate && cfg.max_viewers > 0,
+            &mut msg,
+        );
+        if ws::write_frame(&mut stream, ws::OPCODE_TEXT, &msg[..n]).is_err() {
+            stream.close();
+            return Err("ws write");
+        }
+    }
+    let (older, newer) = scrollback.replay();
+    for part in [older, newer] {
+        if !part.is_empty() && ws::write_binary_frame(&mut stream, part).is_err() {
+            stream.close();
+            return Err("ws write");
+        }
+    }
+    for (participant, write) in viewers.roster() {
+        let mut msg = [0u8; 64];
+        let n = control::participant_message(b"join", participant, Some(write), &mut msg);
+        if ws::write_frame(&mut stream, ws::OPCODE_TEXT, &msg[..n]).is_err() {
+            stream.close();
+            return Err("ws write");
+        }
+    }
+    if sys::epoll::epoll_add(epfd, stream.fd, sys::epoll::EPOLLIN).is_err() {
+        stream.close();
+        return Err("epoll add ws");
+    }
+    Ok(Client {
+        stream,
+        decoder,
+        ping_seq: 0,
+        missed_pongs: 0,
+    })
+}
+
+/// Let the attached client go. Without a grace period that ends the
+/// session (`false`); otherwise the shell keeps running until the grace
+/// timer fires or a resume arrives.
+fn detach(
+    epfd: usize,
+    client: &mut Option<Client>,
+    parting: Parting,
+    cfg: &Config,
+    grace_fd: usize,
+) -> bool {
+    if let Some(c) = client.take() {
+        part(
+            epfd,
+            c,
+            parting.close,
+            parting.await_peer,
+            cfg.close_timeout,
+        );
+    }
+    if grace_fd == usize::MAX
+        || sys::timer::timerfd_set_once(grace_fd, cfg.session_grace as u64 * 1000).is_err()
+    {
+        return false;
+    }
+    super::log(b"session detached\n");
+    true
+}
+
+/// Send `close` if given, wait for the peer's answer if `await_peer`, and
+/// hang up.
+fn part(epfd: usize, mut c: Client, close: Option<(u16, &[u8])>, await_peer: bool, timeout: u32) {
+    let _ = sys::epoll::epoll_del(epfd, c.stream.fd);
+    if let Some((code, reason)) = close
+        && ws::write_close_frame(&mut c.stream, code, reason).is_ok()
+        && await_peer
+    {
+        await_close(&mut c.stream, &mut c.decoder, timeout);
+    }
+    c.stream.shutdown();
+    c.stream.abandon();
+}
+
+impl Client {
+    /// One keepalive tick: ping the client, or give up on it once it has
+    /// stopped answering.
+    fn ping(&mut self, max_missed: u32) -> Option<Parting> {
+        if self.missed_pongs >= max_missed {
+            super::log(b"peer stopped answering pings\n");
+            return Some(Parting {
+                close: Some((ws::CLOSE_GOING_AWAY, b"keepalive timeout")),
+                await_peer: true,
+            });
+        }
+        self.ping_seq += 1;
+        if ws::write_frame(
+            &mut self.stream,
+            ws::OPCODE_PING,
+            &self.ping_seq.to_be_bytes(),
+        )
+        .is_err()
+        {
+            return Some(Parting::LOST);
+        }
+        self.missed_pongs += 1;
+        None
+    }
+
+    /// Handle what the client sent: input and resizes for the PTY (and
+    /// the viewers and the recording), revoked write access, pings and
+    /// pongs, and its close. `Some` when the client is done.
+    fn serve_input(
+        &mut self,
+        epfd: usize,
+        pty_fd: usize,
+        viewers: &mut Viewers,
+        recorder: &mut Recorder,
+    ) -> Option<Parting> {
+        let Client {
+            stream,
+            decoder,
+            ping_seq,
+            missed_pongs,
+        } = self;
+        // TLS may already hold further records, which epoll will not
+        // report again; keep reading until they are used up.
+        loop {
+            let r = match stream.recv(decoder.spare()) {
+                Ok(v) => v,
+                // Over TLS: a record is still incomplete.
+                Err(super::EAGAIN) => break,
+                Err(_) => return Some(Parting::LOST),
+            };
+            if r == 0 {
+                return Some(Parting::LOST);
+            }
+            decoder.fill(r);
+            loop {
+                match decoder.next_message() {
+                    Ok(None) => break,
+                    Ok(Some(Message::Text(msg))) => match control::parse(msg) {
+                        Some(control::Control::Resize { cols, rows }) => {
+                            let ws = sys::pty::WinSize::new(cols, rows);
+                            resize(epfd, pty_fd, &ws, viewers, recorder);
+                        }
+                        Some(control::Control::Revoke { participant })
+                            if viewers.revoke(participant) =>
+                        {
+                            super::log(b"write access revoked\n");
+                            let mut msg = [0u8; 64];
+                            let n = control::participant_message(
+                                b"revoke",
+                                participant,
+                                None,
+                                &mut msg,
+                            );
+                            viewers.broadcast(epfd, ws::OPCODE_TEXT, &msg[..n]);
+                            if ws::write_frame(stream, ws::OPCODE_TEXT, &msg[..n]).is_err() {
+                                return Some(Parting::LOST);
                             }
                         }
-                        if saw_sigint {
-                            let _ = crate::sys::pty::kill(child_pid, 2);
-                        } else {
-                            let _ = sys::fs::write(pty_fd, payload);
+                        _ => {}
+                    },
+                    Ok(Some(Message::Binary(payload))) => {
+                        // Input goes to the PTY verbatim; the line discipline
+                        // turns ^C, ^Z and ^\ into signals for the foreground
+                        // process group.
+                        recorder.input(payload);
+                        let _ = sys::fs::write_all(pty_fd, payload);
+                    }
+                    Ok(Some(Message::Ping(payload))) => {
+                        if ws::write_frame(stream, ws::OPCODE_PONG, payload).is_err() {
+                            return Some(Parting::LOST);
+                        }
+                    }
+                    Ok(Some(Message::Pong(payload))) => {
+                        if payload == ping_seq.to_be_bytes() {
+                            *missed_pongs = 0;
                         }
                     }
-                    Err("close") => {
-                        should_exit = true;
-                        break;
+                    Ok(Some(Message::Close(payload))) => {
+                        return Some(match ws::parse_close(payload) {
+                            Ok(code) => Parting {
+                                close: Some((code, b"")),
+                                await_peer: false,
+                            },
+                            Err(code) => Parting {
+                                close: Some((code, b"invalid close frame")),
+                                await_peer: true,
+                            },
+                        });
+                    }
+                    Err(e) => {
+                        return Some(Parting {
+                            close: Some((ws::close_code_for(e), e.as_bytes())),
+                            await_peer: true,
+                        });
                     }
-                    Err(_) => {}
                 }
             }
+            if !stream.buffered() {
+                break;
+            }
         }
-        if should_exit {
-            break;
+        None
+    }
+}
+
+/// Tell the client and every viewer who joined or left since last time.
+fn announce(epfd: usize, viewers: &mut Viewers, client: &mut Option<Client>) {
+    // Dropping a viewer that cannot take the news is news in turn.
+    while let Some(change) = viewers.next_change() {
+        let mut msg = [0u8; 64];
+        let n = match change {
+            Change::Joined { participant, write } => {
+                control::participant_message(b"join", participant, Some(write), &mut msg)
+            }
+            Change::Left { participant } => {
+                control::participant_message(b"leave", participant, None, &mut msg)
+            }
+        };
+        viewers.broadcast(epfd, ws::OPCODE_TEXT, &msg[..n]);
+        // A failure shows up on the next write to the client as well.
+        if let Some(c) = client {
+            let _ = ws::write_frame(&mut c.stream, ws::OPCODE_TEXT, &msg[..n]);
         }
     }
+}
 
-    let _ = crate::runtime::allocator::page_free(buf_ptr, buf_len);
-    let _ = crate::runtime::allocator::page_free(scratch_ptr, scratch_len);
-    if sfd != usize::MAX {
-        let _ = sys::fs::close(sfd);
+/// Resize the terminal, telling the viewers and the recording.
+fn resize(
+    epfd: usize,
+    pty_fd: usize,
+    ws: &sys::pty::WinSize,
+    viewers: &mut Viewers,
+    recorder: &mut Recorder,
+) {
+    if sys::pty::set_winsize(pty_fd, ws).is_ok() {
+        viewers.resized(epfd, ws);
+        recorder.resize(ws);
     }
+}
 
-    result
+/// After sending a close frame, drain the socket until the peer's close
+/// arrives, the peer hangs up, or `timeout_ms` elapses.
+fn await_close(stream: &mut Stream, decoder: &mut FrameDecoder, timeout_ms: u32) {
+    let Ok(epfd) = sys::epoll::epoll_create1() else {
+        return;
+    };
+    if sys::epoll::epoll_add(epfd, stream.fd, sys::epoll::EPOLLIN).is_ok() {
+        drain_until_close(epfd, stream, decoder, timeout_ms);
+    }
+    let _ = sys::fs::close(epfd);
+}
+
+fn drain_until_close(
+    epfd: usize,
+    stream: &mut Stream,
+    decoder: &mut FrameDecoder,
+    timeout_ms: u32,
+) {
+    let deadline = sys::timer::monotonic_ms() + timeout_ms as u64;
+    let mut events = [sys::epoll::EpollEvent::default(); 4];
+    loop {
+        let now = sys::timer::monotonic_ms();
+        if now >= deadline {
+            return;
+        }
+        match sys::epoll::epoll_wait(epfd, &mut events, (deadline - now) as isize) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        loop {
+            match stream.recv(decoder.spare()) {
+                Ok(0) => return,
+                Ok(r) => decoder.fill(r),
+                Err(super::EAGAIN) => {}
+                Err(_) => return,
+            }
+            loop {
+                match decoder.next_message() {
+                    Ok(None) => break,
+                    Ok(Some(Message::Close(_))) | Err(_) => return,
+                    Ok(Some(_)) => {}
+                }
+            }
+            if !stream.buffered() {
+                break;
+            }
+        }
+    }
 }
@@ -0,0 +1,210 @@
+//! Control messages exchanged with the browser.
+//!
+//! Terminal data travels in binary frames; text frames carry small flat JSON
+//! objects with a `"type"` field, e.g. `{"type":"resize","cols":80,"rows":24}`.
+//! The server announces the shell's termination the same way, e.g.
+//! `{"type":"exit","code":2}` or
+//! `{"type":"exit","signal":9,"name":"SIGKILL","core_dumped":false}`.
+//! With `session-grace` or `max-viewers` set, a session's first message
+//! names it and tells the page how to resume it and whether it can be
+//! watched, e.g.
+//! `{"type":"session","id":"…","resume":"…","grace":300,"watch":true}`.
+//! With `collaborate` it also carries the token participants join with,
+//! as `"join":"…"`. Viewers and participants never see that message: they
+//! start with a `welcome` message carrying their participant number, then
+//! get the terminal size as a `resize` message, again whenever it changes.
+//! Everyone is told who comes and goes, e.g.
+//! `{"type":"join","participant":3,"write":true}` and
+//! `{"type":"leave","participant":3}`; the client can take a participant's
+//! write access away with `{"type":"revoke","participant":3}`, which is
+//! announced in the same form.
+
+use super::sessions::Ids;
+use crate::sys::pty::{WaitStatus, WinSize};
+use crate::sys::signal::signal_name;
+
+pub(crate) enum Control {
+    Resize { cols: u16, rows: u16 },
+    Revoke { participant: u32 },
+}
+
+pub(crate) fn parse(msg: &[u8]) -> Option<Control> {
+    let s = core::str::from_utf8(msg).ok()?;
+    match json_str(s, "type")? {
+        "resize" => {
+            let cols = json_num(s, "cols")?;
+            let rows = json_num(s, "rows")?;
+            if cols == 0 || rows == 0 {
+                return None;
+            }
+            Some(Control::Resize { cols, rows })
+        }
+        "revoke" => Some(Control::Revoke {
+            participant: json_num(s, "participant")?,
+        }),
+        _ => None,
+    }
+}
+
+/// Write the `session` message for `ids` into `buf`, returning its length.
+pub(crate) fn session_message(
+    ids: &Ids,
+    grace: u32,
+    watch: bool,
+    join: bool,
+    buf: &mut [u8],
+) -> usize {
+    let mut w = Writer { buf, len: 0 };
+    w.push(b"{\"type\":\"session\",\"id\":\"");
+    w.push(&ids.id);
+    w.push(b"\",\"resume\":\"");
+    w.push(&ids.token);
+    w.push(b"\",\"grace\":");
+    w.num(grace);
+    w.push(b",\"watch\":");
+    w.push(if watch { b"true" } else { b"false" });
+    if join {
+        w.push(b",\"join\":\"");
+        w.push(&ids.join);
+        w.push(b"\"");
+    }
+    w.push(b"}");
+    w.len
+}
+
+/// Write the `resize` message for `ws` into `buf`, returning its length.
+pub(crate) fn resize_message(ws: &WinSize, buf: &mut [u8]) -> usize {
+    let mut w = Writer { buf, len: 0 };
+    w.push(b"{\"type\":\"resize\",\"cols\":");
+    w.num(ws.ws_col);
+    w.push(b",\"rows\":");
+    w.num(ws.ws_row);
+    w.push(b"}");
+    w.len
+}
+
+/// Write a `welcome`, `join`, `leave` or `revoke` message about
+/// `participant` into `buf`, returning its length; `write` goes with the
+/// first two.
+pub(crate) fn participant_message(
+    kind: &[u8],
+    participant: u32,
+    write: Option<bool>,
+    buf: &mut [u8],
+) -> usize {
+    let mut w = Writer { buf, len: 0 };
+    w.push(b"{\"type\":\"");
+    w.push(kind);
+    w.push(b"\",\"participant\":");
+    w.num(participant);
+    if let Some(write) = write {
+        w.push(b",\"write\":");
+        w.push(if write { b"true" } else { b"false" });
+    }
+    w.push(b"}");
+    w.len
+}
+
+/// Write the `exit` message for `status` into `buf`, returning its length.
+pub(crate) fn exit_message(status: WaitStatus, buf: &mut [u8]) -> usize {
+    let mut w = Writer { buf, len: 0 };
+    w.push(b"{\"type\":\"exit\",");
+    match status {
+        WaitStatus::Exited(code) => {
+            w.push(b"\"code\":");
+            w.num(code);
+        }
+        WaitStatus::Signaled {
+            signal,
+            core_dumped,
+        } => {
+            w.push(b"\"signal\":");
+            w.num(signal);
+            if let Some(name) = signal_name(signal) {
+                w.push(b",\"name\":\"");
+                w.push(name.as_bytes());
+                w.push(b"\"");
+            }
+            w.push(b",\"core_dumped\":");
+            w.push(if core_dumped { b"true" } else { b"false" });
+        }
+    }
+    w.push(b"}");
+    w.len
+}
+
+/// Human-readable summary of `status` for the close frame, e.g.
+/// `process exited with code 2` or `killed by SIGKILL`.
+pub(crate) fn exit_reason(status: WaitStatus, buf: &mut [u8]) -> usize {
+    let mut w = Writer { buf, len: 0 };
+    match status {
+        WaitStatus::Exited(code) => {
+            w.push(b"process exited with code ");
+            w.num(code);
+        }
+        WaitStatus::Signaled {
+            signal,
+            core_dumped,
+        } => {
+            w.push(b"killed by ");
+            match signal_name(signal) {
+                Some(name) => w.push(name.as_bytes()),
+                None => {
+                    w.push(b"signal ");
+                    w.num(signal);
+                }
+            }
+            if core_dumped {
+                w.push(b" (core dumped)");
+            }
+        }
+    }
+    w.len
+}
+
+/// Appends to a fixed buffer, dropping whatever does not fit.
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn push(&mut self, bytes: &[u8]) {
+        let n = core::cmp::min(bytes.len(), self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+        self.len += n;
+    }
+
+    fn num(&mut self, n: impl itoa::Integer) {
+        let mut num = itoa::Buffer::new();
+        self.push(num.format(n).as_bytes());
+    }
+}
+
+/// Locate the value following `"key":` in a flat JSON object.
+fn json_value<'a>(s: &'a str, key: &str) -> Option<&'a str> {
+    let mut rest = s;
+    while let Some(pos) = rest.find('"') {
+        let after = &rest[pos + 1..];
+        if after.starts_with(key) && after[key.len()..].starts_with('"') {
+            let tail = after[key.len() + 1..].trim_start();
+            if let Some(v) = tail.strip_prefix(':') {
+                return Some(v.trim_start());
+            }
+        }
+        rest = after;
+    }
+    None
+}
+
+fn json_str<'a>(s: &'a str, key: &str) -> Option<&'a str> {
+    let v = json_value(s, key)?.strip_prefix('"')?;
+    let end = v.find('"')?;
+    Some(&v[..end])
+}
+
+fn json_num<T: core::str::FromStr>(s: &str, key: &str) -> Option<T> {
+    let v = json_value(s, key)?;
+    let end = v.find(|c: char| !c.is_ascii_digit()).unwrap_or(v.len());
+    v[..end].parse().ok()
+}
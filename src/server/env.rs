@@ -0,0 +1,58 @@
+//! Environment of a session's shell.
+//!
+//! Layered from lowest to highest precedence: built-in defaults, variables
+//! inherited from the server (`env-inherit`), fixed admin variables
+//! (`env`), the route's own `env` entries, then client variables from the
+//! upgrade query string (`?env.NAME=VALUE`, only for names listed in
+//! `client-env`).
+
+use crate::config::{self, Config, Route};
+use crate::net;
+use crate::pty::Environment;
+
+const DEFAULTS: &[(&[u8], &[u8])] = &[
+    (b"TERM", b"xterm-256color"),
+    (b"COLORTERM", b"truecolor"),
+    (b"LANG", b"C.UTF-8"),
+    (
+        b"PATH",
+        b"/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin",
+    ),
+];
+
+const CLIENT_PREFIX: &str = "env.";
+const MAX_CLIENT_VALUE: usize = 1024;
+
+pub(crate) fn session_env(cfg: &Config, route: &Route, req: &[u8]) -> Environment {
+    let mut env = Environment::new();
+    for (name, value) in DEFAULTS {
+        let _ = env.set(name, value);
+    }
+    let _ = env.set(b"SHELL", cfg.shell.as_bytes());
+    // Config entries were validated when loaded.
+    for entry in cfg
+        .inherited
+        .iter()
+        .chain(cfg.env.iter())
+        .chain(route.env.iter())
+    {
+        let _ = env.set_entry(entry.as_bytes());
+    }
+    for (key, raw) in net::http::query_pairs(req) {
+        let Some(name) = key.strip_prefix(CLIENT_PREFIX) else {
+            continue;
+        };
+        if !config::list_contains(cfg.client_env, name) {
+            super::log(b"client env variable not allowed: ");
+            super::log(name.as_bytes());
+            super::log(b"\n");
+            continue;
+        }
+        let mut value = [0u8; MAX_CLIENT_VALUE];
+        match net::http::percent_decode(raw, &mut value) {
+            Some(n) if env.set(name.as_bytes(), &value[..n]).is_ok() => {}
+            _ => super::log(b"client env value rejected\n"),
+        }
+    }
+    env
+}
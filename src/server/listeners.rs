@@ -0,0 +1,115 @@
+use crate::config::Config;
+use crate::net::addr::{IpAddr, ListenAddr, MAX_LISTEN_ADDR_LEN, MAX_UNIX_PATH};
+use crate::sys;
+
+const MAX_LISTENERS: usize = 8;
+
+/// Listening sockets, one per configured address.
+pub(crate) struct Listeners {
+    fds: [usize; MAX_LISTENERS],
+    /// Socket file created for each listener, if any (removed on shutdown).
+    files: [Option<&'static str>; MAX_LISTENERS],
+    /// Whether each listener speaks TLS.
+    tls: [bool; MAX_LISTENERS],
+    len: usize,
+}
+
+impl Listeners {
+    /// Open every configured address, exiting if any of them fails.
+    pub(crate) fn open(cfg: &Config) -> Self {
+        let mut l = Self {
+            fds: [0; MAX_LISTENERS],
+            files: [None; MAX_LISTENERS],
+            tls: [false; MAX_LISTENERS],
+            len: 0,
+        };
+        for addr in cfg.listen_addrs().iter().take(MAX_LISTENERS) {
+            let res = match addr {
+                ListenAddr::Tcp(a) | ListenAddr::Tls(a) => match a.ip {
+                    IpAddr::V4(ip) => sys::net::tcp_listen(ip, a.port),
+                    IpAddr::V6(ip) => sys::net::tcp6_listen(ip, a.port, cfg.ipv6_only),
+                },
+                ListenAddr::Unix(path) => sys::net::unix_listen(path.as_bytes()),
+            };
+            match res {
+                Ok(fd) => {
+                    l.fds[l.len] = fd;
+                    l.tls[l.len] = matches!(addr, ListenAddr::Tls(_));
+                    if let ListenAddr::Unix(path) = addr
+                        && !path.starts_with('@')
+                    {
+                        l.files[l.len] = Some(path);
+                        set_permissions(cfg, path);
+                    }
+                    l.len += 1;
+                }
+                Err(e) => {
+                    log_addr(b"listen failed on ", addr);
+                    super::log(b" with errno: ");
+                    super::log_num(-e as i32);
+                    super::log(b"\n");
+                    super::exit_now(1);
+                }
+            }
+        }
+        l
+    }
+
+    pub(crate) fn fds(&self) -> &[usize] {
+        &self.fds[..self.len]
+    }
+
+    pub(crate) fn contains(&self, fd: usize) -> bool {
+        self.fds().contains(&fd)
+    }
+
+    pub(crate) fn is_tls(&self, fd: usize) -> bool {
+        self.fds()
+            .iter()
+            .position(|&f| f == fd)
+            .is_some_and(|i| self.tls[i])
+    }
+
+    pub(crate) fn close_all(&self) {
+        for &fd in self.fds() {
+            let _ = sys::fs::close(fd);
+        }
+    }
+
+    /// Remove the socket files this process created. Only the parent calls
+    /// this, on shutdown; workers merely close their copies.
+    pub(crate) fn remove_files(&self) {
+        for path in self.files[..self.len].iter().flatten() {
+            let _ = sys::fs::unlink(c_path(path).as_ptr());
+        }
+    }
+}
+
+fn set_permissions(cfg: &Config, path: &str) {
+    let cpath = c_path(path);
+    if sys::fs::chmod(cpath.as_ptr(), cfg.unix_mode).is_err() {
+        super::log(b"chmod failed on socket ");
+        super::log(path.as_bytes());
+        super::log(b"\n");
+    }
+    if let Some((uid, gid)) = cfg.unix_owner
+        && sys::fs::chown(cpath.as_ptr(), uid, gid).is_err()
+    {
+        super::log(b"chown failed on socket ");
+        super::log(path.as_bytes());
+        super::log(b"\n");
+    }
+}
+
+fn c_path(path: &str) -> [u8; MAX_UNIX_PATH + 1] {
+    let mut buf = [0u8; MAX_UNIX_PATH + 1];
+    buf[..path.len()].copy_from_slice(path.as_bytes());
+    buf
+}
+
+fn log_addr(prefix: &[u8], addr: &ListenAddr) {
+    let mut buf = [0u8; MAX_LISTEN_ADDR_LEN];
+    let n = addr.format(&mut buf);
+    super::log(prefix);
+    super::log(&buf[..n]);
+}
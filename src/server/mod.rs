@@ -1,19 +1,33 @@
+use crate::config::{Config, Route};
 use crate::net;
+use crate::net::http::{Found, Request, Resource, Routed};
+use crate::net::stream::Stream;
 use crate::pty;
 use crate::sys;
+mod assets;
+mod auth;
 mod bridge;
+mod control;
+mod env;
+mod listeners;
+mod pending;
+mod recorder;
+mod scrollback;
+mod sessions;
+mod viewers;
+mod workers;
+
+pub(crate) use auth::Auth;
+pub(crate) use listeners::Listeners;
+pub(crate) use pending::Pending;
+pub(crate) use sessions::Sessions;
+pub(crate) use workers::Workers;
+const EAGAIN: isize = -11;
+
 pub static INDEX_HTML: &[u8] = include_bytes!("../../assets/terminal.html");
 
-pub(crate) fn setup_listener() -> (usize, usize, usize) {
-    let listen_fd = match sys::net::tcp_listen(8000) {
-        Ok(fd) => fd,
-        Err(e) => {
-            log(b"listen failed with errno: ");
-            log_num(-e as i32);
-            log(b"\n");
-            exit_now(1);
-        }
-    };
+pub(crate) fn setup_listener(cfg: &Config) -> (Listeners, usize, usize) {
+    let listeners = Listeners::open(cfg);
     let epfd = match sys::epoll::epoll_create1() {
         Ok(e) => e,
         Err(_) => {
@@ -21,7 +35,9 @@ pub(crate) fn setup_listener() -> (usize, usize, usize) {
             exit_now(1);
         }
     };
-    let _ = sys::epoll::epoll_add(epfd, listen_fd, sys::epoll::EPOLLIN);
+    for &fd in listeners.fds() {
+        let _ = sys::epoll::epoll_add(epfd, fd, sys::epoll::EPOLLIN);
+    }
     let mut mask: u64 = 0;
     mask |= 1u64 << (2 - 1);
     mask |= 1u64 << (15 - 1);
@@ -34,10 +50,14 @@ pub(crate) fn setup_listener() -> (usize, usize, usize) {
     if sfd != usize::MAX {
         let _ = sys::epoll::epoll_add(epfd, sfd, sys::epoll::EPOLLIN);
     }
-    (listen_fd, epfd, sfd)
+    (listeners, epfd, sfd)
 }
 
-pub(crate) fn handle_signal_event(sfd: usize, active_workers: &mut i32) -> bool {
+pub(crate) fn handle_signal_event(
+    sfd: usize,
+    workers: &mut Workers,
+    sessions: &mut Sessions,
+) -> bool {
     let mut info = [0u8; 128];
     if let Ok(r) = sys::fs::read(sfd, &mut info)
         && r >= 4
@@ -51,7 +71,8 @@ pub(crate) fn handle_signal_event(sfd: usize, active_workers: &mut i32) -> bool
                 match crate::sys::pty::wait_any_nohang() {
                     Ok(0) => break,
                     Ok(pid) if pid > 0 => {
-                        *active_workers -= 1;
+                        workers.remove(pid);
+                        sessions.remove(pid);
                         continue;
                     }
                     Err(_) => break,
@@ -66,84 +87,508 @@ pub(crate) fn handle_signal_event(sfd: usize, active_workers: &mut i32) -> bool
     false
 }
 
+/// Accept every waiting connection on `listen_fd` and start reading its
+/// request head (after the TLS handshake on `tls:` listeners).
 pub(crate) fn handle_listener_event(
     listen_fd: usize,
-    active_workers: &mut i32,
-    max_workers: i32,
-    sfd: usize,
+    listeners: &Listeners,
+    pending: &mut Pending,
+    cfg: &Config,
     epfd: usize,
 ) -> Result<(), &'static str> {
-    let (fd2, _) = sys::net::accept_blocking(listen_fd).map_err(|_| "accept")?;
-    let fd = fd2;
-
-    let mut buf = [0u8; 8192];
-    let n = sys::net::recv(fd, &mut buf).map_err(|_| {
-        let _ = sys::fs::close(fd);
-        "recv"
-    })?;
-
-    if net::http::is_websocket_upgrade(&buf[..n]) && net::http::path_is_term(&buf[..n]) {
-        if *active_workers >= max_workers {
-            let _ = crate::sys::fs::write(
-                fd,
-                b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n",
-            );
-            let _ = sys::fs::close(fd);
-            return Ok(());
-        }
-
-        match crate::sys::pty::fork() {
-            Err(_) => {
-                log(b"fork failed\n");
+    loop {
+        let fd = match sys::net::accept_nonblocking(listen_fd) {
+            Ok(fd) => fd,
+            Err(EAGAIN) => return Ok(()),
+            Err(_) => return Err("accept"),
+        };
+        let stream = match (listeners.is_tls(listen_fd), &cfg.tls) {
+            (false, _) => Stream::plain(fd),
+            (true, Some(creds)) => match net::tls::Session::new(creds) {
+                Ok(session) => Stream::tls(fd, session),
+                Err(_) => {
+                    log(b"tls session alloc failed\n");
+                    let _ = sys::fs::close(fd);
+                    continue;
+                }
+            },
+            (true, None) => {
                 let _ = sys::fs::close(fd);
-                return Err("fork");
+                continue;
             }
-            Ok(p) if p > 0 => {
-                *active_workers += 1;
-                let _ = sys::fs::close(fd);
+        };
+        let deadline = sys::timer::monotonic_ms() + cfg.header_timeout as u64;
+        if let Err(stream) = pending.add(stream, deadline) {
+            log(b"too many pending connections\n");
+            net::http::serve_error(stream, b"503 Service Unavailable");
+            continue;
+        }
+        if sys::epoll::epoll_add(epfd, fd, sys::epoll::EPOLLIN).is_err()
+            && let Some(stream) = pending.remove(fd)
+        {
+            stream.abandon();
+        }
+    }
+}
+
+/// Read from a connection still sending its request head; once complete,
+/// hand it to [`dispatch`]. A kept-alive connection goes back into
+/// `pending`, and round again: the next request may be buffered already.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_connection_event(
+    fd: usize,
+    listeners: &Listeners,
+    pending: &mut Pending,
+    workers: &mut Workers,
+    sessions: &mut Sessions,
+    auth: &mut Auth,
+    cfg: &Config,
+    sfd: usize,
+    epfd: usize,
+) -> Result<(), &'static str> {
+    loop {
+        match pending.read(fd) {
+            pending::Progress::Incomplete => return Ok(()),
+            pending::Progress::Complete => {}
+            pending::Progress::TooLarge => {
+                let _ = sys::epoll::epoll_del(epfd, fd);
+                if let Some(mut stream) = pending.remove(fd) {
+                    log(b"request header too large\n");
+                    drain(&mut stream);
+                    net::http::serve_error(stream, b"431 Request Header Fields Too Large");
+                }
                 return Ok(());
             }
-            Ok(0) => {
-                let _ = sys::fs::close(listen_fd);
-                if sfd != usize::MAX {
-                    let _ = sys::fs::close(sfd);
+            pending::Progress::Closed | pending::Progress::TlsFailed => {
+                let _ = sys::epoll::epoll_del(epfd, fd);
+                if let Some(stream) = pending.remove(fd) {
+                    stream.abandon();
                 }
-                let _ = sys::fs::close(epfd);
+                return Ok(());
             }
-            _ => {
-                let _ = sys::fs::close(fd);
-                return Err("fork-other");
+        }
+        let _ = sys::epoll::epoll_del(epfd, fd);
+        let mut buf = [0u8; pending::MAX_REQUEST];
+        let Some(ready) = pending.take(fd, &mut buf) else {
+            return Ok(());
+        };
+        if sys::fs::set_blocking(fd).is_err() {
+            ready.stream.abandon();
+            return Err("fcntl");
+        }
+        let kept = dispatch(
+            ready.stream,
+            &buf[..ready.head_len],
+            ready.served,
+            listeners,
+            pending,
+            workers,
+            sessions,
+            auth,
+            cfg,
+            sfd,
+            epfd,
+        )?;
+        let Some(stream) = kept else {
+            return Ok(());
+        };
+        if sys::fs::set_nonblocking(fd).is_err() {
+            stream.abandon();
+            return Err("fcntl");
+        }
+        let deadline = sys::timer::monotonic_ms() + cfg.keepalive_timeout as u64;
+        let pipelined = &buf[ready.head_len..ready.len];
+        if let Err(stream) = pending.resume(stream, deadline, ready.served + 1, pipelined) {
+            stream.close();
+            return Ok(());
+        }
+        if sys::epoll::epoll_add(epfd, fd, sys::epoll::EPOLLIN).is_err() {
+            if let Some(stream) = pending.remove(fd) {
+                stream.abandon();
             }
+            return Ok(());
+        }
+    }
+}
+
+/// Answer connections whose request head did not arrive in time with 408;
+/// kept-alive connections that stayed idle are just closed.
+pub(crate) fn expire_connections(pending: &mut Pending, epfd: usize) {
+    let now = sys::timer::monotonic_ms();
+    while let Some((stream, idle)) = pending.take_expired(now) {
+        let _ = sys::epoll::epoll_del(epfd, stream.fd);
+        if idle {
+            stream.close();
+            continue;
+        }
+        log(b"request header timeout\n");
+        net::http::serve_error(stream, b"408 Request Timeout");
+    }
+}
+
+/// Discard whatever the peer has already sent, so closing the socket does
+/// not reset the connection before our response is read.
+fn drain(stream: &mut Stream) {
+    let mut scratch = [0u8; 1024];
+    while let Ok(n) = stream.recv(&mut scratch) {
+        if n == 0 {
+            break;
         }
+    }
+}
+
+/// Plain (non-WebSocket) things a path can name.
+enum Document {
+    File(Found),
+    Asset(&'static assets::Asset),
+    Page,
+    Login,
+}
 
-        match net::ws::upgrade_to_websocket(fd, &buf[..n]) {
-            Ok(ws) => match pty::spawn_sh() {
-                Ok(p) => {
-                    let _ = bridge::run_bridge(ws.fd, p.master_fd, p.child_pid);
-                    let _ = crate::sys::pty::kill(p.child_pid, 15);
-                    if crate::sys::pty::waitpid(p.child_pid).is_err() {
-                        let _ = crate::sys::pty::kill(p.child_pid, 9);
-                        let _ = crate::sys::pty::waitpid_nohang(p.child_pid);
+/// Find what `req`'s path names: a WebSocket route, or for plain requests a
+/// file under the document root, an embedded asset, the page at `/` or the
+/// page's `/login` check.
+fn lookup<'c>(req: &Request, cfg: &'c Config) -> Option<Resource<&'c Route, Document>> {
+    if let Some(route) = cfg.routes.find(req.path) {
+        return Some(Resource::WebSocket(route));
+    }
+    // Nothing but a route takes an upgrade.
+    if req.is_websocket_upgrade() {
+        return None;
+    }
+    let doc = if req.path == "/login" {
+        Document::Login
+    } else if let Some(root) = cfg.docroot_fd
+        && let Some(found) = net::http::find_file(req.path, root, cfg.index_files)
+    {
+        Document::File(found)
+    } else if let Some(asset) = assets::find(req.path) {
+        Document::Asset(asset)
+    } else if req.path == "/" {
+        Document::Page
+    } else {
+        return None;
+    };
+    Some(Resource::Document(doc))
+}
+
+/// Route a complete request, the one after `served` others on its
+/// connection: upgrade it in a forked worker or serve a plain HTTP
+/// response. Returns the stream if it is kept alive for another request.
+#[allow(clippy::too_many_arguments)]
+fn dispatch(
+    mut stream: Stream,
+    head: &[u8],
+    served: u32,
+    listeners: &Listeners,
+    pending: &mut Pending,
+    workers: &mut Workers,
+    sessions: &mut Sessions,
+    auth: &mut Auth,
+    cfg: &Config,
+    sfd: usize,
+    epfd: usize,
+) -> Result<Option<Stream>, &'static str> {
+    let mut token = [0u8; auth::MAX_TOKEN];
+    let mut path_buf = [0u8; pending::MAX_REQUEST];
+    let mut req = match Request::parse(head, &mut path_buf) {
+        Ok(req) => req,
+        Err(status) => {
+            net::http::serve_error(stream, status);
+            return Ok(None);
+        }
+    };
+    req.keep_alive &= cfg.keepalive_timeout > 0 && served + 1 < cfg.keepalive_requests;
+    let mut routed = net::http::route(&req, lookup(&req, cfg));
+    // With an htpasswd file nothing is served without a login; upgrades
+    // are checked after their own checks, and /login answers for itself.
+    if auth.guards_documents()
+        && !matches!(routed, Routed::Upgrade(_) | Routed::Serve(Document::Login))
+        && let Err(denied) = auth.check(&req, stream.fd, &mut token)
+    {
+        // Browsers ask without credentials first.
+        if !matches!(denied, auth::Denied::Missing) {
+            log_refusal(b"request refused: ", &denied, stream.fd);
+        }
+        routed = Routed::Reject(denied.status(), auth.challenge(&denied));
+    }
+    match routed {
+        Routed::Reject(status, headers) => {
+            net::http::serve_status(&mut stream, &req, status, headers);
+        }
+        Routed::Upgrade(route) => {
+            if let Err(refusal) = net::ws::check_upgrade(&req, cfg.allowed_origins) {
+                log(b"upgrade refused: ");
+                log(refusal.reason);
+                log(b"\n");
+                net::http::serve_status(&mut stream, &req, refusal.status, refusal.headers);
+            } else if let Err(denied) = auth.check(&req, stream.fd, &mut token) {
+                log_refusal(b"upgrade refused: ", &denied, stream.fd);
+                let challenge = auth.challenge(&denied);
+                net::http::serve_status(&mut stream, &req, denied.status(), challenge);
+            } else if let Some(token) = net::http::query_param(head, "resume") {
+                match sessions.find(token.as_bytes()) {
+                    Some(i) => {
+                        let join = sessions::Join::Resume(initial_size(head));
+                        join_session(stream, head, sessions, i, join);
+                        return Ok(None);
+                    }
+                    None => {
+                        log(b"resume refused: unknown session");
+                        log_peer(stream.fd);
+                        log(b"\n");
+                        net::http::serve_status(&mut stream, &req, b"404 Not Found", b"");
                     }
-                    let _ = sys::fs::close(p.master_fd);
-                    exit_now(0);
                 }
-                Err(_) => {
-                    log(b"pty spawn failed\n");
-                    let _ = sys::fs::close(ws.fd);
-                    exit_now(1);
+            } else if let Some(id) = net::http::query_param(head, "watch") {
+                match sessions.find_id(id.as_bytes()) {
+                    Some(i) if cfg.max_viewers > 0 => {
+                        join_session(stream, head, sessions, i, sessions::Join::Watch);
+                        return Ok(None);
+                    }
+                    _ => {
+                        log(b"watch refused: unknown session");
+                        log_peer(stream.fd);
+                        log(b"\n");
+                        net::http::serve_status(&mut stream, &req, b"404 Not Found", b"");
+                    }
+                }
+            } else if let Some(token) = net::http::query_param(head, "join") {
+                match sessions.find_join(token.as_bytes()) {
+                    Some(i) if cfg.collaborate && cfg.max_viewers > 0 => {
+                        join_session(stream, head, sessions, i, sessions::Join::Collaborate);
+                        return Ok(None);
+                    }
+                    _ => {
+                        log(b"join refused: unknown session");
+                        log_peer(stream.fd);
+                        log(b"\n");
+                        net::http::serve_status(&mut stream, &req, b"404 Not Found", b"");
+                    }
+                }
+            } else {
+                let fresh = match sessions::Fresh::new() {
+                    Ok(f) => f,
+                    Err(_) => {
+                        log(b"session setup failed\n");
+                        net::http::serve_error(stream, b"503 Service Unavailable");
+                        return Ok(None);
+                    }
+                };
+                let forked = fork_worker(
+                    stream,
+                    Some(fresh),
+                    listeners,
+                    pending,
+                    workers,
+                    sessions,
+                    sfd,
+                    epfd,
+                )?;
+                let Some((mut stream, Some(mut handle))) = forked else {
+                    return Ok(None);
+                };
+                let env = env::session_env(cfg, route, head);
+                match net::ws::upgrade_to_websocket(&mut stream, head) {
+                    Ok(()) => match pty::spawn(
+                        route.command,
+                        route.args,
+                        route.cwd,
+                        &env,
+                        initial_size(head),
+                    ) {
+                        Ok(p) => {
+                            let size = sys::pty::get_winsize(p.master_fd).unwrap_or_default();
+                            let recorder = recorder::Recorder::start(
+                                cfg,
+                                &handle.ids.id,
+                                &size,
+                                route.command,
+                                route.args,
+                                env.get(b"TERM"),
+                            );
+                            let bridged =
+                                bridge::run_bridge(cfg, stream, &p, &mut handle, recorder);
+                            if let Ok(Some(_)) = bridged {
+                                let _ = sys::fs::close(p.master_fd);
+                            } else {
+                                hang_up(&p);
+                            }
+                            exit_now(0);
+                        }
+                        Err(_) => {
+                            log(b"pty spawn failed\n");
+                            stream.close();
+                            exit_now(1);
+                        }
+                    },
+                    Err(_) => {
+                        log(b"ws upgrade failed\n");
+                        stream.close();
+                        exit_now(1);
+                    }
                 }
-            },
-            Err(_) => {
-                log(b"ws upgrade failed\n");
-                let _ = sys::fs::close(fd);
-                exit_now(1);
             }
         }
-    } else {
-        net::http::serve_html(fd, INDEX_HTML);
+        Routed::Serve(Document::File(Found::Directory)) => {
+            net::http::redirect_to_dir(&mut stream, &req);
+        }
+        // A large or slow download must not hold up the accept loop; the
+        // worker closes the connection when it is done.
+        Routed::Serve(Document::File(Found::File(file))) => {
+            let forked = fork_worker(
+                stream, None, listeners, pending, workers, sessions, sfd, epfd,
+            )?;
+            if let Some((stream, _)) = forked {
+                net::http::serve_file(stream, &req, &file);
+                exit_now(0);
+            }
+            return Ok(None);
+        }
+        Routed::Serve(Document::Asset(asset)) => {
+            let content_type = net::http::content_type(asset.path);
+            net::http::serve_asset(&mut stream, &req, asset.body, content_type, &asset.etag());
+        }
+        Routed::Serve(Document::Page) => net::http::serve_html(&mut stream, &req, INDEX_HTML),
+        Routed::Serve(Document::Login) => {
+            let verdict = auth.check(&req, stream.fd, &mut token).map_err(|denied| {
+                // The page asks without a token first; only wrong
+                // credentials are a failed attempt.
+                if !matches!(denied, auth::Denied::Missing) {
+                    log_refusal(b"login failed: ", &denied, stream.fd);
+                }
+                (denied.status(), auth.challenge(&denied))
+            });
+            auth::serve_login(&mut stream, &req, verdict);
+        }
+    }
+    if req.keep_alive {
+        return Ok(Some(stream));
+    }
+    stream.close();
+    Ok(None)
+}
+
+/// Fork a worker to handle `stream`, filing it under `session` if it
+/// starts one. The parent tracks it and lets go of the connection
+/// (`None`); the child drops what else it inherited from the parent and
+/// gets the stream back, with its side of the session. Answers 503 when
+/// every worker slot is taken.
+#[allow(clippy::too_many_arguments)]
+fn fork_worker(
+    stream: Stream,
+    session: Option<sessions::Fresh>,
+    listeners: &Listeners,
+    pending: &mut Pending,
+    workers: &mut Workers,
+    sessions: &mut Sessions,
+    sfd: usize,
+    epfd: usize,
+) -> Result<Option<(Stream, Option<sessions::Handle>)>, &'static str> {
+    if workers.is_full() {
+        if let Some(fresh) = session {
+            fresh.discard();
+        }
+        net::http::serve_error(stream, b"503 Service Unavailable");
+        return Ok(None);
+    }
+    match crate::sys::pty::fork() {
+        Err(_) => {
+            log(b"fork failed\n");
+            if let Some(fresh) = session {
+                fresh.discard();
+            }
+            stream.close();
+            Err("fork")
+        }
+        Ok(p) if p > 0 => {
+            workers.add(p);
+            if let Some(fresh) = session {
+                fresh.file(sessions, p);
+            }
+            stream.abandon();
+            Ok(None)
+        }
+        Ok(0) => {
+            listeners.close_all();
+            pending.close_all();
+            sessions.close_all();
+            if sfd != usize::MAX {
+                let _ = sys::fs::close(sfd);
+            }
+            let _ = sys::fs::close(epfd);
+            Ok(Some((stream, session.map(sessions::Fresh::into_handle))))
+        }
+        _ => {
+            if let Some(fresh) = session {
+                fresh.discard();
+            }
+            stream.close();
+            Err("fork-other")
+        }
+    }
+}
+
+/// Answer an upgrade to `?resume=TOKEN`, `?watch=ID` or `?join=TOKEN` and
+/// pass the connection on to the worker running session `i`.
+fn join_session(
+    mut stream: Stream,
+    head: &[u8],
+    sessions: &mut Sessions,
+    i: usize,
+    join: sessions::Join,
+) {
+    if net::ws::upgrade_to_websocket(&mut stream, head).is_err() {
+        log(b"ws upgrade failed\n");
+        stream.close();
+        return;
     }
-    Ok(())
+    let what: &[u8] = match join {
+        sessions::Join::Resume(_) => b"resume failed: ",
+        sessions::Join::Watch => b"watch failed: ",
+        sessions::Join::Collaborate => b"join failed: ",
+    };
+    if let Err(e) = sessions.hand_over(i, stream, join) {
+        log(what);
+        log(e.as_bytes());
+        log(b"\n");
+    }
+}
+
+/// Hang up the shell's terminal and reap it. Interactive shells ignore
+/// SIGTERM, so rely on SIGHUP and escalate to SIGKILL after a grace period.
+fn hang_up(p: &pty::Pty) {
+    let _ = sys::fs::close(p.master_fd);
+    let _ = sys::pty::kill(p.child_pid, 1);
+    let _ = sys::pty::kill(p.child_pid, 18);
+    if wait_shell(p.child_pid, 500).is_some() {
+        return;
+    }
+    let _ = sys::pty::kill(p.child_pid, 9);
+    let _ = sys::pty::waitpid(p.child_pid);
+}
+
+/// Reap the shell, polling for up to `timeout_ms`. `None` if it is still
+/// running (or was already reaped).
+fn wait_shell(pid: i32, timeout_ms: u32) -> Option<sys::pty::WaitStatus> {
+    for _ in 0..timeout_ms / 10 {
+        match sys::pty::waitpid_nohang(pid) {
+            Ok(None) => sys::timer::sleep_ms(10),
+            Ok(Some(status)) => return Some(status),
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+/// Initial terminal geometry requested via `/term?cols=N&rows=M`.
+fn initial_size(req: &[u8]) -> Option<sys::pty::WinSize> {
+    let cols: u16 = net::http::query_param(req, "cols")?.parse().ok()?;
+    let rows: u16 = net::http::query_param(req, "rows")?.parse().ok()?;
+    if cols == 0 || rows == 0 {
+        return None;
+    }
+    Some(sys::pty::WinSize::new(cols, rows))
 }
 
 #[inline(always)]
@@ -151,6 +596,40 @@ pub(crate) fn log(msg: &[u8]) {
     let _ = sys::fs::write(1, msg);
 }
 
+/// Log a refused request with why and where from.
+fn log_refusal(what: &[u8], denied: &auth::Denied, fd: usize) {
+    log(what);
+    log(denied.reason());
+    log_peer(fd);
+    log(b"\n");
+}
+
+/// Address of a TCP peer; `None` for a Unix socket.
+fn peer_ip(fd: usize) -> Option<net::addr::IpAddr> {
+    let mut sa = [0u8; 28];
+    sys::net::getpeername(fd, &mut sa).ok()?;
+    match u16::from_ne_bytes([sa[0], sa[1]]) as usize {
+        sys::net::AF_INET => Some(net::addr::IpAddr::V4([sa[4], sa[5], sa[6], sa[7]])),
+        sys::net::AF_INET6 => {
+            let mut ip = [0u8; 16];
+            ip.copy_from_slice(&sa[8..24]);
+            Some(net::addr::IpAddr::V6(ip))
+        }
+        _ => None,
+    }
+}
+
+/// Log ` from ADDR` for a TCP peer; nothing for a Unix socket.
+fn log_peer(fd: usize) {
+    let Some(ip) = peer_ip(fd) else {
+        return;
+    };
+    let mut text = [0u8; 40];
+    let n = ip.format(&mut text);
+    log(b" from ");
+    log(&text[..n]);
+}
+
 #[inline(always)]
 pub(crate) fn log_num(mut n: i32) {
     if n == 0 {
@@ -0,0 +1,193 @@
+//! Connections whose HTTP request head is still being read.
+//!
+//! The parent accepts sockets non-blocking and parks them here, registered
+//! in its epoll set, until `\r\n\r\n` arrives. A silent or slow client then
+//! holds one slot until its deadline instead of stalling the accept loop.
+//! Kept-alive connections come back here between requests, along with any
+//! pipelined bytes already read past the previous head.
+
+use crate::net::stream::Stream;
+use crate::net::tls;
+use crate::runtime::{allocator, util};
+
+/// Largest request head accepted; anything longer is answered with 431.
+pub(crate) const MAX_REQUEST: usize = 8192;
+const MAX_PENDING: usize = 128;
+
+struct Conn {
+    stream: Stream,
+    len: usize,
+    /// Monotonic time (ms) by which the request head must be complete.
+    deadline: u64,
+    /// Requests already answered on this connection.
+    served: u32,
+    buf: [u8; MAX_REQUEST],
+}
+
+/// A connection taken out of [`Pending`] with a complete request head.
+pub(crate) struct Ready {
+    pub(crate) stream: Stream,
+    /// Length of the head, through its `\r\n\r\n`.
+    pub(crate) head_len: usize,
+    /// Bytes copied out: the head and whatever was pipelined after it.
+    pub(crate) len: usize,
+    pub(crate) served: u32,
+}
+
+pub(crate) enum Progress {
+    /// Need more bytes.
+    Incomplete,
+    /// The request head is buffered; fetch it with [`Pending::take`].
+    Complete,
+    /// No `\r\n\r\n` within [`MAX_REQUEST`] bytes.
+    TooLarge,
+    /// The peer hung up or the socket failed.
+    Closed,
+    /// The TLS handshake or a record was rejected.
+    TlsFailed,
+}
+
+pub(crate) struct Pending {
+    conns: *mut Conn,
+    len: usize,
+}
+
+impl Pending {
+    pub(crate) fn new() -> Result<Self, &'static str> {
+        let conns = allocator::page_alloc(MAX_PENDING * core::mem::size_of::<Conn>())
+            .map_err(|_| "mmap pending")?;
+        Ok(Self {
+            conns: conns as *mut Conn,
+            len: 0,
+        })
+    }
+
+    fn conns(&mut self) -> &mut [Conn] {
+        &mut util::ptr_to_mut_slice_of(self.conns, MAX_PENDING)[..self.len]
+    }
+
+    fn find(&mut self, fd: usize) -> Option<&mut Conn> {
+        self.conns().iter_mut().find(|c| c.stream.fd == fd)
+    }
+
+    pub(crate) fn contains(&mut self, fd: usize) -> bool {
+        self.find(fd).is_some()
+    }
+
+    /// Track `stream`, or hand it back when every slot is taken.
+    pub(crate) fn add(&mut self, stream: Stream, deadline: u64) -> Result<(), Stream> {
+        self.resume(stream, deadline, 0, &[])
+    }
+
+    /// Track a kept-alive `stream` again after `served` requests, starting
+    /// from the bytes already `buffered` for the next one.
+    pub(crate) fn resume(
+        &mut self,
+        stream: Stream,
+        deadline: u64,
+        served: u32,
+        buffered: &[u8],
+    ) -> Result<(), Stream> {
+        if self.len == MAX_PENDING {
+            return Err(stream);
+        }
+        let conn = &mut util::ptr_to_mut_slice_of(self.conns, MAX_PENDING)[self.len];
+        conn.stream = stream;
+        conn.len = buffered.len();
+        conn.buf[..buffered.len()].copy_from_slice(buffered);
+        conn.deadline = deadline;
+        conn.served = served;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Stop tracking `fd` and return its stream, for the caller to close or
+    /// hand off.
+    pub(crate) fn remove(&mut self, fd: usize) -> Option<Stream> {
+        let last = self.len.wrapping_sub(1);
+        let conns = self.conns();
+        let i = conns.iter().position(|c| c.stream.fd == fd)?;
+        conns.swap(i, last);
+        self.len -= 1;
+        let slot = &mut util::ptr_to_mut_slice_of(self.conns, MAX_PENDING)[last].stream;
+        Some(core::mem::replace(slot, Stream::plain(usize::MAX)))
+    }
+
+    /// Receive whatever `fd` has ready and report whether the request head
+    /// is complete.
+    pub(crate) fn read(&mut self, fd: usize) -> Progress {
+        let Some(conn) = self.find(fd) else {
+            return Progress::Closed;
+        };
+        // A pipelined request may be here in full already.
+        if head_len(&conn.buf[..conn.len]).is_some() {
+            return Progress::Complete;
+        }
+        loop {
+            if conn.len == MAX_REQUEST {
+                return Progress::TooLarge;
+            }
+            match conn.stream.recv(&mut conn.buf[conn.len..]) {
+                Ok(0) => return Progress::Closed,
+                Ok(n) => {
+                    // Rescan from just before the new bytes: the terminator
+                    // may straddle two reads.
+                    let from = conn.len.saturating_sub(3);
+                    conn.len += n;
+                    if conn.buf[from..conn.len]
+                        .windows(4)
+                        .any(|w| w == b"\r\n\r\n")
+                    {
+                        return Progress::Complete;
+                    }
+                }
+                Err(super::EAGAIN) => return Progress::Incomplete,
+                Err(tls::PROTOCOL_ERROR) => return Progress::TlsFailed,
+                Err(_) => return Progress::Closed,
+            }
+        }
+    }
+
+    /// Copy out the buffered request of `fd` and stop tracking it.
+    pub(crate) fn take(&mut self, fd: usize, out: &mut [u8; MAX_REQUEST]) -> Option<Ready> {
+        let conn = self.find(fd)?;
+        let (len, served) = (conn.len, conn.served);
+        let head_len = head_len(&conn.buf[..len])?;
+        out[..len].copy_from_slice(&conn.buf[..len]);
+        Some(Ready {
+            stream: self.remove(fd)?,
+            head_len,
+            len,
+            served,
+        })
+    }
+
+    /// Earliest deadline among pending connections.
+    pub(crate) fn next_deadline(&mut self) -> Option<u64> {
+        self.conns().iter().map(|c| c.deadline).min()
+    }
+
+    /// Remove and return one connection whose deadline has passed, and
+    /// whether it was merely idle between requests.
+    pub(crate) fn take_expired(&mut self, now: u64) -> Option<(Stream, bool)> {
+        let conn = self.conns().iter().find(|c| c.deadline <= now)?;
+        let (fd, idle) = (conn.stream.fd, conn.served > 0 && conn.len == 0);
+        Some((self.remove(fd)?, idle))
+    }
+
+    /// Drop every tracked connection without a word to the peer; used by
+    /// workers right after fork.
+    pub(crate) fn close_all(&mut self) {
+        while let Some(conn) = self.conns().first() {
+            let fd = conn.stream.fd;
+            if let Some(stream) = self.remove(fd) {
+                stream.abandon();
+            }
+        }
+    }
+}
+
+/// Length of the request head at the start of `buf`, if it is complete.
+fn head_len(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|w| w == b"\r\n\r\n").map(|i| i + 4)
+}
@@ -0,0 +1,262 @@
+//! Session recordings in asciicast v2 format.
+//!
+//! With `record-dir` set, a session's output is written to `ID.cast` there
+//! as it happens: a header line, then one `[seconds, "o", "data"]` event
+//! per read from the PTY, with `"r"` events for resizes and, with
+//! `record-input`, `"i"` events for what is typed. The file is synced when
+//! the session ends.
+
+use crate::config::Config;
+use crate::sys;
+use crate::sys::pty::WinSize;
+
+const FILE_MODE: usize = 0o600;
+
+pub(crate) struct Recorder {
+    /// The `.cast` file; `usize::MAX` when not recording.
+    fd: usize,
+    input: bool,
+    start_ms: u64,
+    /// Start of a UTF-8 character split between two reads of output.
+    carry: [u8; 4],
+    carry_len: usize,
+    buf: [u8; 4096],
+    len: usize,
+}
+
+impl Recorder {
+    /// Start recording session `id` running `command` with `args` on a
+    /// terminal of `size`. Records nothing without `record-dir`, or if the
+    /// file cannot be created.
+    pub(crate) fn start(
+        cfg: &Config,
+        id: &[u8],
+        size: &WinSize,
+        command: &str,
+        args: &str,
+        term: Option<&[u8]>,
+    ) -> Self {
+        let mut rec = Self {
+            fd: usize::MAX,
+            input: cfg.record_input,
+            start_ms: sys::timer::monotonic_ms(),
+            carry: [0; 4],
+            carry_len: 0,
+            buf: [0; 4096],
+            len: 0,
+        };
+        let Some(dir) = cfg.record_dir_fd else {
+            return rec;
+        };
+        let mut name = [0u8; 64];
+        name[..id.len()].copy_from_slice(id);
+        name[id.len()..id.len() + 5].copy_from_slice(b".cast");
+        let flags = sys::fs::O_WRONLY | sys::fs::O_CREAT | sys::fs::O_EXCL | sys::fs::O_CLOEXEC;
+        match sys::fs::openat(dir, name.as_ptr(), flags, FILE_MODE) {
+            Ok(fd) => rec.fd = fd,
+            Err(_) => {
+                super::log(b"recording failed: cannot create file\n");
+                return rec;
+            }
+        }
+        rec.push(b"{\"version\":2,\"width\":");
+        rec.num(size.ws_col as u64);
+        rec.push(b",\"height\":");
+        rec.num(size.ws_row as u64);
+        rec.push(b",\"timestamp\":");
+        rec.num(sys::timer::unix_time());
+        rec.push(b",\"command\":\"");
+        rec.text(command.as_bytes());
+        let args = args.trim();
+        if !args.is_empty() {
+            rec.push(b" ");
+            rec.text(args.as_bytes());
+        }
+        rec.push(b"\",\"env\":{");
+        if let Some(term) = term {
+            rec.push(b"\"TERM\":\"");
+            rec.text(term);
+            rec.push(b"\"");
+        }
+        rec.push(b"}}\n");
+        rec.flush();
+        rec
+    }
+
+    /// Record a read of PTY output.
+    pub(crate) fn output(&mut self, data: &[u8]) {
+        if self.fd == usize::MAX {
+            return;
+        }
+        self.event(b"o");
+        self.text_carrying(data);
+        self.end_event();
+    }
+
+    /// Record input from the client or a participant, if asked to.
+    pub(crate) fn input(&mut self, data: &[u8]) {
+        if self.fd == usize::MAX || !self.input {
+            return;
+        }
+        self.event(b"i");
+        self.text(data);
+        self.end_event();
+    }
+
+    pub(crate) fn resize(&mut self, ws: &WinSize) {
+        if self.fd == usize::MAX {
+            return;
+        }
+        self.event(b"r");
+        self.num(ws.ws_col as u64);
+        self.push(b"x");
+        self.num(ws.ws_row as u64);
+        self.end_event();
+    }
+
+    /// Sync and close the file.
+    pub(crate) fn finish(mut self) {
+        if self.fd == usize::MAX {
+            return;
+        }
+        if self.carry_len > 0 {
+            self.event(b"o");
+            self.push(b"\\ufffd");
+            self.end_event();
+        }
+        if sys::fs::fsync(self.fd).is_err() {
+            super::log(b"recording failed: fsync\n");
+        }
+        let _ = sys::fs::close(self.fd);
+    }
+
+    /// Open an event at the time elapsed since the start, in seconds with
+    /// millisecond precision, up to the opening quote of its data.
+    fn event(&mut self, code: &[u8]) {
+        let ms = sys::timer::monotonic_ms() - self.start_ms;
+        self.push(b"[");
+        self.num(ms / 1000);
+        let frac = ms % 1000;
+        self.push(&[
+            b'.',
+            b'0' + (frac / 100) as u8,
+            b'0' + (frac / 10 % 10) as u8,
+            b'0' + (frac % 10) as u8,
+        ]);
+        self.push(b", \"");
+        self.push(code);
+        self.push(b"\", \"");
+    }
+
+    fn end_event(&mut self) {
+        self.push(b"\"]\n");
+        self.flush();
+    }
+
+    /// Append `data` as JSON string content, holding back a character cut
+    /// off at the end until the next call completes it.
+    fn text_carrying(&mut self, mut data: &[u8]) {
+        if self.carry_len > 0 {
+            let want = utf8_width(self.carry[0]);
+            while self.carry_len < want && data.first().is_some_and(|&b| b & 0xC0 == 0x80) {
+                self.carry[self.carry_len] = data[0];
+                self.carry_len += 1;
+                data = &data[1..];
+            }
+            if self.carry_len < want && data.is_empty() {
+                return;
+            }
+            let (carry, n) = (self.carry, self.carry_len);
+            self.carry_len = 0;
+            self.text(&carry[..n]);
+        }
+        let cut = data.len() - incomplete_tail(data);
+        self.text(&data[..cut]);
+        self.carry_len = data.len() - cut;
+        self.carry[..self.carry_len].copy_from_slice(&data[cut..]);
+    }
+
+    /// Append `data` as JSON string content; invalid UTF-8 becomes U+FFFD.
+    fn text(&mut self, data: &[u8]) {
+        for chunk in data.utf8_chunks() {
+            let valid = chunk.valid().as_bytes();
+            let mut from = 0;
+            for (i, &b) in valid.iter().enumerate() {
+                if b >= 0x20 && b != b'"' && b != b'\\' && b != 0x7f {
+                    continue;
+                }
+                self.push(&valid[from..i]);
+                match b {
+                    b'"' => self.push(b"\\\""),
+                    b'\\' => self.push(b"\\\\"),
+                    b'\n' => self.push(b"\\n"),
+                    b'\r' => self.push(b"\\r"),
+                    b'\t' => self.push(b"\\t"),
+                    _ => self.push(&[
+                        b'\\',
+                        b'u',
+                        b'0',
+                        b'0',
+                        b"0123456789abcdef"[(b >> 4) as usize],
+                        b"0123456789abcdef"[(b & 15) as usize],
+                    ]),
+                }
+                from = i + 1;
+            }
+            self.push(&valid[from..]);
+            if !chunk.invalid().is_empty() {
+                self.push(b"\\ufffd");
+            }
+        }
+    }
+
+    fn num(&mut self, n: u64) {
+        let mut num = itoa::Buffer::new();
+        self.push(num.format(n).as_bytes());
+    }
+
+    fn push(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            if self.len == self.buf.len() {
+                self.flush();
+            }
+            let n = core::cmp::min(bytes.len(), self.buf.len() - self.len);
+            self.buf[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+            self.len += n;
+            bytes = &bytes[n..];
+        }
+    }
+
+    /// Write out what is buffered. A failed write ends the recording; the
+    /// session carries on.
+    fn flush(&mut self) {
+        let len = core::mem::take(&mut self.len);
+        if self.fd == usize::MAX || sys::fs::write_all(self.fd, &self.buf[..len]).is_ok() {
+            return;
+        }
+        super::log(b"recording failed: write error\n");
+        let _ = sys::fs::close(self.fd);
+        self.fd = usize::MAX;
+    }
+}
+
+/// Length of the UTF-8 sequence `lead` starts; 1 for anything else.
+fn utf8_width(lead: u8) -> usize {
+    match lead {
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => 1,
+    }
+}
+
+/// Length of a UTF-8 sequence cut short at the end of `data`.
+fn incomplete_tail(data: &[u8]) -> usize {
+    for back in 1..=core::cmp::min(3, data.len()) {
+        let b = data[data.len() - back];
+        if b & 0xC0 != 0x80 {
+            return if utf8_width(b) > back { back } else { 0 };
+        }
+    }
+    0
+}
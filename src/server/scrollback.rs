@@ -0,0 +1,98 @@
+//! Recent PTY output, kept by the bridge so a client resuming a session
+//! does not start from a blank screen.
+
+use crate::runtime::{allocator, util};
+
+const ESC: u8 = 0x1b;
+
+/// Ring of the last `scrollback` bytes of output.
+pub(crate) struct Scrollback {
+    buf: *mut u8,
+    cap: usize,
+    /// Where the next byte goes; the oldest byte once the ring is full.
+    head: usize,
+    len: usize,
+    /// Older output has been overwritten, so the ring may start in the
+    /// middle of a character or escape sequence.
+    cut: bool,
+}
+
+impl Scrollback {
+    /// A ring of `cap` bytes; zero keeps nothing.
+    pub(crate) fn new(cap: usize) -> Result<Self, &'static str> {
+        let buf = if cap > 0 {
+            allocator::page_alloc(cap).map_err(|_| "mmap scrollback")?
+        } else {
+            core::ptr::null_mut()
+        };
+        Ok(Self {
+            buf,
+            cap,
+            head: 0,
+            len: 0,
+            cut: false,
+        })
+    }
+
+    pub(crate) fn push(&mut self, mut data: &[u8]) {
+        if self.cap == 0 {
+            return;
+        }
+        self.cut |= self.len + data.len() > self.cap;
+        if data.len() > self.cap {
+            data = &data[data.len() - self.cap..];
+        }
+        let ring = util::ptr_to_mut_slice(self.buf, self.cap);
+        let first = core::cmp::min(data.len(), self.cap - self.head);
+        ring[self.head..self.head + first].copy_from_slice(&data[..first]);
+        ring[..data.len() - first].copy_from_slice(&data[first..]);
+        self.head = (self.head + data.len()) % self.cap;
+        self.len = core::cmp::min(self.len + data.len(), self.cap);
+    }
+
+    /// The kept output, oldest first, as two slices. Once older output was
+    /// overwritten it starts at the first escape sequence or line after
+    /// the cut: both begin on a character boundary with the terminal's
+    /// parser in its ground state, so no stray parameters or half
+    /// characters are shown. The end is left as is: live output carries on
+    /// from exactly there.
+    pub(crate) fn replay(&self) -> (&[u8], &[u8]) {
+        if self.cap == 0 {
+            return (&[], &[]);
+        }
+        let ring = util::ptr_to_slice(self.buf, self.cap);
+        let (older, newer) = if self.len < self.cap {
+            (&ring[..self.len], &ring[..0])
+        } else {
+            (&ring[self.head..], &ring[..self.head])
+        };
+        if !self.cut {
+            return (older, newer);
+        }
+        let start = older
+            .iter()
+            .chain(newer)
+            .position(|&b| b == ESC || b == b'\n')
+            .map_or(self.len, |i| {
+                let at = if i < older.len() {
+                    older[i]
+                } else {
+                    newer[i - older.len()]
+                };
+                if at == b'\n' { i + 1 } else { i }
+            });
+        if start <= older.len() {
+            (&older[start..], newer)
+        } else {
+            (&newer[start - older.len()..], &newer[..0])
+        }
+    }
+}
+
+impl Drop for Scrollback {
+    fn drop(&mut self) {
+        if self.cap > 0 {
+            let _ = allocator::page_free(self.buf, self.cap);
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! Terminal sessions that outlive their connection.
+//!
+//! Every WebSocket session gets a public id, a secret resume token and a
+//! secret join token, and its worker one end of a `SOCK_SEQPACKET` pair
+//! whose other end the parent files under them. An upgrade with
+//! `?resume=TOKEN` is answered by the parent, which then passes the
+//! connection's descriptor (and TLS state) down that pair: the worker puts
+//! it in place of the client it lost, or still had. Each resume replaces
+//! the token. An upgrade with `?watch=ID` is passed on the same way, for
+//! the worker to add as a read-only viewer, and one with `?join=TOKEN` for
+//! it to add as a participant who may type.
+
+use crate::net::stream::Stream;
+use crate::runtime::{allocator, util};
+use crate::sys;
+use crate::sys::pty::WinSize;
+
+/// Hex digits in a session id and in a resume or join token.
+pub(crate) const ID_LEN: usize = 16;
+pub(crate) const TOKEN_LEN: usize = 32;
+
+/// Handoff message: a kind byte, the requested columns and rows (zero for
+/// unchanged), the new resume token (unused for anyone else), then the TLS
+/// state if any.
+const RESUME: u8 = 1;
+const WATCH: u8 = 2;
+const COLLABORATE: u8 = 3;
+const HEADER_LEN: usize = 5 + TOKEN_LEN;
+const MAX_HANDOFF: usize = HEADER_LEN + Stream::MAX_HANDOFF_STATE;
+
+/// What a connection handed to a session's worker is for.
+pub(crate) enum Join {
+    /// Take over as the session's client, at the given terminal size.
+    Resume(Option<WinSize>),
+    /// Watch the session without being able to type into it.
+    Watch,
+    /// Type into the session alongside its client, until the client
+    /// revokes that.
+    Collaborate,
+}
+
+#[derive(Copy, Clone)]
+pub(crate) struct Ids {
+    pub(crate) id: [u8; ID_LEN],
+    pub(crate) token: [u8; TOKEN_LEN],
+    /// Admits participants; unlike the resume token it never changes.
+    pub(crate) join: [u8; TOKEN_LEN],
+}
+
+impl Ids {
+    pub(crate) fn generate() -> Result<Self, &'static str> {
+        let mut ids = Ids {
+            id: [0; ID_LEN],
+            token: [0; TOKEN_LEN],
+            join: [0; TOKEN_LEN],
+        };
+        random_hex(&mut ids.id)?;
+        random_hex(&mut ids.token)?;
+        random_hex(&mut ids.join)?;
+        Ok(ids)
+    }
+}
+
+fn random_hex(out: &mut [u8]) -> Result<(), &'static str> {
+    let mut raw = [0u8; TOKEN_LEN / 2];
+    let raw = &mut raw[..out.len() / 2];
+    sys::random::getrandom(raw).map_err(|_| "getrandom")?;
+    for (pair, b) in out.chunks_exact_mut(2).zip(raw.iter()) {
+        pair[0] = b"0123456789abcdef"[(b >> 4) as usize];
+        pair[1] = b"0123456789abcdef"[(b & 15) as usize];
+    }
+    Ok(())
+}
+
+struct Entry {
+    pid: i32,
+    /// Parent's end of the worker's socket pair.
+    ctl: usize,
+    ids: Ids,
+}
+
+/// The parent's table of live sessions, bounded by `max-workers`.
+pub(crate) struct Sessions {
+    entries: *mut Entry,
+    cap: usize,
+    len: usize,
+}
+
+impl Sessions {
+    pub(crate) fn new(cap: usize) -> Result<Self, &'static str> {
+        let entries = allocator::page_alloc(cap * core::mem::size_of::<Entry>())
+            .map_err(|_| "mmap sessions")?;
+        Ok(Self {
+            entries: entries as *mut Entry,
+            cap,
+            len: 0,
+        })
+    }
+
+    fn entries(&mut self) -> &mut [Entry] {
+        &mut util::ptr_to_mut_slice_of(self.entries, self.cap)[..self.len]
+    }
+
+    /// File the session run by worker `pid`; `ctl` is closed if the table
+    /// is full.
+    fn add(&mut self, pid: i32, ctl: usize, ids: Ids) {
+        if self.len < self.cap {
+            util::ptr_to_mut_slice_of(self.entries, self.cap)[self.len] = Entry { pid, ctl, ids };
+            self.len += 1;
+        } else {
+            let _ = sys::fs::close(ctl);
+        }
+    }
+
+    /// Forget worker `pid`'s session, if it had one.
+    pub(crate) fn remove(&mut self, pid: i32) {
+        let last = self.len.wrapping_sub(1);
+        let entries = self.entries();
+        if let Some(i) = entries.iter().position(|e| e.pid == pid) {
+            let _ = sys::fs::close(entries[i].ctl);
+            entries.swap(i, last);
+            self.len -= 1;
+        }
+    }
+
+    /// Index of the session `token` resumes. Every entry is compared in
+    /// full, so the time taken does not tell how much of a token matched.
+    pub(crate) fn find(&mut self, token: &[u8]) -> Option<usize> {
+        self.entries()
+            .iter()
+            .enumerate()
+            .fold(None, |found, (i, e)| {
+                if same(&e.ids.token, token) {
+                    Some(i)
+                } else {
+                    found
+                }
+            })
+    }
+
+    /// Index of the session named `id`, compared the same way.
+    pub(crate) fn find_id(&mut self, id: &[u8]) -> Option<usize> {
+        self.entries()
+            .iter()
+            .enumerate()
+            .fold(
+                None,
+                |found, (i, e)| {
+                    if same(&e.ids.id, id) { Some(i) } else { found }
+                },
+            )
+    }
+
+    /// Index of the session `token` lets participants join.
+    pub(crate) fn find_join(&mut self, token: &[u8]) -> Option<usize> {
+        self.entries()
+            .iter()
+            .enumerate()
+            .fold(None, |found, (i, e)| {
+                if same(&e.ids.join, token) {
+                    Some(i)
+                } else {
+                    found
+                }
+            })
+    }
+
+    /// Give the upgraded `stream` to the worker of session `i`; a resume
+    /// also gets a fresh token. The parent's copy of the connection is
+    /// released either way.
+    pub(crate) fn hand_over(
+        &mut self,
+        i: usize,
+        mut stream: Stream,
+        join: Join,
+    ) -> Result<(), &'static str> {
+        let entry = &mut self.entries()[i];
+        let res = Ids::generate().and_then(|fresh| {
+            let mut header = [0u8; HEADER_LEN];
+            match join {
+                Join::Resume(size) => {
+                    header[0] = RESUME;
+                    let (cols, rows) = size.map_or((0, 0), |ws| (ws.ws_col, ws.ws_row));
+                    header[1..3].copy_from_slice(&cols.to_le_bytes());
+                    header[3..5].copy_from_slice(&rows.to_le_bytes());
+                    header[5..].copy_from_slice(&fresh.token);
+                }
+                Join::Watch => header[0] = WATCH,
+                Join::Collaborate => header[0] = COLLABORATE,
+            }
+            let fd = stream.fd;
+            sys::net::send_fd(entry.ctl, &[&header, stream.handoff_state()], fd)
+                .map_err(|_| "handoff send")?;
+            if header[0] == RESUME {
+                entry.ids.token = fresh.token;
+            }
+            Ok(())
+        });
+        stream.abandon();
+        res
+    }
+
+    /// Close every socket pair end; for a freshly forked worker, which has
+    /// no business with other sessions.
+    pub(crate) fn close_all(&mut self) {
+        for e in self.entries().iter() {
+            let _ = sys::fs::close(e.ctl);
+        }
+        self.len = 0;
+    }
+}
+
+/// Whether `given` is `stored`, looking at every byte either way.
+fn same(stored: &[u8], given: &[u8]) -> bool {
+    let diff = stored
+        .iter()
+        .enumerate()
+        .fold((given.len() != stored.len()) as u8, |acc, (j, &x)| {
+            acc | (x ^ given.get(j).copied().unwrap_or(0))
+        });
+    diff == 0
+}
+
+/// A worker's side of its session.
+pub(crate) struct Handle {
+    /// Worker's end of the socket pair; `usize::MAX` once the parent is
+    /// gone.
+    pub(crate) ctl: usize,
+    pub(crate) ids: Ids,
+}
+
+impl Handle {
+    /// Take the connection the parent handed over, and what it is for.
+    /// `Ok(None)` if the parent has closed its end.
+    pub(crate) fn receive(&mut self) -> Result<Option<(Stream, Join)>, &'static str> {
+        let buf_ptr = allocator::page_alloc(MAX_HANDOFF).map_err(|_| "mmap handoff")?;
+        let buf = util::ptr_to_mut_slice(buf_ptr, MAX_HANDOFF);
+        let res = match sys::net::recv_fd(self.ctl, buf) {
+            Ok((0, _)) => Ok(None),
+            Ok((n, Some(fd))) if n >= HEADER_LEN && (RESUME..=COLLABORATE).contains(&buf[0]) => {
+                match Stream::adopt(fd, &buf[HEADER_LEN..n]) {
+                    Ok(stream) if buf[0] == WATCH => Ok(Some((stream, Join::Watch))),
+                    Ok(stream) if buf[0] == COLLABORATE => Ok(Some((stream, Join::Collaborate))),
+                    Ok(stream) => {
+                        self.ids.token.copy_from_slice(&buf[5..HEADER_LEN]);
+                        let cols = u16::from_le_bytes([buf[1], buf[2]]);
+                        let rows = u16::from_le_bytes([buf[3], buf[4]]);
+                        let size = (cols > 0 && rows > 0).then(|| WinSize::new(cols, rows));
+                        Ok(Some((stream, Join::Resume(size))))
+                    }
+                    Err(e) => {
+                        let _ = sys::fs::close(fd);
+                        Err(e)
+                    }
+                }
+            }
+            Ok((_, fd)) => {
+                if let Some(fd) = fd {
+                    let _ = sys::fs::close(fd);
+                }
+                Err("malformed handoff")
+            }
+            Err(_) => Err("handoff recv"),
+        };
+        let _ = allocator::page_free(buf_ptr, MAX_HANDOFF);
+        res
+    }
+}
+
+/// A session about to be forked: its ids and both ends of its socket pair.
+pub(crate) struct Fresh {
+    ids: Ids,
+    parent: usize,
+    child: usize,
+}
+
+impl Fresh {
+    pub(crate) fn new() -> Result<Self, &'static str> {
+        let ids = Ids::generate()?;
+        let (parent, child) =
+            sys::net::socketpair(sys::net::SOCK_SEQPACKET).map_err(|_| "socketpair")?;
+        Ok(Self { ids, parent, child })
+    }
+
+    /// In the parent, once the worker is forked.
+    pub(crate) fn file(self, sessions: &mut Sessions, pid: i32) {
+        let _ = sys::fs::close(self.child);
+        sessions.add(pid, self.parent, self.ids);
+    }
+
+    /// In the worker.
+    pub(crate) fn into_handle(self) -> Handle {
+        let _ = sys::fs::close(self.parent);
+        Handle {
+            ctl: self.child,
+            ids: self.ids,
+        }
+    }
+
+    /// When no worker could be forked.
+    pub(crate) fn discard(self) {
+        let _ = sys::fs::close(self.parent);
+        let _ = sys::fs::close(self.child);
+    }
+}
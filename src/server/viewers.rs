@@ -0,0 +1,464 @@
+//! Viewers of a session, and participants who may type into it.
+//!
+//! A viewer is sent the same output as the session's client. Whatever it
+//! types is dropped, unless it joined as a participant with write access,
+//! which the client can take away again. Its frames are queued in an outbox
+//! of its own and sent as fast as its socket takes them, so a viewer on a
+//! slow link never holds up the shell or the client; one that falls further
+//! behind than its outbox holds is dropped.
+
+use super::control;
+use super::recorder::Recorder;
+use super::scrollback::Scrollback;
+use crate::config::{Config, MAX_VIEWERS};
+use crate::net::stream::Stream;
+use crate::net::ws::{self, FrameDecoder, Message};
+use crate::runtime::{allocator, util};
+use crate::sys;
+use crate::sys::SysResult;
+use crate::sys::epoll::{EPOLLIN, EPOLLOUT};
+use crate::sys::pty::WinSize;
+
+const ENOBUFS: isize = -105;
+
+/// Someone joined or left, to be announced to the client and every viewer.
+#[derive(Copy, Clone)]
+pub(crate) enum Change {
+    Joined { participant: u32, write: bool },
+    Left { participant: u32 },
+}
+
+/// The close frame a viewer is let go with, if any.
+type Close<'a> = Option<(u16, &'a [u8])>;
+
+/// Sealed frames a viewer's socket has not taken yet.
+struct Outbox {
+    buf: *mut u8,
+    cap: usize,
+    start: usize,
+    end: usize,
+}
+
+impl Outbox {
+    fn new(cap: usize) -> Result<Self, &'static str> {
+        let buf = allocator::page_alloc(cap).map_err(|_| "mmap outbox")?;
+        Ok(Self {
+            buf,
+            cap,
+            start: 0,
+            end: 0,
+        })
+    }
+
+    /// Append `data`; `ENOBUFS` if it does not fit.
+    fn push(&mut self, data: &[u8]) -> SysResult<()> {
+        let buf = util::ptr_to_mut_slice(self.buf, self.cap);
+        if self.end + data.len() > self.cap {
+            buf.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+        }
+        if self.end + data.len() > self.cap {
+            return Err(ENOBUFS);
+        }
+        buf[self.end..self.end + data.len()].copy_from_slice(data);
+        self.end += data.len();
+        Ok(())
+    }
+
+    /// Send as much as `fd` takes without blocking; `Ok(true)` once
+    /// nothing is left.
+    fn flush(&mut self, fd: usize) -> SysResult<bool> {
+        let buf = util::ptr_to_slice(self.buf, self.cap);
+        while self.start < self.end {
+            match sys::net::send_nonblocking(fd, &buf[self.start..self.end]) {
+                Ok(n) => self.start += n,
+                Err(super::EAGAIN) => return Ok(false),
+                Err(e) => return Err(e),
+            }
+        }
+        self.start = 0;
+        self.end = 0;
+        Ok(true)
+    }
+}
+
+impl Drop for Outbox {
+    fn drop(&mut self) {
+        let _ = allocator::page_free(self.buf, self.cap);
+    }
+}
+
+struct Viewer {
+    stream: Stream,
+    decoder: FrameDecoder,
+    outbox: Outbox,
+    ping_seq: u64,
+    missed_pongs: u32,
+    /// The outbox is not empty, so epoll also reports when the socket has
+    /// room.
+    waiting: bool,
+    /// Number it was announced under.
+    participant: u32,
+    /// Joined to type along; `write` tells whether it still may.
+    collaborator: bool,
+    write: bool,
+}
+
+impl Viewer {
+    fn queue(&mut self, opcode: u8, payload: &[u8]) -> SysResult<()> {
+        queue(&mut self.stream, &mut self.outbox, opcode, payload)
+    }
+
+    /// Queue a frame and send what the socket takes now; the rest goes
+    /// out as epoll reports room.
+    fn send(&mut self, epfd: usize, opcode: u8, payload: &[u8]) -> SysResult<()> {
+        self.queue(opcode, payload)?;
+        self.flush(epfd)
+    }
+
+    fn flush(&mut self, epfd: usize) -> SysResult<()> {
+        let empty = self.outbox.flush(self.stream.fd)?;
+        if empty == self.waiting {
+            self.waiting = !empty;
+            let events = if empty { EPOLLIN } else { EPOLLIN | EPOLLOUT };
+            sys::epoll::epoll_mod(epfd, self.stream.fd, events)?;
+        }
+        Ok(())
+    }
+
+    /// Handle what the viewer sent: input for the PTY if it may write,
+    /// pings, pongs and its close; anything else is dropped. `Some` when
+    /// the viewer is done.
+    fn serve_input(
+        &mut self,
+        epfd: usize,
+        pty_fd: usize,
+        recorder: &mut Recorder,
+    ) -> Option<Close<'static>> {
+        let Viewer {
+            stream,
+            decoder,
+            outbox,
+            ping_seq,
+            missed_pongs,
+            write,
+            ..
+        } = self;
+        // As for the client: TLS may hold records epoll will not report.
+        loop {
+            let r = match stream.recv(decoder.spare()) {
+                Ok(v) => v,
+                Err(super::EAGAIN) => break,
+                Err(_) => return Some(None),
+            };
+            if r == 0 {
+                return Some(None);
+            }
+            decoder.fill(r);
+            loop {
+                match decoder.next_message() {
+                    Ok(None) => break,
+                    Ok(Some(Message::Binary(payload))) => {
+                        if *write {
+                            recorder.input(payload);
+                            let _ = sys::fs::write_all(pty_fd, payload);
+                        }
+                    }
+                    Ok(Some(Message::Text(_))) => {}
+                    Ok(Some(Message::Ping(payload))) => {
+                        if queue(stream, outbox, ws::OPCODE_PONG, payload).is_err() {
+                            return Some(None);
+                        }
+                    }
+                    Ok(Some(Message::Pong(payload))) => {
+                        if payload == ping_seq.to_be_bytes() {
+                            *missed_pongs = 0;
+                        }
+                    }
+                    Ok(Some(Message::Close(payload))) => {
+                        return Some(match ws::parse_close(payload) {
+                            Ok(code) => Some((code, b"")),
+                            Err(code) => Some((code, b"invalid close frame")),
+                        });
+                    }
+                    Err(e) => return Some(Some((ws::close_code_for(e), e.as_bytes()))),
+                }
+            }
+            if !stream.buffered() {
+                break;
+            }
+        }
+        self.flush(epfd).err().map(|_| None)
+    }
+
+    /// Send `close` if given and hang up. Nothing waits for the viewer:
+    /// the close frame only goes out if everything queued before it fits
+    /// in the socket now.
+    fn leave(mut self, epfd: usize, close: Close<'_>) {
+        let _ = sys::epoll::epoll_del(epfd, self.stream.fd);
+        let mut payload = [0u8; 125];
+        let closed = close.is_some_and(|(code, reason)| {
+            let n = ws::close_payload(code, reason, &mut payload);
+            self.queue(ws::OPCODE_CLOSE, &payload[..n]).is_ok()
+                && self.outbox.flush(self.stream.fd) == Ok(true)
+        });
+        if closed {
+            self.stream.shutdown();
+        } else {
+            let _ = sys::net::shutdown(self.stream.fd, sys::net::SHUT_RDWR);
+        }
+        self.stream.abandon();
+        super::log(if self.collaborator {
+            b"participant left\n"
+        } else {
+            b"viewer left\n"
+        });
+    }
+}
+
+fn queue(stream: &mut Stream, outbox: &mut Outbox, opcode: u8, payload: &[u8]) -> SysResult<()> {
+    let mut hdr = [0u8; 10];
+    let n = ws::frame_header(opcode, payload.len(), &mut hdr);
+    stream.seal(&hdr[..n], &mut |record| outbox.push(record))?;
+    stream.seal(payload, &mut |record| outbox.push(record))
+}
+
+/// A session's viewers, at most `max-viewers` of them.
+pub(crate) struct Viewers {
+    slots: [Option<Viewer>; MAX_VIEWERS],
+    limit: usize,
+    /// Outbox size: the scrollback replay plus a few reads of output.
+    outbox: usize,
+    max_message: usize,
+    last_participant: u32,
+    /// Joins and leaves not announced yet, oldest first. Each viewer makes
+    /// at most one of each between announcements.
+    changes: [Change; 2 * MAX_VIEWERS],
+    pending: usize,
+}
+
+impl Viewers {
+    pub(crate) fn new(cfg: &Config) -> Self {
+        Self {
+            slots: [const { None }; MAX_VIEWERS],
+            limit: cfg.max_viewers,
+            outbox: cfg.scrollback + 4 * cfg.buffer_size + 4096,
+            max_message: cfg.max_message,
+            last_participant: 0,
+            changes: [Change::Left { participant: 0 }; 2 * MAX_VIEWERS],
+            pending: 0,
+        }
+    }
+
+    /// Admit `stream` to watch a terminal of `size`, or to type into it as
+    /// well if `write`, starting it off with its number, the scrollback and
+    /// who else is there. It is turned away with 1013 when the session has
+    /// no room for another.
+    pub(crate) fn add(
+        &mut self,
+        epfd: usize,
+        mut stream: Stream,
+        write: bool,
+        size: &WinSize,
+        scrollback: &Scrollback,
+    ) {
+        let Some(slot) = self.slots[..self.limit].iter().position(Option::is_none) else {
+            super::log(b"viewer refused: session full\n");
+            let _ =
+                ws::write_close_frame(&mut stream, ws::CLOSE_TRY_AGAIN_LATER, b"too many viewers");
+            stream.close();
+            return;
+        };
+        let (decoder, outbox) = match (
+            FrameDecoder::new(self.max_message),
+            Outbox::new(self.outbox),
+        ) {
+            (Ok(decoder), Ok(outbox)) => (decoder, outbox),
+            _ => {
+                stream.close();
+                return;
+            }
+        };
+        if sys::epoll::epoll_add(epfd, stream.fd, EPOLLIN).is_err() {
+            stream.close();
+            return;
+        }
+        self.last_participant += 1;
+        let mut viewer = Viewer {
+            stream,
+            decoder,
+            outbox,
+            ping_seq: 0,
+            missed_pongs: 0,
+            waiting: false,
+            participant: self.last_participant,
+            collaborator: write,
+            write,
+        };
+        let mut msg = [0u8; 64];
+        let n = control::resize_message(size, &mut msg);
+        let (older, newer) = scrollback.replay();
+        let mut welcome = [0u8; 64];
+        let w =
+            control::participant_message(b"welcome", viewer.participant, Some(write), &mut welcome);
+        let started = viewer.queue(ws::OPCODE_TEXT, &welcome[..w]).is_ok()
+            && viewer.queue(ws::OPCODE_TEXT, &msg[..n]).is_ok()
+            && [older, newer]
+                .iter()
+                .all(|part| part.is_empty() || viewer.queue(ws::OPCODE_BINARY, part).is_ok())
+            && self.roster().all(|(participant, write)| {
+                let n = control::participant_message(b"join", participant, Some(write), &mut msg);
+                viewer.queue(ws::OPCODE_TEXT, &msg[..n]).is_ok()
+            })
+            && viewer.flush(epfd).is_ok();
+        if !started {
+            viewer.leave(epfd, None);
+            return;
+        }
+        super::log(if write {
+            b"participant joined\n"
+        } else {
+            b"viewer joined\n"
+        });
+        self.record(Change::Joined {
+            participant: viewer.participant,
+            write,
+        });
+        self.slots[slot] = Some(viewer);
+    }
+
+    /// Everyone there, by number, and whether they may write.
+    pub(crate) fn roster(&self) -> impl Iterator<Item = (u32, bool)> + '_ {
+        self.slots
+            .iter()
+            .flatten()
+            .map(|v| (v.participant, v.write))
+    }
+
+    /// Take away `participant`'s write access; `false` if it had none.
+    pub(crate) fn revoke(&mut self, participant: u32) -> bool {
+        self.slots
+            .iter_mut()
+            .flatten()
+            .find(|v| v.participant == participant && v.write)
+            .map(|v| v.write = false)
+            .is_some()
+    }
+
+    /// The oldest join or leave not announced yet.
+    pub(crate) fn next_change(&mut self) -> Option<Change> {
+        if self.pending == 0 {
+            return None;
+        }
+        let change = self.changes[0];
+        self.changes.copy_within(1..self.pending, 0);
+        self.pending -= 1;
+        Some(change)
+    }
+
+    fn record(&mut self, change: Change) {
+        if self.pending < self.changes.len() {
+            self.changes[self.pending] = change;
+            self.pending += 1;
+        }
+    }
+
+    fn remove(&mut self, epfd: usize, i: usize, close: Close<'_>) {
+        if let Some(v) = self.slots[i].take() {
+            self.record(Change::Left {
+                participant: v.participant,
+            });
+            v.leave(epfd, close);
+        }
+    }
+
+    /// Send a frame to every viewer, dropping those too far behind to
+    /// take it.
+    pub(crate) fn broadcast(&mut self, epfd: usize, opcode: u8, payload: &[u8]) {
+        for i in 0..MAX_VIEWERS {
+            if let Some(v) = self.slots[i].as_mut()
+                && let Err(e) = v.send(epfd, opcode, payload)
+            {
+                if e == ENOBUFS {
+                    super::log(b"viewer fell too far behind\n");
+                }
+                self.remove(epfd, i, None);
+            }
+        }
+    }
+
+    /// Tell every viewer the terminal is now `size`.
+    pub(crate) fn resized(&mut self, epfd: usize, size: &WinSize) {
+        let mut msg = [0u8; 64];
+        let n = control::resize_message(size, &mut msg);
+        self.broadcast(epfd, ws::OPCODE_TEXT, &msg[..n]);
+    }
+
+    /// Handle readiness of `fd` if it is a viewer's: send more of its
+    /// outbox, read what it sent. `false` if it is not a viewer's.
+    pub(crate) fn serve(
+        &mut self,
+        epfd: usize,
+        fd: usize,
+        events: u32,
+        pty_fd: usize,
+        recorder: &mut Recorder,
+    ) -> bool {
+        let Some(i) = self
+            .slots
+            .iter()
+            .position(|s| s.as_ref().is_some_and(|v| v.stream.fd == fd))
+        else {
+            return false;
+        };
+        let done = self.slots[i].as_mut().and_then(|v| {
+            if events & EPOLLOUT != 0 && v.flush(epfd).is_err() {
+                Some(None)
+            } else if events & !EPOLLOUT != 0 {
+                v.serve_input(epfd, pty_fd, recorder)
+            } else {
+                None
+            }
+        });
+        if let Some(close) = done {
+            self.remove(epfd, i, close);
+        }
+        true
+    }
+
+    /// One keepalive tick for every viewer, as for the client.
+    pub(crate) fn ping(&mut self, epfd: usize, max_missed: u32) {
+        for i in 0..MAX_VIEWERS {
+            let Some(v) = self.slots[i].as_mut() else {
+                continue;
+            };
+            let done = if v.missed_pongs >= max_missed {
+                Some(Some((ws::CLOSE_GOING_AWAY, &b"keepalive timeout"[..])))
+            } else {
+                v.ping_seq += 1;
+                v.missed_pongs += 1;
+                let seq = v.ping_seq.to_be_bytes();
+                v.send(epfd, ws::OPCODE_PING, &seq).err().map(|_| None)
+            };
+            if let Some(close) = done {
+                self.remove(epfd, i, close);
+            }
+        }
+    }
+
+    /// Let every viewer go at the end of the session, after the `exit`
+    /// message if there is one.
+    pub(crate) fn close_all(&mut self, epfd: usize, exit: &[u8], close: Close<'_>) {
+        for slot in self.slots.iter_mut() {
+            if let Some(mut v) = slot.take() {
+                let close = if exit.is_empty() || v.queue(ws::OPCODE_TEXT, exit).is_ok() {
+                    close
+                } else {
+                    None
+                };
+                v.leave(epfd, close);
+            }
+        }
+    }
+}
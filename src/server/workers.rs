@@ -0,0 +1,51 @@
+use crate::runtime::{allocator, util};
+use crate::sys;
+
+/// Pids of live worker processes, bounded by `max-workers`.
+pub(crate) struct Workers {
+    pids: *mut i32,
+    cap: usize,
+    len: usize,
+}
+
+impl Workers {
+    pub(crate) fn new(cap: usize) -> Result<Self, &'static str> {
+        let pids =
+            allocator::page_alloc(cap * core::mem::size_of::<i32>()).map_err(|_| "mmap workers")?;
+        Ok(Self {
+            pids: pids as *mut i32,
+            cap,
+            len: 0,
+        })
+    }
+
+    fn pids(&mut self) -> &mut [i32] {
+        &mut util::ptr_to_mut_slice_of(self.pids, self.cap)[..self.len]
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.len >= self.cap
+    }
+
+    pub(crate) fn add(&mut self, pid: i32) {
+        if self.len < self.cap {
+            util::ptr_to_mut_slice_of(self.pids, self.cap)[self.len] = pid;
+            self.len += 1;
+        }
+    }
+
+    pub(crate) fn remove(&mut self, pid: i32) {
+        let last = self.len.wrapping_sub(1);
+        let pids = self.pids();
+        if let Some(i) = pids.iter().position(|&p| p == pid) {
+            pids[i] = pids[last];
+            self.len -= 1;
+        }
+    }
+
+    pub(crate) fn signal_all(&mut self, sig: i32) {
+        for &pid in self.pids().iter() {
+            let _ = sys::pty::kill(pid, sig);
+        }
+    }
+}
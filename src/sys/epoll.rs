@@ -6,9 +6,11 @@ const SYS_EPOLL_CTL: usize = 233;
 const SYS_EPOLL_WAIT: usize = 232;
 
 pub const EPOLLIN: u32 = 0x001;
+pub const EPOLLOUT: u32 = 0x004;
 
+/// `struct epoll_event`; the kernel packs it to 12 bytes on x86_64.
 #[derive(Copy, Clone, Default)]
-#[repr(C)]
+#[repr(C, packed)]
 pub struct EpollEvent {
     pub events: u32,
     pub data: u64,
@@ -23,6 +25,9 @@ impl EpollEvent {
     pub fn fd(&self) -> usize {
         self.data as usize
     }
+    pub fn events(&self) -> u32 {
+        self.events
+    }
 }
 
 pub fn epoll_create1() -> SysResult<usize> {
@@ -40,6 +45,29 @@ pub fn epoll_add(epfd: usize, fd: usize, events: u32) -> SysResult<()> {
     )?;
     Ok(())
 }
+/// Change the events `fd` is watched for.
+pub fn epoll_mod(epfd: usize, fd: usize, events: u32) -> SysResult<()> {
+    let mut ev = EpollEvent::new(events, fd);
+    let _ = syscall4_checked(
+        SYS_EPOLL_CTL,
+        epfd,
+        3, /*MOD*/
+        fd,
+        &mut ev as *mut _ as usize,
+    )?;
+    Ok(())
+}
+pub fn epoll_del(epfd: usize, fd: usize) -> SysResult<()> {
+    let mut ev = EpollEvent::new(0, fd);
+    let _ = syscall4_checked(
+        SYS_EPOLL_CTL,
+        epfd,
+        2, /*DEL*/
+        fd,
+        &mut ev as *mut _ as usize,
+    )?;
+    Ok(())
+}
 pub fn epoll_wait(epfd: usize, events: &mut [EpollEvent], timeout_ms: isize) -> SysResult<usize> {
     let r = syscall4_checked(
         SYS_EPOLL_WAIT,
@@ -1,9 +1,53 @@
-use crate::runtime::syscall::{syscall1_checked, syscall3_checked};
+use crate::runtime::syscall::{
+    syscall1_checked, syscall2_checked, syscall3_checked, syscall4_checked,
+};
 use crate::sys::SysResult;
 const SYS_CLOSE: usize = 3;
 const SYS_OPEN: usize = 2;
 const SYS_READ: usize = 0;
 const SYS_WRITE: usize = 1;
+const SYS_ACCESS: usize = 21;
+const SYS_CHDIR: usize = 80;
+const SYS_FSTAT: usize = 5;
+const SYS_LSTAT: usize = 6;
+const SYS_PREAD64: usize = 17;
+const SYS_SENDFILE: usize = 40;
+const SYS_UNLINK: usize = 87;
+const SYS_CHMOD: usize = 90;
+const SYS_CHOWN: usize = 92;
+const SYS_FCNTL: usize = 72;
+const SYS_OPENAT2: usize = 437;
+const SYS_OPENAT: usize = 257;
+const SYS_FSYNC: usize = 74;
+const F_GETFL: usize = 3;
+const F_SETFL: usize = 4;
+
+pub const O_RDONLY: usize = 0;
+pub const O_WRONLY: usize = 1;
+pub const O_CREAT: usize = 0o100;
+pub const O_EXCL: usize = 0o200;
+pub const O_CLOEXEC: usize = 0o2000000;
+pub const O_NONBLOCK: usize = 0o4000;
+pub const O_DIRECTORY: usize = 0o200000;
+pub const O_PATH: usize = 0o10000000;
+/// `openat2` resolve flags: no `/proc` magic links, nothing outside `dirfd`.
+pub const RESOLVE_NO_MAGICLINKS: u64 = 0x02;
+pub const RESOLVE_BENEATH: u64 = 0x08;
+pub const X_OK: usize = 1;
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFSOCK: u32 = 0o140000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+
+/// The parts of `struct stat` the server uses.
+pub struct Stat {
+    pub ino: u64,
+    pub mode: u32,
+    pub size: u64,
+    /// Modification time, seconds since the epoch.
+    pub mtime: i64,
+    pub mtime_nsec: i64,
+}
 
 pub fn close(fd: usize) -> SysResult<()> {
     let _ = syscall1_checked(SYS_CLOSE, fd)?;
@@ -17,7 +61,115 @@ pub fn write(fd: usize, buf: &[u8]) -> SysResult<usize> {
     let r = syscall3_checked(SYS_WRITE, fd, buf.as_ptr() as usize, buf.len())?;
     Ok(r as usize)
 }
+pub fn write_all(fd: usize, mut buf: &[u8]) -> SysResult<()> {
+    while !buf.is_empty() {
+        let n = write(fd, buf)?;
+        if n == 0 {
+            break;
+        }
+        buf = &buf[n..];
+    }
+    Ok(())
+}
 pub fn open(path: *const u8, flags: usize, mode: usize) -> SysResult<usize> {
     let r = syscall3_checked(SYS_OPEN, path as usize, flags, mode)?;
     Ok(r as usize)
 }
+/// `openat(2)`: open `path` (NUL-terminated) relative to `dirfd`, creating
+/// it with `mode` if `flags` say so.
+pub fn openat(dirfd: usize, path: *const u8, flags: usize, mode: usize) -> SysResult<usize> {
+    let r = syscall4_checked(SYS_OPENAT, dirfd, path as usize, flags, mode)?;
+    Ok(r as usize)
+}
+pub fn fsync(fd: usize) -> SysResult<()> {
+    let _ = syscall1_checked(SYS_FSYNC, fd)?;
+    Ok(())
+}
+/// `openat2(2)`: open `path` (NUL-terminated) relative to `dirfd` under
+/// the `resolve` restrictions.
+pub fn openat2(dirfd: usize, path: *const u8, flags: usize, resolve: u64) -> SysResult<usize> {
+    // struct open_how { u64 flags; u64 mode; u64 resolve; }
+    let how: [u64; 3] = [flags as u64, 0, resolve];
+    let r = syscall4_checked(SYS_OPENAT2, dirfd, path as usize, how.as_ptr() as usize, 24)?;
+    Ok(r as usize)
+}
+pub fn fstat(fd: usize) -> SysResult<Stat> {
+    // x86_64 layout: st_ino at 8, st_mode at 24, st_size at 48, st_mtime
+    // at 88 and st_mtime_nsec at 96.
+    let mut st = [0u8; 144];
+    let _ = syscall2_checked(SYS_FSTAT, fd, st.as_mut_ptr() as usize)?;
+    let word = |off: usize| {
+        let mut w = [0u8; 8];
+        w.copy_from_slice(&st[off..off + 8]);
+        u64::from_ne_bytes(w)
+    };
+    Ok(Stat {
+        ino: word(8),
+        mode: u32::from_ne_bytes([st[24], st[25], st[26], st[27]]),
+        size: word(48),
+        mtime: word(88) as i64,
+        mtime_nsec: word(96) as i64,
+    })
+}
+pub fn pread(fd: usize, buf: &mut [u8], offset: u64) -> SysResult<usize> {
+    let r = syscall4_checked(
+        SYS_PREAD64,
+        fd,
+        buf.as_mut_ptr() as usize,
+        buf.len(),
+        offset as usize,
+    )?;
+    Ok(r as usize)
+}
+/// Copy up to `count` bytes of `in_fd` from `*offset` to `out_fd` in the
+/// kernel, advancing `*offset`.
+pub fn sendfile(out_fd: usize, in_fd: usize, offset: &mut u64, count: usize) -> SysResult<usize> {
+    let r = syscall4_checked(
+        SYS_SENDFILE,
+        out_fd,
+        in_fd,
+        offset as *mut u64 as usize,
+        count,
+    )?;
+    Ok(r as usize)
+}
+pub fn access(path: *const u8, mode: usize) -> SysResult<()> {
+    let _ = syscall2_checked(SYS_ACCESS, path as usize, mode)?;
+    Ok(())
+}
+pub fn chdir(path: *const u8) -> SysResult<()> {
+    let _ = syscall1_checked(SYS_CHDIR, path as usize)?;
+    Ok(())
+}
+/// `st_mode` of `path`, not following a final symlink.
+pub fn lstat_mode(path: *const u8) -> SysResult<u32> {
+    // x86_64 `struct stat` is 144 bytes; `st_mode` sits at offset 24.
+    let mut st = [0u8; 144];
+    let _ = syscall2_checked(SYS_LSTAT, path as usize, st.as_mut_ptr() as usize)?;
+    Ok(u32::from_ne_bytes([st[24], st[25], st[26], st[27]]))
+}
+pub fn unlink(path: *const u8) -> SysResult<()> {
+    let _ = syscall1_checked(SYS_UNLINK, path as usize)?;
+    Ok(())
+}
+pub fn chmod(path: *const u8, mode: u32) -> SysResult<()> {
+    let _ = syscall2_checked(SYS_CHMOD, path as usize, mode as usize)?;
+    Ok(())
+}
+/// `u32::MAX` for `uid` or `gid` leaves it unchanged.
+pub fn chown(path: *const u8, uid: u32, gid: u32) -> SysResult<()> {
+    let _ = syscall3_checked(SYS_CHOWN, path as usize, uid as usize, gid as usize)?;
+    Ok(())
+}
+/// Clear `O_NONBLOCK` so plain blocking reads and writes work on `fd`.
+pub fn set_blocking(fd: usize) -> SysResult<()> {
+    let flags = syscall3_checked(SYS_FCNTL, fd, F_GETFL, 0)? as usize;
+    let _ = syscall3_checked(SYS_FCNTL, fd, F_SETFL, flags & !O_NONBLOCK)?;
+    Ok(())
+}
+/// Set `O_NONBLOCK` again, e.g. to park a kept-alive connection.
+pub fn set_nonblocking(fd: usize) -> SysResult<()> {
+    let flags = syscall3_checked(SYS_FCNTL, fd, F_GETFL, 0)? as usize;
+    let _ = syscall3_checked(SYS_FCNTL, fd, F_SETFL, flags | O_NONBLOCK)?;
+    Ok(())
+}
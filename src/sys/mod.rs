@@ -5,4 +5,6 @@ pub mod fs;
 pub mod mmap;
 pub mod net;
 pub mod pty;
+pub mod random;
 pub mod signal;
+pub mod timer;
@@ -10,12 +10,32 @@ const SYS_ACCEPT4: usize = 288;
 const SYS_SETSOCKOPT: usize = 54;
 const SYS_SENDTO: usize = 44;
 const SYS_RECVFROM: usize = 45;
+const SYS_SHUTDOWN: usize = 48;
+const SYS_CONNECT: usize = 42;
+const SYS_GETPEERNAME: usize = 52;
+const SYS_SOCKETPAIR: usize = 53;
+const SYS_SENDMSG: usize = 46;
+const SYS_RECVMSG: usize = 47;
 
+pub const AF_UNIX: usize = 1;
 pub const AF_INET: usize = 2;
+pub const AF_INET6: usize = 10;
 pub const SOCK_STREAM: usize = 1;
+pub const SOCK_SEQPACKET: usize = 5;
+pub const SOCK_NONBLOCK: usize = 2048;
 pub const SOCK_CLOEXEC: usize = 524288;
 pub const SOL_SOCKET: usize = 1;
 pub const SO_REUSEADDR: usize = 2;
+pub const IPPROTO_IPV6: usize = 41;
+pub const IPV6_V6ONLY: usize = 26;
+pub const SHUT_RDWR: usize = 2;
+/// Report EPIPE instead of raising SIGPIPE when the peer has gone away.
+const MSG_NOSIGNAL: usize = 0x4000;
+const MSG_DONTWAIT: usize = 0x40;
+/// Set close-on-exec on descriptors received with `SCM_RIGHTS`.
+const MSG_CMSG_CLOEXEC: usize = 0x4000_0000;
+const MSG_TRUNC: i32 = 0x20;
+const SCM_RIGHTS: i32 = 1;
 
 #[repr(C)]
 pub struct SockAddrIn {
@@ -25,14 +45,60 @@ pub struct SockAddrIn {
     pub sin_zero: [u8; 8],
 }
 
+#[repr(C)]
+pub struct SockAddrIn6 {
+    pub sin6_family: u16,
+    pub sin6_port: u16,
+    pub sin6_flowinfo: u32,
+    pub sin6_addr: [u8; 16],
+    pub sin6_scope_id: u32,
+}
+
+#[repr(C)]
+pub struct SockAddrUn {
+    pub sun_family: u16,
+    pub sun_path: [u8; 108],
+}
+
+#[repr(C)]
+struct IoVec {
+    base: usize,
+    len: usize,
+}
+
+#[repr(C)]
+struct MsgHdr {
+    name: usize,
+    namelen: u32,
+    iov: usize,
+    iovlen: usize,
+    control: usize,
+    controllen: usize,
+    flags: i32,
+}
+
+/// Control buffer for one `SCM_RIGHTS` descriptor: a `cmsghdr` (length,
+/// level, type) followed by the fd, padded to `CMSG_SPACE(4)`.
+#[repr(C, align(8))]
+struct FdControl([u8; 24]);
+
+const EADDRINUSE: isize = -98;
+const ECONNREFUSED: isize = -111;
+const ENOENT: isize = -2;
+
 pub fn socket(domain: usize, ty: usize, proto: usize) -> SysResult<usize> {
     let r = syscall3_checked(SYS_SOCKET, domain, ty, proto)?;
     Ok(r as usize)
 }
-pub fn bind(fd: usize, addr: *const SockAddrIn, len: usize) -> SysResult<()> {
+/// `addr` points at a `SockAddrIn`, `SockAddrIn6` or similar of `len` bytes.
+pub fn bind(fd: usize, addr: *const u8, len: usize) -> SysResult<()> {
     let _ = syscall3_checked(SYS_BIND, fd, addr as usize, len)?;
     Ok(())
 }
+pub fn connect(fd: usize, addr: *const u8, len: usize) -> SysResult<()> {
+    let _ = syscall3_checked(SYS_CONNECT, fd, addr as usize, len)?;
+    Ok(())
+}
 pub fn listen(fd: usize, backlog: usize) -> SysResult<()> {
     let _ = syscall2_checked(SYS_LISTEN, fd, backlog)?;
     Ok(())
@@ -41,16 +107,117 @@ pub fn setsockopt(fd: usize, lvl: usize, opt: usize, val: *const u8, len: usize)
     let _ = syscall6_checked(SYS_SETSOCKOPT, fd, lvl, opt, val as usize, len, 0)?;
     Ok(())
 }
-pub fn accept_blocking(fd: usize) -> SysResult<(usize, u32)> {
+/// Accept a connection as a non-blocking socket; `EAGAIN` when none is
+/// waiting.
+pub fn accept_nonblocking(fd: usize) -> SysResult<usize> {
     let r = syscall4_checked(
         SYS_ACCEPT4,
         fd,
         core::ptr::null_mut::<u8>() as usize,
         0,
-        SOCK_CLOEXEC,
+        SOCK_NONBLOCK | SOCK_CLOEXEC,
+    )?;
+    Ok(r as usize)
+}
+/// Raw `sockaddr` of the connected peer, truncated to `buf`.
+pub fn getpeername(fd: usize, buf: &mut [u8]) -> SysResult<()> {
+    let mut len = buf.len() as u32;
+    let _ = syscall3_checked(
+        SYS_GETPEERNAME,
+        fd,
+        buf.as_mut_ptr() as usize,
+        &mut len as *mut u32 as usize,
+    )?;
+    Ok(())
+}
+/// A connected pair of Unix sockets, both close-on-exec.
+pub fn socketpair(ty: usize) -> SysResult<(usize, usize)> {
+    let mut fds = [0i32; 2];
+    let _ = syscall4_checked(
+        SYS_SOCKETPAIR,
+        AF_UNIX,
+        ty | SOCK_CLOEXEC,
+        0,
+        fds.as_mut_ptr() as usize,
     )?;
-    Ok((r as usize, 0))
+    Ok((fds[0] as usize, fds[1] as usize))
+}
+
+/// Send `parts` as one message on a Unix socket, passing a duplicate of the
+/// descriptor `fd` along with it.
+pub fn send_fd(sock: usize, parts: &[&[u8]], fd: usize) -> SysResult<()> {
+    let mut iov = [const { IoVec { base: 0, len: 0 } }; 4];
+    if parts.len() > iov.len() {
+        return Err(-22);
+    }
+    for (v, part) in iov.iter_mut().zip(parts) {
+        *v = IoVec {
+            base: part.as_ptr() as usize,
+            len: part.len(),
+        };
+    }
+    let mut control = FdControl([0; 24]);
+    control.0[..8].copy_from_slice(&20usize.to_ne_bytes());
+    control.0[8..12].copy_from_slice(&(SOL_SOCKET as i32).to_ne_bytes());
+    control.0[12..16].copy_from_slice(&SCM_RIGHTS.to_ne_bytes());
+    control.0[16..20].copy_from_slice(&(fd as i32).to_ne_bytes());
+    let msg = MsgHdr {
+        name: 0,
+        namelen: 0,
+        iov: iov.as_ptr() as usize,
+        iovlen: parts.len(),
+        control: control.0.as_ptr() as usize,
+        controllen: control.0.len(),
+        flags: 0,
+    };
+    let _ = syscall3_checked(
+        SYS_SENDMSG,
+        sock,
+        &msg as *const MsgHdr as usize,
+        MSG_NOSIGNAL,
+    )?;
+    Ok(())
 }
+
+/// Receive one message from a Unix socket into `buf`, with the descriptor
+/// passed along with it, if any. `Ok((0, None))` once the peer has closed;
+/// a message longer than `buf` fails with `EMSGSIZE` (its descriptor is
+/// closed).
+pub fn recv_fd(sock: usize, buf: &mut [u8]) -> SysResult<(usize, Option<usize>)> {
+    let iov = IoVec {
+        base: buf.as_mut_ptr() as usize,
+        len: buf.len(),
+    };
+    let mut control = FdControl([0; 24]);
+    let mut msg = MsgHdr {
+        name: 0,
+        namelen: 0,
+        iov: &iov as *const IoVec as usize,
+        iovlen: 1,
+        control: control.0.as_mut_ptr() as usize,
+        controllen: control.0.len(),
+        flags: 0,
+    };
+    let n = syscall3_checked(
+        SYS_RECVMSG,
+        sock,
+        &mut msg as *mut MsgHdr as usize,
+        MSG_CMSG_CLOEXEC,
+    )? as usize;
+    let c = &control.0;
+    let fd = (msg.controllen >= 20
+        && i32::from_ne_bytes([c[8], c[9], c[10], c[11]]) == SOL_SOCKET as i32
+        && i32::from_ne_bytes([c[12], c[13], c[14], c[15]]) == SCM_RIGHTS)
+        .then(|| i32::from_ne_bytes([c[16], c[17], c[18], c[19]]) as usize);
+    if msg.flags & MSG_TRUNC != 0 {
+        if let Some(fd) = fd {
+            let _ = crate::sys::fs::close(fd);
+        }
+        return Err(-90);
+    }
+    Ok((n, fd))
+}
+
 pub fn send_all(fd: usize, buf: &[u8]) -> SysResult<()> {
     let mut off = 0;
     while off < buf.len() {
@@ -60,7 +227,7 @@ pub fn send_all(fd: usize, buf: &[u8]) -> SysResult<()> {
             fd,
             remaining.as_ptr() as usize,
             remaining.len(),
-            0,
+            MSG_NOSIGNAL,
             0,
             0,
         )?;
@@ -71,6 +238,20 @@ pub fn send_all(fd: usize, buf: &[u8]) -> SysResult<()> {
     }
     Ok(())
 }
+/// Send what fits in the socket buffer right now, even on a blocking
+/// socket; `EAGAIN` if nothing does.
+pub fn send_nonblocking(fd: usize, buf: &[u8]) -> SysResult<usize> {
+    let r = syscall6_checked(
+        SYS_SENDTO,
+        fd,
+        buf.as_ptr() as usize,
+        buf.len(),
+        MSG_NOSIGNAL | MSG_DONTWAIT,
+        0,
+        0,
+    )?;
+    Ok(r as usize)
+}
 pub fn recv(fd: usize, buf: &mut [u8]) -> SysResult<usize> {
     let r = syscall6_checked(
         SYS_RECVFROM,
@@ -84,27 +265,120 @@ pub fn recv(fd: usize, buf: &mut [u8]) -> SysResult<usize> {
     Ok(r as usize)
 }
 
-pub fn tcp_listen(port: u16) -> SysResult<usize> {
-    let fd = socket(AF_INET, SOCK_STREAM | SOCK_CLOEXEC, 0)?;
+pub fn shutdown(fd: usize, how: usize) -> SysResult<()> {
+    let _ = syscall2_checked(SYS_SHUTDOWN, fd, how)?;
+    Ok(())
+}
+
+pub fn tcp_listen(ip: [u8; 4], port: u16) -> SysResult<usize> {
+    let addr = SockAddrIn {
+        sin_family: AF_INET as u16,
+        sin_port: port.to_be(),
+        sin_addr: u32::from_ne_bytes(ip),
+        sin_zero: [0; 8],
+    };
+    let fd = socket(AF_INET, SOCK_STREAM | SOCK_NONBLOCK | SOCK_CLOEXEC, 0)?;
+    listen_on(
+        fd,
+        &addr as *const _ as *const u8,
+        core::mem::size_of::<SockAddrIn>(),
+    )
+}
+
+/// Listen on an IPv6 address. With `v6only` unset, binding the unspecified
+/// address `::` also accepts IPv4 clients (as `::ffff:a.b.c.d`).
+pub fn tcp6_listen(ip: [u8; 16], port: u16, v6only: bool) -> SysResult<usize> {
+    let addr = SockAddrIn6 {
+        sin6_family: AF_INET6 as u16,
+        sin6_port: port.to_be(),
+        sin6_flowinfo: 0,
+        sin6_addr: ip,
+        sin6_scope_id: 0,
+    };
+    let fd = socket(AF_INET6, SOCK_STREAM | SOCK_NONBLOCK | SOCK_CLOEXEC, 0)?;
+    let flag: i32 = v6only as i32;
+    if let Err(e) = setsockopt(
+        fd,
+        IPPROTO_IPV6,
+        IPV6_V6ONLY,
+        &flag as *const _ as *const u8,
+        core::mem::size_of::<i32>(),
+    ) {
+        let _ = crate::sys::fs::close(fd);
+        return Err(e);
+    }
+    listen_on(
+        fd,
+        &addr as *const _ as *const u8,
+        core::mem::size_of::<SockAddrIn6>(),
+    )
+}
+
+/// Listen on a Unix domain socket at `path`; a leading `@` selects the
+/// abstract namespace. A socket file left behind by a dead server is
+/// replaced, while one a live server still accepts on fails with
+/// `EADDRINUSE`, as does any other kind of file.
+pub fn unix_listen(path: &[u8]) -> SysResult<usize> {
+    let mut addr = SockAddrUn {
+        sun_family: AF_UNIX as u16,
+        sun_path: [0; 108],
+    };
+    if path.is_empty() || path.len() >= addr.sun_path.len() {
+        return Err(-22);
+    }
+    addr.sun_path[..path.len()].copy_from_slice(path);
+    let abstract_ns = path[0] == b'@';
+    if abstract_ns {
+        addr.sun_path[0] = 0;
+    } else {
+        remove_stale_socket(&addr)?;
+    }
+    // Abstract names are not NUL-terminated: the length delimits them.
+    let len = 2 + path.len() + !abstract_ns as usize;
+    let fd = socket(AF_UNIX, SOCK_STREAM | SOCK_NONBLOCK | SOCK_CLOEXEC, 0)?;
+    listen_on(fd, &addr as *const _ as *const u8, len)
+}
+
+fn remove_stale_socket(addr: &SockAddrUn) -> SysResult<()> {
+    let path = addr.sun_path.as_ptr();
+    match crate::sys::fs::lstat_mode(path) {
+        Err(ENOENT) => return Ok(()),
+        Err(e) => return Err(e),
+        Ok(mode) if mode & crate::sys::fs::S_IFMT != crate::sys::fs::S_IFSOCK => {
+            return Err(EADDRINUSE);
+        }
+        Ok(_) => {}
+    }
+    let probe = socket(AF_UNIX, SOCK_STREAM | SOCK_CLOEXEC, 0)?;
+    let res = connect(
+        probe,
+        addr as *const _ as *const u8,
+        core::mem::size_of::<SockAddrUn>(),
+    );
+    let _ = crate::sys::fs::close(probe);
+    match res {
+        Ok(()) => Err(EADDRINUSE),
+        Err(ECONNREFUSED) => crate::sys::fs::unlink(path),
+        Err(e) => Err(e),
+    }
+}
+
+/// Bind `fd` to `addr` and start listening; closes `fd` on failure. Listening
+/// sockets are non-blocking so the accept loop can drain them until `EAGAIN`.
+fn listen_on(fd: usize, addr: *const u8, len: usize) -> SysResult<usize> {
     let one: i32 = 1;
-    setsockopt(
+    let res = setsockopt(
         fd,
         SOL_SOCKET,
         SO_REUSEADDR,
         &one as *const _ as *const u8,
         core::mem::size_of::<i32>(),
-    )?;
-    let addr = SockAddrIn {
-        sin_family: AF_INET as u16,
-        sin_port: port.to_be(),
-        sin_addr: u32::from_be_bytes([0, 0, 0, 0]),
-        sin_zero: [0; 8],
-    };
-    bind(fd, &addr as *const _, core::mem::size_of::<SockAddrIn>())?;
-    listen(fd, 128)?;
-    const SYS_FCNTL: usize = 72;
-    const F_SETFD: usize = 2;
-    const FD_CLOEXEC: usize = 1;
-    let _ = syscall3_checked(SYS_FCNTL, fd, F_SETFD, FD_CLOEXEC)?;
+    )
+    .and_then(|()| bind(fd, addr, len))
+    .and_then(|()| listen(fd, 128));
+    if let Err(e) = res {
+        let _ = crate::sys::fs::close(fd);
+        return Err(e);
+    }
     Ok(fd)
 }
@@ -17,6 +17,29 @@ const TIOCGPTN: usize = 0x80045430;
 const TIOCSPTLCK: usize = 0x40045431;
 const TIOCSCTTY: usize = 0x540E;
 const TIOCSPGRP: usize = 0x5410;
+const TIOCGWINSZ: usize = 0x5413;
+const TIOCSWINSZ: usize = 0x5414;
+
+/// Terminal geometry as exchanged with the kernel via `TIOC[GS]WINSZ`.
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct WinSize {
+    pub ws_row: u16,
+    pub ws_col: u16,
+    pub ws_xpixel: u16,
+    pub ws_ypixel: u16,
+}
+
+impl WinSize {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        Self {
+            ws_row: rows,
+            ws_col: cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        }
+    }
+}
 
 pub fn open_ptmx() -> SysResult<usize> {
     open(b"/dev/ptmx\0".as_ptr(), 0o0002 /*O_RDWR*/, 0)
@@ -92,6 +115,19 @@ pub fn tcsetpgrp(fd: usize, pgrp: i32) -> SysResult<()> {
     Ok(())
 }
 
+/// Set the window size of the terminal behind `fd`. When the size actually
+/// changes the kernel delivers SIGWINCH to the foreground process group.
+pub fn set_winsize(fd: usize, ws: &WinSize) -> SysResult<()> {
+    let _ = syscall3_checked(SYS_IOCTL, fd, TIOCSWINSZ, ws as *const _ as usize)?;
+    Ok(())
+}
+
+pub fn get_winsize(fd: usize) -> SysResult<WinSize> {
+    let mut ws = WinSize::default();
+    let _ = syscall3_checked(SYS_IOCTL, fd, TIOCGWINSZ, &mut ws as *mut _ as usize)?;
+    Ok(ws)
+}
+
 pub fn fork() -> SysResult<i32> {
     let r = syscall0_checked(SYS_FORK)?;
     Ok(r as i32)
@@ -108,7 +144,6 @@ pub fn execve(path: *const u8, argv: *const *const u8, envp: *const *const u8) -
         core::hint::spin_loop();
     }
 }
-#[allow(dead_code)]
 pub fn exit(code: i32) -> ! {
     let _ = crate::runtime::syscall::syscall1_checked(SYS_EXIT, code as usize);
     loop {
@@ -140,19 +175,41 @@ pub fn kill(pid: i32, sig: i32) -> SysResult<()> {
     Ok(())
 }
 
-pub fn waitpid(pid: i32) -> SysResult<i32> {
+/// How a child terminated, decoded from the `wait4` status word.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum WaitStatus {
+    Exited(u8),
+    Signaled { signal: u8, core_dumped: bool },
+}
+
+impl WaitStatus {
+    pub fn from_raw(status: i32) -> Self {
+        let signal = (status & 0x7F) as u8;
+        if signal == 0 {
+            WaitStatus::Exited(((status >> 8) & 0xFF) as u8)
+        } else {
+            WaitStatus::Signaled {
+                signal,
+                core_dumped: (status & 0x80) != 0,
+            }
+        }
+    }
+}
+
+pub fn waitpid(pid: i32) -> SysResult<WaitStatus> {
     let mut status: i32 = 0;
-    let r = crate::runtime::syscall::syscall4_checked(
+    let _ = crate::runtime::syscall::syscall4_checked(
         SYS_WAIT4,
         pid as usize,
         &mut status as *mut _ as usize,
         0,
         0,
     )?;
-    Ok(r as i32)
+    Ok(WaitStatus::from_raw(status))
 }
 
-pub fn waitpid_nohang(pid: i32) -> SysResult<i32> {
+/// `Ok(None)` while the child is still running.
+pub fn waitpid_nohang(pid: i32) -> SysResult<Option<WaitStatus>> {
     let mut status: i32 = 0;
     const WNOHANG: usize = 1;
     let r = crate::runtime::syscall::syscall4_checked(
@@ -162,7 +219,10 @@ pub fn waitpid_nohang(pid: i32) -> SysResult<i32> {
         WNOHANG,
         0,
     )?;
-    Ok(r as i32)
+    if r == 0 {
+        return Ok(None);
+    }
+    Ok(Some(WaitStatus::from_raw(status)))
 }
 
 pub fn wait_any_nohang() -> SysResult<i32> {
@@ -0,0 +1,15 @@
+use crate::runtime::syscall::syscall3_checked;
+use crate::sys::SysResult;
+
+const SYS_GETRANDOM: usize = 318;
+
+/// Fill `buf` from the kernel CSPRNG.
+pub fn getrandom(buf: &mut [u8]) -> SysResult<()> {
+    let mut off = 0;
+    while off < buf.len() {
+        let rest = &mut buf[off..];
+        let r = syscall3_checked(SYS_GETRANDOM, rest.as_mut_ptr() as usize, rest.len(), 0)?;
+        off += r as usize;
+    }
+    Ok(())
+}
@@ -1,14 +1,100 @@
 use crate::runtime::syscall::syscall4_checked;
 use crate::sys::SysResult;
 
+const SYS_RT_SIGACTION: usize = 13;
 const SYS_RT_SIGPROCMASK: usize = 14;
 const SYS_SIGNALFD4: usize = 289;
 
+const SIG_SETMASK: usize = 2;
+const SIG_DFL: usize = 0;
+const NSIG: usize = 64;
+
+/// Kernel `struct sigaction` layout for x86_64.
+#[repr(C)]
+struct SigAction {
+    handler: usize,
+    flags: u64,
+    restorer: usize,
+    mask: u64,
+}
+
+const SIGNAL_NAMES: [&str; 31] = [
+    "SIGHUP",
+    "SIGINT",
+    "SIGQUIT",
+    "SIGILL",
+    "SIGTRAP",
+    "SIGABRT",
+    "SIGBUS",
+    "SIGFPE",
+    "SIGKILL",
+    "SIGUSR1",
+    "SIGSEGV",
+    "SIGUSR2",
+    "SIGPIPE",
+    "SIGALRM",
+    "SIGTERM",
+    "SIGSTKFLT",
+    "SIGCHLD",
+    "SIGCONT",
+    "SIGSTOP",
+    "SIGTSTP",
+    "SIGTTIN",
+    "SIGTTOU",
+    "SIGURG",
+    "SIGXCPU",
+    "SIGXFSZ",
+    "SIGVTALRM",
+    "SIGPROF",
+    "SIGWINCH",
+    "SIGIO",
+    "SIGPWR",
+    "SIGSYS",
+];
+
+/// Conventional name of a standard signal, e.g. `"SIGKILL"` for 9.
+pub fn signal_name(sig: u8) -> Option<&'static str> {
+    SIGNAL_NAMES.get((sig as usize).checked_sub(1)?).copied()
+}
+
 pub fn block_signals(mask_ptr: *const u64, sigsetsize: usize) -> SysResult<()> {
     let _ = syscall4_checked(SYS_RT_SIGPROCMASK, 0, mask_ptr as usize, 0, sigsetsize)?;
     Ok(())
 }
 
+/// Replace the blocked signal mask with an empty one.
+pub fn unblock_all_signals() -> SysResult<()> {
+    let empty: u64 = 0;
+    let _ = syscall4_checked(
+        SYS_RT_SIGPROCMASK,
+        SIG_SETMASK,
+        &empty as *const u64 as usize,
+        0,
+        core::mem::size_of::<u64>(),
+    )?;
+    Ok(())
+}
+
+/// Restore the default disposition of every signal. SIGKILL and SIGSTOP
+/// (and the reserved real-time signals) reject this, which is harmless.
+pub fn reset_signal_dispositions() {
+    let sa = SigAction {
+        handler: SIG_DFL,
+        flags: 0,
+        restorer: 0,
+        mask: 0,
+    };
+    for sig in 1..=NSIG {
+        let _ = syscall4_checked(
+            SYS_RT_SIGACTION,
+            sig,
+            &sa as *const SigAction as usize,
+            0,
+            core::mem::size_of::<u64>(),
+        );
+    }
+}
+
 pub fn signalfd(mask_ptr: *const u64, sigsetsize: usize, flags: usize) -> SysResult<usize> {
     let fd_arg = !0usize;
     let r = syscall4_checked(SYS_SIGNALFD4, fd_arg, mask_ptr as usize, sigsetsize, flags)?;
@@ -0,0 +1,106 @@
+use crate::runtime::syscall::{syscall2_checked, syscall4_checked};
+use crate::sys::SysResult;
+
+const SYS_NANOSLEEP: usize = 35;
+const SYS_CLOCK_GETTIME: usize = 228;
+const SYS_TIMERFD_CREATE: usize = 283;
+const SYS_TIMERFD_SETTIME: usize = 286;
+
+const CLOCK_REALTIME: usize = 0;
+const CLOCK_MONOTONIC: usize = 1;
+const TFD_CLOEXEC: usize = 0o2000000;
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+struct TimeSpec {
+    tv_sec: i64,
+    tv_nsec: i64,
+}
+
+#[repr(C)]
+struct ITimerSpec {
+    it_interval: TimeSpec,
+    it_value: TimeSpec,
+}
+
+/// Milliseconds on the monotonic clock (arbitrary epoch).
+pub fn monotonic_ms() -> u64 {
+    let mut ts = TimeSpec::default();
+    let _ = syscall2_checked(
+        SYS_CLOCK_GETTIME,
+        CLOCK_MONOTONIC,
+        &mut ts as *mut TimeSpec as usize,
+    );
+    ts.tv_sec as u64 * 1000 + ts.tv_nsec as u64 / 1_000_000
+}
+
+/// Seconds since the Unix epoch.
+pub fn unix_time() -> u64 {
+    let mut ts = TimeSpec::default();
+    let _ = syscall2_checked(
+        SYS_CLOCK_GETTIME,
+        CLOCK_REALTIME,
+        &mut ts as *mut TimeSpec as usize,
+    );
+    ts.tv_sec as u64
+}
+
+pub fn sleep_ms(ms: u64) {
+    let ts = TimeSpec {
+        tv_sec: (ms / 1000) as i64,
+        tv_nsec: ((ms % 1000) * 1_000_000) as i64,
+    };
+    let _ = syscall2_checked(SYS_NANOSLEEP, &ts as *const TimeSpec as usize, 0);
+}
+
+pub fn timerfd_create() -> SysResult<usize> {
+    let r = syscall2_checked(SYS_TIMERFD_CREATE, CLOCK_MONOTONIC, TFD_CLOEXEC)?;
+    Ok(r as usize)
+}
+
+/// Arm `fd` to fire every `interval_ms` milliseconds, starting one interval
+/// from now. Zero disarms it.
+pub fn timerfd_set_interval(fd: usize, interval_ms: u64) -> SysResult<()> {
+    let ts = TimeSpec {
+        tv_sec: (interval_ms / 1000) as i64,
+        tv_nsec: ((interval_ms % 1000) * 1_000_000) as i64,
+    };
+    let spec = ITimerSpec {
+        it_interval: ts,
+        it_value: ts,
+    };
+    let _ = syscall4_checked(
+        SYS_TIMERFD_SETTIME,
+        fd,
+        0,
+        &spec as *const ITimerSpec as usize,
+        0,
+    )?;
+    Ok(())
+}
+
+/// Arm `fd` to fire once, `ms` milliseconds from now. Zero disarms it.
+pub fn timerfd_set_once(fd: usize, ms: u64) -> SysResult<()> {
+    let spec = ITimerSpec {
+        it_interval: TimeSpec::default(),
+        it_value: TimeSpec {
+            tv_sec: (ms / 1000) as i64,
+            tv_nsec: ((ms % 1000) * 1_000_000) as i64,
+        },
+    };
+    let _ = syscall4_checked(
+        SYS_TIMERFD_SETTIME,
+        fd,
+        0,
+        &spec as *const ITimerSpec as usize,
+        0,
+    )?;
+    Ok(())
+}
+
+/// Consume pending expirations, returning how many have elapsed.
+pub fn timerfd_read(fd: usize) -> SysResult<u64> {
+    let mut buf = [0u8; 8];
+    let _ = crate::sys::fs::read(fd, &mut buf)?;
+    Ok(u64::from_ne_bytes(buf))
+}